@@ -6,3 +6,20 @@ pub mod v0_37;
 
 #[cfg(feature = "v0_38")]
 pub mod v0_38;
+
+/// Drops transactions from the end of `txs` until their total size is at most `max_tx_bytes`.
+#[cfg(any(feature = "v0_37", feature = "v0_38"))]
+pub(crate) fn truncate_txs(txs: &mut Vec<prost::bytes::Bytes>, max_tx_bytes: i64) {
+    let max_tx_bytes: usize = max_tx_bytes.try_into().unwrap_or(0);
+    let mut total_tx_bytes: usize = txs
+        .iter()
+        .map(|tx| tx.len())
+        .fold(0, |acc, len| acc.saturating_add(len));
+    while total_tx_bytes > max_tx_bytes {
+        if let Some(tx) = txs.pop() {
+            total_tx_bytes = total_tx_bytes.saturating_sub(tx.len());
+        } else {
+            break;
+        }
+    }
+}
@@ -1,22 +1,16 @@
 //! Contains methods specifically implemented for use with the Tower ABCI
 //! interface, compatible with CometBFT version 0.37
 
-use std::fmt::{Debug, Write};
+use std::fmt::Debug;
 
-use basecoin_modules::auth::ACCOUNT_PREFIX;
 use basecoin_modules::error::Error;
 use basecoin_modules::types::IdentifiedModule;
 use basecoin_store::context::{ProvableStore, Store};
 use basecoin_store::types::{Height, Path};
 use basecoin_store::utils::SharedRwExt;
-use cosmrs::tx::{SignerInfo, SignerPublicKey};
-use cosmrs::Tx;
-use ibc_proto::google::protobuf::Any;
-use prost::Message;
 use serde_json::Value;
-use tendermint::merkle::proof::{ProofOp, ProofOps};
 use tendermint_proto::v0_37::abci::{
-    response_process_proposal, Event as ProtoEvent, RequestApplySnapshotChunk, RequestBeginBlock,
+    response_process_proposal, CheckTxType, RequestApplySnapshotChunk, RequestBeginBlock,
     RequestCheckTx, RequestDeliverTx, RequestEcho, RequestEndBlock, RequestInfo, RequestInitChain,
     RequestLoadSnapshotChunk, RequestOfferSnapshot, RequestPrepareProposal, RequestProcessProposal,
     RequestQuery, ResponseApplySnapshotChunk, ResponseBeginBlock, ResponseCheckTx, ResponseCommit,
@@ -26,8 +20,9 @@ use tendermint_proto::v0_37::abci::{
 };
 use tracing::{debug, info};
 
+use crate::abci::truncate_txs;
 use crate::error::ResponseFromErrorExt;
-use crate::BaseCoinApp;
+use crate::{BaseCoinApp, COMMIT_INFO_QUERY_PATH};
 
 pub fn echo<S: Default + ProvableStore>(
     _app: &BaseCoinApp<S>,
@@ -74,6 +69,8 @@ pub fn init_chain<S: Default + ProvableStore>(
         &String::from_utf8(request.app_state_bytes.clone().into()).expect("invalid genesis state"),
     )
     .expect("genesis state isn't valid JSON");
+    app.set_chain_id(request.chain_id.parse().expect("invalid chain identifier"));
+
     let mut modules = app.modules.write_access();
     for IdentifiedModule { module, .. } in modules.iter_mut() {
         module.init(app_state.clone());
@@ -94,32 +91,29 @@ pub fn query<S: Default + ProvableStore>(
 ) -> ResponseQuery {
     debug!("Got query request: {:?}", request);
 
+    let height = Height::from(request.height as u64);
+    if request.path == COMMIT_INFO_QUERY_PATH {
+        return match app.commit_info(height) {
+            Some(commit_info) => ResponseQuery {
+                code: 0,
+                log: "exists".to_string(),
+                value: commit_info.to_json().to_string().into_bytes().into(),
+                height: commit_info.version as i64,
+                ..Default::default()
+            },
+            None => ResponseQuery::from_error(1, "commit info not found"),
+        };
+    }
+
     let path: Option<Path> = Some(request.path.into());
     let modules = app.modules.read_access();
-    let height = Height::from(request.height as u64);
     for IdentifiedModule { id, module } in modules.iter() {
         match module.query(&request.data, path.as_ref(), height, request.prove) {
             // success - implies query was handled by this module, so return response
             Ok(result) => {
-                let store = app.store.read_access();
-                let proof_ops = if request.prove {
-                    let proof = store.get_proof(height, &id.clone().into()).unwrap();
-                    let mut buffer = Vec::new();
-                    proof.encode(&mut buffer).unwrap(); // safety - cannot fail since buf is a vector
-
-                    let mut ops = vec![];
-                    if let Some(mut proofs) = result.proof {
-                        ops.append(&mut proofs);
-                    }
-                    ops.push(ProofOp {
-                        field_type: "".to_string(),
-                        key: id.to_string().into_bytes(),
-                        data: buffer,
-                    });
-                    Some(ProofOps { ops })
-                } else {
-                    None
-                };
+                let proof_ops = request
+                    .prove
+                    .then(|| app.proof_ops(id, height, result.proof));
 
                 return ResponseQuery {
                     code: 0,
@@ -127,7 +121,7 @@ pub fn query<S: Default + ProvableStore>(
                     key: request.data,
                     value: result.data.into(),
                     proof_ops: proof_ops.map(|proof_ops| proof_ops.into()),
-                    height: store.current_height() as i64,
+                    height: app.store.read_access().current_height() as i64,
                     ..Default::default()
                 };
             }
@@ -142,10 +136,22 @@ pub fn query<S: Default + ProvableStore>(
 }
 
 pub fn check_tx<S: Default + ProvableStore>(
-    _app: &BaseCoinApp<S>,
-    _request: RequestCheckTx,
+    app: &BaseCoinApp<S>,
+    request: RequestCheckTx,
 ) -> ResponseCheckTx {
-    Default::default()
+    debug!("Got checkTx request: {request:?}");
+
+    let recheck = request.r#type == CheckTxType::Recheck as i32;
+    match app.check_tx(&request.tx, recheck) {
+        Ok(checked) => ResponseCheckTx {
+            log: "success".to_owned(),
+            gas_wanted: checked.gas_wanted.try_into().unwrap_or(i64::MAX),
+            priority: checked.priority,
+            sender: checked.sender,
+            ..ResponseCheckTx::default()
+        },
+        Err(e) => ResponseCheckTx::from_tx_error(e),
+    }
 }
 
 pub fn deliver_tx<S: Default + Debug + ProvableStore>(
@@ -154,122 +160,24 @@ pub fn deliver_tx<S: Default + Debug + ProvableStore>(
 ) -> ResponseDeliverTx {
     debug!("Got deliverTx request: {request:?}");
 
-    let tx: Tx = match request.tx.as_ref().try_into() {
-        Ok(tx) => tx,
-        Err(err) => {
-            return ResponseDeliverTx::from_error(
-                1,
-                format!("failed to decode incoming tx bytes: {err}"),
-            );
-        }
-    };
-
-    // Extract `AccountId` of first signer
-    let signer = {
-        let pubkey = match tx.auth_info.signer_infos.first() {
-            Some(&SignerInfo {
-                public_key: Some(SignerPublicKey::Single(pubkey)),
-                ..
-            }) => pubkey,
-            _ => return ResponseDeliverTx::from_error(2, "Empty signers"),
-        };
-        if let Ok(signer) = pubkey.account_id(ACCOUNT_PREFIX) {
-            signer
-        } else {
-            return ResponseDeliverTx::from_error(2, "Invalid signer");
-        }
-    };
-
-    if tx.body.messages.is_empty() {
-        return ResponseDeliverTx::from_error(2, "Empty Tx");
-    }
-
-    let mut events = vec![];
-    for message in tx.body.messages {
-        let message = Any {
-            type_url: message.type_url,
-            value: message.value,
-        };
-
-        // try to deliver message to every module
-        match app.deliver_msg(message, &signer) {
-            // success - append events and continue with next message
-            Ok(msg_events) => {
-                let mut proto_events: Vec<ProtoEvent> =
-                    msg_events.into_iter().map(|event| event.into()).collect();
-
-                events.append(&mut proto_events);
-            }
-            // return on first error -
-            // either an error that occurred during execution of this message OR no module
-            // could handle this message
-            Err(e) => {
-                // reset changes from other messages in this tx
-                let mut modules = app.modules.write_access();
-                for IdentifiedModule { module, .. } in modules.iter_mut() {
-                    module.store_mut().reset();
-                }
-
-                // probably the main store doesn't need to be reset.
-                // currently the only time it is written while committing a block.
-                // but doing it nonetheless, just in case.
-                app.store.write_access().reset();
-                return ResponseDeliverTx::from_error(2, format!("deliver failed with error: {e}"));
-            }
-        }
-    }
-
-    // persists changes from all the messages in this tx
-    let mut modules = app.modules.write_access();
-    for IdentifiedModule { module, .. } in modules.iter_mut() {
-        module
-            .store_mut()
-            .apply()
-            .expect("failed to apply to module state");
-    }
-
-    // probably the main store doesn't need to be applied.
-    // currently the only time it is written while committing a block.
-    // but doing it nonetheless, just in case.
-    app.store
-        .write_access()
-        .apply()
-        .expect("failed to commit to state");
-
-    ResponseDeliverTx {
-        log: "success".to_owned(),
-        events,
-        ..ResponseDeliverTx::default()
+    match app.deliver_tx(&request.tx) {
+        Ok(events) => ResponseDeliverTx {
+            log: "success".to_owned(),
+            events: events.into_iter().map(Into::into).collect(),
+            ..ResponseDeliverTx::default()
+        },
+        Err(e) => ResponseDeliverTx::from_tx_error(e),
     }
 }
 
 pub fn commit<S: Default + ProvableStore>(app: &BaseCoinApp<S>) -> ResponseCommit {
-    let mut modules = app.modules.write_access();
-    for IdentifiedModule { id, module } in modules.iter_mut() {
-        module
-            .store_mut()
-            .commit()
-            .expect("failed to commit to module state");
-        let mut state = app.store.write_access();
-        state
-            .set(id.clone().into(), module.store().root_hash())
-            .expect("failed to update sub-store commitment");
-    }
+    let data = app.commit_stores();
+    let retain_height = app.prune_stores();
+    app.reset_check_state();
 
-    let mut state = app.store.write_access();
-    let data = state.commit().expect("failed to commit to state");
-    info!(
-        "Committed height {} with hash({})",
-        state.current_height() - 1,
-        data.iter().fold(String::new(), |mut acc, b| {
-            // write!-ing into a String can never fail
-            let _ = write!(acc, "{b:02X}");
-            acc
-        })
-    );
     ResponseCommit {
         data: data.into(),
-        retain_height: 0,
+        retain_height: retain_height as i64,
     }
 }
 
@@ -279,26 +187,24 @@ pub fn begin_block<S: Default + ProvableStore>(
 ) -> ResponseBeginBlock {
     debug!("Got begin block request.");
 
-    let mut modules = app.modules.write_access();
-    let mut events = vec![];
     let header = request.header.unwrap().try_into().unwrap();
-    for IdentifiedModule { module, .. } in modules.iter_mut() {
-        let tm_event = module.begin_block(&header);
-
-        let proto_events: Vec<ProtoEvent> =
-            tm_event.into_iter().map(|event| event.into()).collect();
-
-        events.extend(proto_events);
-    }
+    let events = app
+        .begin_block(&header)
+        .into_iter()
+        .map(Into::into)
+        .collect();
 
     ResponseBeginBlock { events }
 }
 
 pub fn end_block<S: Default + ProvableStore>(
-    _app: &BaseCoinApp<S>,
-    _request: RequestEndBlock,
+    app: &BaseCoinApp<S>,
+    request: RequestEndBlock,
 ) -> ResponseEndBlock {
-    Default::default()
+    debug!("Got end block request.");
+
+    let height = request.height.try_into().expect("invalid block height");
+    app.end_block(height).into()
 }
 
 pub fn list_snapshots<S: Default + ProvableStore>(_app: &BaseCoinApp<S>) -> ResponseListSnapshots {
@@ -335,18 +241,7 @@ pub fn prepare_proposal<S: Default + ProvableStore>(
         max_tx_bytes,
         ..
     } = request;
-    let max_tx_bytes: usize = max_tx_bytes.try_into().unwrap_or(0);
-    let mut total_tx_bytes: usize = txs
-        .iter()
-        .map(|tx| tx.len())
-        .fold(0, |acc, len| acc.saturating_add(len));
-    while total_tx_bytes > max_tx_bytes {
-        if let Some(tx) = txs.pop() {
-            total_tx_bytes = total_tx_bytes.saturating_sub(tx.len());
-        } else {
-            break;
-        }
-    }
+    truncate_txs(&mut txs, max_tx_bytes);
     ResponsePrepareProposal { txs }
 }
 
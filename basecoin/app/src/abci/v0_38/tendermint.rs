@@ -1,24 +1,33 @@
-use std::fmt::{Debug, Write};
+use std::fmt::Debug;
 
 use basecoin_modules::error::Error;
 use basecoin_modules::types::IdentifiedModule;
 use basecoin_store::context::{ProvableStore, Store};
 use basecoin_store::types::{Height, Path};
 use basecoin_store::utils::SharedRwExt;
-use prost::Message;
 use serde_json::Value;
-use tendermint::merkle::proof::{ProofOp, ProofOps};
+use tendermint::abci::request::{ExtendVote, VerifyVoteExtension};
+use tendermint::abci::types::ExtendedCommitInfo;
+use tendermint::block::header::Version;
+use tendermint::block::Header;
+use tendermint::hash::Hash;
 use tendermint_abci::Application;
+use tendermint_proto::abci::response_verify_vote_extension::VerifyStatus;
 use tendermint_proto::abci::{
-    RequestFinalizeBlock, RequestInfo, RequestInitChain, RequestQuery, ResponseCommit,
-    ResponseFinalizeBlock, ResponseInfo, ResponseInitChain, ResponseQuery,
+    CheckTxType, ExecTxResult, RequestCheckTx, RequestExtendVote, RequestFinalizeBlock,
+    RequestInfo, RequestInitChain, RequestPrepareProposal, RequestQuery,
+    RequestVerifyVoteExtension, ResponseCheckTx, ResponseCommit, ResponseExtendVote,
+    ResponseFinalizeBlock, ResponseInfo, ResponseInitChain, ResponsePrepareProposal, ResponseQuery,
+    ResponseVerifyVoteExtension,
 };
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
+use crate::abci::truncate_txs;
 use crate::builder::BaseCoinApp;
+use crate::commit_info::COMMIT_INFO_QUERY_PATH;
 use crate::error::ResponseFromErrorExt;
 
-impl<S: Debug + ProvableStore> Application for BaseCoinApp<S> {
+impl<S: Default + Debug + ProvableStore> Application for BaseCoinApp<S> {
     fn info(&self, request: RequestInfo) -> ResponseInfo {
         let (last_block_height, last_block_app_hash) = {
             let state = self.store.read_access();
@@ -46,6 +55,8 @@ impl<S: Debug + ProvableStore> Application for BaseCoinApp<S> {
         )
         .expect("genesis state isn't valid JSON");
 
+        self.set_chain_id(request.chain_id.parse().expect("invalid chain identifier"));
+
         let mut modules = self.modules.write_access();
 
         for IdentifiedModule { module, .. } in modules.iter_mut() {
@@ -64,35 +75,31 @@ impl<S: Debug + ProvableStore> Application for BaseCoinApp<S> {
     fn query(&self, request: RequestQuery) -> ResponseQuery {
         debug!("Got query request: {:?}", request);
 
+        let height = Height::from(request.height as u64);
+        if request.path == COMMIT_INFO_QUERY_PATH {
+            return match self.commit_info(height) {
+                Some(commit_info) => ResponseQuery {
+                    code: 0,
+                    log: "exists".to_string(),
+                    value: commit_info.to_json().to_string().into_bytes().into(),
+                    height: commit_info.version as i64,
+                    ..Default::default()
+                },
+                None => ResponseQuery::from_error(1, "commit info not found"),
+            };
+        }
+
         let path = Path::from(request.path);
 
         let modules = self.modules.read_access();
 
-        let height = Height::from(request.height as u64);
-
         for IdentifiedModule { id, module } in modules.iter() {
             match module.query(&request.data, Some(&path), height, request.prove) {
                 // success - implies query was handled by this module, so return response
                 Ok(result) => {
-                    let store = self.store.read_access();
-                    let proof_ops = if request.prove {
-                        let proof = store.get_proof(height, &id.clone().into()).unwrap();
-                        let mut buffer = Vec::new();
-                        proof.encode(&mut buffer).unwrap(); // safety - cannot fail since buf is a vector
-
-                        let mut ops = vec![];
-                        if let Some(mut proofs) = result.proof {
-                            ops.append(&mut proofs);
-                        }
-                        ops.push(ProofOp {
-                            field_type: "".to_string(),
-                            key: id.to_string().into_bytes(),
-                            data: buffer,
-                        });
-                        Some(ProofOps { ops })
-                    } else {
-                        None
-                    };
+                    let proof_ops = request
+                        .prove
+                        .then(|| self.proof_ops(id, height, result.proof));
 
                     return ResponseQuery {
                         code: 0,
@@ -100,7 +107,7 @@ impl<S: Debug + ProvableStore> Application for BaseCoinApp<S> {
                         key: request.data,
                         value: result.data.into(),
                         proof_ops: proof_ops.map(Into::into),
-                        height: store.current_height() as i64,
+                        height: self.store.read_access().current_height() as i64,
                         ..Default::default()
                     };
                 }
@@ -114,36 +121,811 @@ impl<S: Debug + ProvableStore> Application for BaseCoinApp<S> {
         ResponseQuery::from_error(1, "query msg not handled")
     }
 
+    /// Commits and prunes the stores, whose app hash was already returned by `FinalizeBlock`, and
+    /// resets the check state.
     fn commit(&self) -> ResponseCommit {
-        let mut modules = self.modules.write_access();
+        self.commit_stores();
+        let retain_height = self.prune_stores();
+        self.reset_check_state();
+
+        ResponseCommit {
+            retain_height: retain_height as i64,
+        }
+    }
+
+    fn check_tx(&self, request: RequestCheckTx) -> ResponseCheckTx {
+        debug!("Got checkTx request: {request:?}");
+
+        let recheck = request.r#type == CheckTxType::Recheck as i32;
+        match self.check_tx(&request.tx, recheck) {
+            Ok(checked) => ResponseCheckTx {
+                log: "success".to_owned(),
+                gas_wanted: checked.gas_wanted.try_into().unwrap_or(i64::MAX),
+                ..ResponseCheckTx::default()
+            },
+            Err(e) => ResponseCheckTx::from_tx_error(e),
+        }
+    }
+
+    /// Puts the transactions that the modules add to the block before the proposed ones.
+    fn prepare_proposal(&self, request: RequestPrepareProposal) -> ResponsePrepareProposal {
+        let local_last_commit = request
+            .local_last_commit
+            .map(ExtendedCommitInfo::try_from)
+            .transpose()
+            .unwrap_or_else(|e| {
+                error!("invalid extended commit info: {e}");
+                None
+            });
+
+        let mut txs: Vec<_> = local_last_commit
+            .map(|commit| self.prepare_proposal(&commit))
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        txs.extend(request.txs);
+        truncate_txs(&mut txs, request.max_tx_bytes);
+        ResponsePrepareProposal { txs }
+    }
+
+    fn extend_vote(&self, request: RequestExtendVote) -> ResponseExtendVote {
+        debug!("Got extend vote request for height {}.", request.height);
+
+        let vote_extension = match ExtendVote::try_from(request) {
+            Ok(request) => self.extend_vote(&request).encode(),
+            Err(e) => {
+                error!("invalid extend vote request: {e}");
+                vec![]
+            }
+        };
+        ResponseExtendVote {
+            vote_extension: vote_extension.into(),
+        }
+    }
 
-        for IdentifiedModule { id, module } in modules.iter_mut() {
-            module
-                .store_mut()
-                .commit()
-                .expect("failed to commit to module state");
-            let mut state = self.store.write_access();
-            state
-                .set(id.clone().into(), module.store().root_hash())
-                .expect("failed to update sub-store commitment");
+    fn verify_vote_extension(
+        &self,
+        request: RequestVerifyVoteExtension,
+    ) -> ResponseVerifyVoteExtension {
+        let result = VerifyVoteExtension::try_from(request)
+            .map_err(|e| e.to_string())
+            .and_then(|request| {
+                self.verify_vote_extension(&request)
+                    .map_err(|e| e.to_string())
+            });
+        let status = match result {
+            Ok(()) => VerifyStatus::Accept,
+            Err(e) => {
+                error!("rejected vote extension: {e}");
+                VerifyStatus::Reject
+            }
+        };
+        ResponseVerifyVoteExtension {
+            status: status as i32,
         }
+    }
 
-        let mut state = self.store.write_access();
+    fn finalize_block(&self, request: RequestFinalizeBlock) -> ResponseFinalizeBlock {
+        debug!("Got finalize block request for height {}.", request.height);
 
-        let data = state.commit().expect("failed to commit to state");
+        let header = block_header(self, &request);
+        let mut events: Vec<_> = self
+            .begin_block(&header)
+            .into_iter()
+            .map(Into::into)
+            .collect();
 
-        info!(
-            "Committed height {} with hash({})",
-            state.current_height() - 1,
-            data.iter().fold(String::new(), |mut output, b| {
-                let _ = write!(output, "{b:02X}");
-                output
+        let tx_results = request
+            .txs
+            .iter()
+            .map(|tx| match self.deliver_tx(tx) {
+                Ok(events) => ExecTxResult {
+                    log: "success".to_owned(),
+                    events: events.into_iter().map(Into::into).collect(),
+                    ..ExecTxResult::default()
+                },
+                Err(e) => ExecTxResult::from_tx_error(e),
             })
+            .collect();
+
+        // the end-block hooks run after the txs, as `EndBlock` did
+        let end_block = self.end_block(header.height);
+        events.extend(end_block.events.into_iter().map(Into::into));
+
+        // the app hash is the root hash of the working state, which is only committed by `Commit`
+        let app_hash = self.working_app_hash();
+
+        ResponseFinalizeBlock {
+            events,
+            tx_results,
+            validator_updates: end_block
+                .validator_updates
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            consensus_param_updates: end_block.consensus_param_updates.map(Into::into),
+            app_hash: app_hash.into(),
+        }
+    }
+}
+
+/// Builds the header of the block finalized by `request`, which is given to the begin-block hooks
+/// of the modules.
+///
+/// `FinalizeBlock` only carries the header fields that the hooks read, the other hashes are left
+/// empty. The chain identifier is the one of `InitChain`.
+fn block_header<S: ProvableStore>(app: &BaseCoinApp<S>, request: &RequestFinalizeBlock) -> Header {
+    let chain_id = app
+        .chain_id()
+        .unwrap_or_else(|| "unknown".parse().expect("valid chain identifier"));
+
+    Header {
+        version: Version { block: 11, app: 1 },
+        chain_id,
+        height: request.height.try_into().expect("invalid block height"),
+        time: request
+            .time
+            .clone()
+            .expect("missing block time")
+            .try_into()
+            .expect("invalid block time"),
+        last_block_id: None,
+        last_commit_hash: None,
+        data_hash: None,
+        validators_hash: Hash::None,
+        next_validators_hash: request
+            .next_validators_hash
+            .to_vec()
+            .try_into()
+            .expect("invalid next validators hash"),
+        consensus_hash: Hash::None,
+        // the app hash of a header is the one of the previous block
+        app_hash: app.store.root_hash().try_into().expect("invalid app hash"),
+        last_results_hash: None,
+        evidence_hash: None,
+        proposer_address: request
+            .proposer_address
+            .to_vec()
+            .try_into()
+            .expect("invalid proposer address"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use basecoin_modules::auth::{Auth, ACCOUNT_PREFIX};
+    use basecoin_modules::bank::Bank;
+    use basecoin_modules::context::{prefix, Identifiable, Module};
+    use basecoin_modules::types::ModuleStore;
+    use basecoin_store::impls::{InMemoryStore, SharedStore};
+    use basecoin_store::types::Identifier;
+    use cosmrs::bank::MsgSend;
+    use cosmrs::crypto::secp256k1::SigningKey;
+    use cosmrs::proto::cosmos::tx::v1beta1::{AuthInfo, TxRaw};
+    use cosmrs::tx::{Body, Fee, Msg, SignDoc, SignerInfo};
+    use cosmrs::{AccountId, Coin};
+    use prost::Message;
+    use serde_json::json;
+    use tendermint::abci::response::EndBlock;
+    use tendermint::abci::Event;
+    use tendermint::{validator, PublicKey};
+    use tendermint_proto::abci::{
+        ExtendedCommitInfo as ProtoExtendedCommitInfo, ExtendedVoteInfo, Validator,
+    };
+    use tendermint_proto::google::protobuf::Timestamp;
+    use tendermint_proto::types::BlockIdFlag;
+
+    use super::*;
+    use crate::error::TxError;
+    use crate::{Builder, StoreLayout, VoteExtension};
+
+    const CHAIN_ID: &str = "basecoin-test";
+
+    fn app() -> BaseCoinApp<InMemoryStore> {
+        app_with_layout(StoreLayout::default())
+    }
+
+    fn app_with_layout(layout: StoreLayout) -> BaseCoinApp<InMemoryStore> {
+        let builder = Builder::new(InMemoryStore::default()).layout(layout);
+        let auth = Auth::new(builder.module_store(&prefix::Auth {}.identifier()));
+        let bank = Bank::new(
+            builder.module_store(&prefix::Bank {}.identifier()),
+            auth.account_reader().clone(),
+            auth.account_keeper().clone(),
+        );
+        builder
+            .add_module(prefix::Auth {}.identifier(), auth)
+            .add_module(prefix::Bank {}.identifier(), bank)
+            .build()
+    }
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn account(key: &SigningKey) -> AccountId {
+        key.public_key().account_id(ACCOUNT_PREFIX).unwrap()
+    }
+
+    fn coin(amount: u128) -> Coin {
+        Coin {
+            denom: "basecoin".parse().unwrap(),
+            amount,
+        }
+    }
+
+    /// Returns the number of the account of `key` created by `init_chain`, which numbers the
+    /// accounts of alice and bob in the order of their addresses.
+    fn account_number(key: &SigningKey) -> u64 {
+        let mut genesis =
+            [signing_key(1), signing_key(2)].map(|alice_or_bob| account(&alice_or_bob).to_string());
+        genesis.sort();
+        genesis
+            .iter()
+            .position(|address| *address == account(key).to_string())
+            .map_or(0, |number| number as u64)
+    }
+
+    fn send_tx(
+        from: &SigningKey,
+        to: &SigningKey,
+        amount: u128,
+        fee: u128,
+        sequence: u64,
+    ) -> Vec<u8> {
+        let msg = MsgSend {
+            from_address: account(from),
+            to_address: account(to),
+            amount: vec![coin(amount)],
+        };
+        let body = Body::new(vec![msg.to_any().unwrap()], "", 0u32);
+        let auth_info = SignerInfo::single_direct(Some(from.public_key()), sequence)
+            .auth_info(Fee::from_amount_and_gas(coin(fee), 100_000u64));
+        let sign_doc = SignDoc::new(
+            &body,
+            &auth_info,
+            &CHAIN_ID.parse().unwrap(),
+            account_number(from),
+        )
+        .unwrap();
+        sign_doc.sign(from).unwrap().to_bytes().unwrap()
+    }
+
+    fn finalize_block(
+        app: &BaseCoinApp<InMemoryStore>,
+        height: i64,
+        txs: Vec<Vec<u8>>,
+    ) -> ResponseFinalizeBlock {
+        app.finalize_block(RequestFinalizeBlock {
+            txs: txs.into_iter().map(Into::into).collect(),
+            height,
+            time: Some(Timestamp {
+                seconds: height,
+                nanos: 0,
+            }),
+            next_validators_hash: vec![0; 32].into(),
+            proposer_address: vec![0; 20].into(),
+            ..Default::default()
+        })
+    }
+
+    fn balance(app: &BaseCoinApp<InMemoryStore>, key: &SigningKey) -> Value {
+        let response = app.query(RequestQuery {
+            data: account(key).to_string().into_bytes().into(),
+            ..Default::default()
+        });
+        assert_eq!(response.code, 0, "{}", response.log);
+        serde_json::from_slice(&response.value).unwrap()
+    }
+
+    /// Initializes the chain with the accounts of `alice` and `bob`.
+    fn init_chain(app: &BaseCoinApp<InMemoryStore>, alice: &SigningKey, bob: &SigningKey) {
+        let app_state = json!({
+            (account(alice).to_string()): { "basecoin": "0x100" },
+            (account(bob).to_string()): { "basecoin": "0x10" },
+        });
+        app.init_chain(RequestInitChain {
+            chain_id: CHAIN_ID.to_owned(),
+            app_state_bytes: app_state.to_string().into_bytes().into(),
+            ..Default::default()
+        });
+    }
+
+    fn check_tx(app: &BaseCoinApp<InMemoryStore>, tx: &[u8], kind: CheckTxType) -> u32 {
+        let response = Application::check_tx(
+            app,
+            RequestCheckTx {
+                tx: tx.to_vec().into(),
+                r#type: kind as i32,
+            },
+        );
+        if response.code == 0 {
+            assert_eq!(response.gas_wanted, 100_000);
+        }
+        response.code
+    }
+
+    #[test]
+    fn test_finalize_blocks() {
+        let app = app();
+        let (alice, bob) = (signing_key(1), signing_key(2));
+        init_chain(&app, &alice, &bob);
+
+        // a valid tx and an undecodable one
+        let block = finalize_block(&app, 1, vec![send_tx(&alice, &bob, 0x60, 0, 0), vec![0xff]]);
+        let codes: Vec<_> = block.tx_results.iter().map(|result| result.code).collect();
+        assert_eq!(codes, [0, 1]);
+        // the block is only committed by `Commit`, with the app hash returned by `FinalizeBlock`
+        let info = app.info(RequestInfo::default());
+        assert_eq!(info.last_block_height, 0);
+        assert_ne!(info.last_block_app_hash, block.app_hash);
+        app.commit();
+        let info = app.info(RequestInfo::default());
+        assert_eq!(info.last_block_height, 1);
+        assert_eq!(info.last_block_app_hash, block.app_hash);
+        assert_eq!(
+            balance(&app, &bob),
+            json!([{ "denom": "basecoin", "amount": "0x70" }])
+        );
+
+        // a failing tx only uses the sequence of its signer, its message changes nothing
+        let failed = finalize_block(&app, 2, vec![send_tx(&alice, &bob, 0x1000, 0, 1)]);
+        assert_eq!(failed.tx_results[0].code, 2);
+        assert_ne!(failed.app_hash, block.app_hash);
+        app.commit();
+        assert_eq!(
+            balance(&app, &alice),
+            json!([{ "denom": "basecoin", "amount": "0xa0" }])
+        );
+
+        let block = finalize_block(&app, 3, vec![send_tx(&bob, &alice, 0x70, 0, 0)]);
+        assert_eq!(block.tx_results[0].code, 0, "{}", block.tx_results[0].log);
+        assert_ne!(block.app_hash, failed.app_hash);
+        app.commit();
+        let info = app.info(RequestInfo::default());
+        assert_eq!(info.last_block_height, 3);
+        assert_eq!(info.last_block_app_hash, block.app_hash);
+        assert_eq!(
+            balance(&app, &alice),
+            json!([{ "denom": "basecoin", "amount": "0x110" }])
         );
-        ResponseCommit { retain_height: 0 }
     }
 
-    fn finalize_block(&self, _request: RequestFinalizeBlock) -> ResponseFinalizeBlock {
-        unimplemented!()
+    #[test]
+    fn test_commit_info() {
+        for layout in [StoreLayout::MultiTree, StoreLayout::SingleTree] {
+            // the stores of a height may be empty
+            let empty = app_with_layout(layout);
+            empty.commit_stores();
+            let info = empty.commit_info(Height::Latest).unwrap();
+            assert_eq!(info.version, 1);
+            assert_eq!(info.store_infos.len(), 2);
+
+            let app = app_with_layout(layout);
+            let (alice, bob) = (signing_key(1), signing_key(2));
+            init_chain(&app, &alice, &bob);
+            assert_eq!(app.commit_info(Height::Latest), None);
+
+            let first = finalize_block(&app, 1, vec![]);
+            app.commit();
+            let second = finalize_block(&app, 2, vec![send_tx(&alice, &bob, 0x10, 0, 0)]);
+            app.commit();
+
+            let infos = [1, 2].map(|height| app.commit_info(Height::Stable(height)).unwrap());
+            assert_eq!(app.commit_info(Height::Latest).as_ref(), Some(&infos[1]));
+            for (info, block) in infos.iter().zip([&first, &second]) {
+                let names: Vec<_> = info.store_infos.iter().map(|s| s.name.as_str()).collect();
+                assert_eq!(names, ["auth", "bank"]);
+                if layout == StoreLayout::SingleTree {
+                    assert!(info.store_infos.iter().all(|s| s.hash == block.app_hash));
+                }
+            }
+            if layout == StoreLayout::MultiTree {
+                // the root hashes of the module stores at each height
+                let [first, second] = infos.map(|info| {
+                    let hashes = info.store_infos.into_iter().map(|s| s.hash);
+                    hashes.collect::<Vec<_>>()
+                });
+                let modules = app.modules.read_access();
+                let latest: Vec<_> = modules
+                    .iter()
+                    .map(|m| m.module.store().root_hash())
+                    .collect();
+                assert_eq!(second, latest);
+                assert_ne!(first, second);
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_tx() {
+        let app = app();
+        let (alice, bob) = (signing_key(1), signing_key(2));
+        init_chain(&app, &alice, &bob);
+        // the check state starts from the committed state
+        finalize_block(&app, 1, vec![]);
+        app.commit();
+
+        let first = send_tx(&alice, &bob, 0x10, 0x20, 0);
+        assert_eq!(check_tx(&app, &first, CheckTxType::New), 0);
+        // the sequence was used by the first tx
+        assert_eq!(
+            check_tx(&app, &first, CheckTxType::New),
+            TxError::WRONG_SEQUENCE
+        );
+        let second = send_tx(&alice, &bob, 0x10, 0x20, 1);
+        assert_eq!(check_tx(&app, &second, CheckTxType::New), 0);
+        // the fees of the checked txs are debited in the check state, which no longer covers a
+        // fee that the committed balance would
+        let overdrawn = send_tx(&alice, &bob, 0x10, 0xd0, 2);
+        assert_eq!(check_tx(&app, &overdrawn, CheckTxType::New), 2);
+
+        assert_eq!(check_tx(&app, &[0xff], CheckTxType::New), 1);
+        let mut unsigned = TxRaw::decode(first.as_slice()).unwrap();
+        unsigned.signatures.clear();
+        let unsigned = unsigned.encode_to_vec();
+        assert_eq!(
+            check_tx(&app, &unsigned, CheckTxType::New),
+            TxError::UNAUTHORIZED
+        );
+        let unfunded = send_tx(&bob, &alice, 0x1, 0x20, 0);
+        assert_eq!(check_tx(&app, &unfunded, CheckTxType::New), 2);
+        let unknown = send_tx(&signing_key(3), &alice, 0x1, 0, 0);
+        assert_eq!(check_tx(&app, &unknown, CheckTxType::New), 2);
+        // the fee payer must sign the tx
+        let mut raw = TxRaw::decode(send_tx(&alice, &bob, 0x1, 0x20, 2).as_slice()).unwrap();
+        let mut auth_info = AuthInfo::decode(raw.auth_info_bytes.as_slice()).unwrap();
+        auth_info.fee.as_mut().unwrap().payer = account(&bob).to_string();
+        raw.auth_info_bytes = auth_info.encode_to_vec();
+        let unsigned_payer = resign(&raw.encode_to_vec(), &[&alice], CHAIN_ID);
+        assert_eq!(
+            check_tx(&app, &unsigned_payer, CheckTxType::New),
+            TxError::UNAUTHORIZED
+        );
+
+        // the check state is reset on commit, so the mempool txs are rechecked against the state
+        // of the block that included the first one
+        finalize_block(&app, 2, vec![first.clone()]);
+        app.commit();
+        assert_eq!(
+            check_tx(&app, &first, CheckTxType::Recheck),
+            TxError::WRONG_SEQUENCE
+        );
+        assert_eq!(check_tx(&app, &second, CheckTxType::Recheck), 0);
+    }
+
+    #[test]
+    fn test_fees() {
+        let app = app();
+        let (alice, bob) = (signing_key(1), signing_key(2));
+        init_chain(&app, &alice, &bob);
+
+        let block = finalize_block(&app, 1, vec![send_tx(&alice, &bob, 0x10, 0x20, 0)]);
+        assert_eq!(block.tx_results[0].code, 0, "{}", block.tx_results[0].log);
+        app.commit();
+        assert_eq!(
+            balance(&app, &alice),
+            json!([{ "denom": "basecoin", "amount": "0xd0" }])
+        );
+
+        // the fee of a tx is paid even if its message fails
+        let block = finalize_block(&app, 2, vec![send_tx(&alice, &bob, 0x1000, 0x20, 1)]);
+        assert_eq!(block.tx_results[0].code, 2);
+        app.commit();
+        assert_eq!(
+            balance(&app, &alice),
+            json!([{ "denom": "basecoin", "amount": "0xb0" }])
+        );
+
+        // a tx whose fee can't be paid changes nothing, not even the sequence of its signer
+        let block = finalize_block(
+            &app,
+            3,
+            vec![
+                send_tx(&bob, &alice, 0x1, 0x100, 0),
+                send_tx(&bob, &alice, 0x1, 0, 0),
+            ],
+        );
+        let codes: Vec<_> = block.tx_results.iter().map(|result| result.code).collect();
+        assert_eq!(codes, [2, 0]);
+        app.commit();
+        assert_eq!(
+            balance(&app, &bob),
+            json!([{ "denom": "basecoin", "amount": "0x1f" }])
+        );
+    }
+
+    /// Signs `tx` again with `keys`, in the order of its signers, for the chain `chain_id`.
+    fn resign(tx: &[u8], keys: &[&SigningKey], chain_id: &str) -> Vec<u8> {
+        let keys: Vec<_> = keys.iter().map(|key| (*key, account_number(key))).collect();
+        resign_with_numbers(tx, &keys, chain_id)
+    }
+
+    /// Signs `tx` again with `keys` and the account numbers given with them, in the order of its
+    /// signers, for the chain `chain_id`.
+    fn resign_with_numbers(tx: &[u8], keys: &[(&SigningKey, u64)], chain_id: &str) -> Vec<u8> {
+        let mut raw = TxRaw::decode(tx).unwrap();
+        raw.signatures = keys
+            .iter()
+            .map(|(key, account_number)| {
+                let sign_doc = SignDoc {
+                    body_bytes: raw.body_bytes.clone(),
+                    auth_info_bytes: raw.auth_info_bytes.clone(),
+                    chain_id: chain_id.to_owned(),
+                    account_number: *account_number,
+                };
+                let signed = sign_doc.sign(key).unwrap().to_bytes().unwrap();
+                TxRaw::decode(signed.as_slice())
+                    .unwrap()
+                    .signatures
+                    .remove(0)
+            })
+            .collect();
+        raw.encode_to_vec()
+    }
+
+    /// Adds `cosigner` with `sequence` as the last signer of `tx`, and signs it again with `keys`.
+    fn cosign(tx: &[u8], cosigner: &SigningKey, sequence: u64, keys: &[&SigningKey]) -> Vec<u8> {
+        let mut raw = TxRaw::decode(tx).unwrap();
+        let mut auth_info = AuthInfo::decode(raw.auth_info_bytes.as_slice()).unwrap();
+        auth_info
+            .signer_infos
+            .push(SignerInfo::single_direct(Some(cosigner.public_key()), sequence).into());
+        raw.auth_info_bytes = auth_info.encode_to_vec();
+        resign(&raw.encode_to_vec(), keys, CHAIN_ID)
+    }
+
+    #[test]
+    fn test_signature_verification() {
+        let app = app();
+        let (alice, bob) = (signing_key(1), signing_key(2));
+        init_chain(&app, &alice, &bob);
+        finalize_block(&app, 1, vec![]);
+        app.commit();
+
+        let tx = send_tx(&alice, &bob, 0x10, 0, 0);
+        let other_chain = resign(&tx, &[&alice], "other-chain");
+        let other_key = resign(&tx, &[&bob], CHAIN_ID);
+        // the signature commits to the number of the account of the signer
+        let other_number = resign_with_numbers(&tx, &[(&alice, account_number(&bob))], CHAIN_ID);
+        for forged in [&other_chain, &other_key, &other_number] {
+            assert_eq!(
+                check_tx(&app, forged, CheckTxType::New),
+                TxError::UNAUTHORIZED
+            );
+        }
+        // the signatures of mempool txs aren't verified again on recheck
+        assert_eq!(check_tx(&app, &other_key, CheckTxType::Recheck), 0);
+
+        let block = finalize_block(&app, 2, vec![other_chain, other_key, other_number, tx]);
+        let codes: Vec<_> = block.tx_results.iter().map(|result| result.code).collect();
+        assert_eq!(
+            codes,
+            [
+                TxError::UNAUTHORIZED,
+                TxError::UNAUTHORIZED,
+                TxError::UNAUTHORIZED,
+                0
+            ]
+        );
+        assert_eq!(
+            balance(&app, &bob),
+            json!([{ "denom": "basecoin", "amount": "0x20" }])
+        );
+    }
+
+    #[test]
+    fn test_sequences() {
+        let app = app();
+        let (alice, bob) = (signing_key(1), signing_key(2));
+        init_chain(&app, &alice, &bob);
+        finalize_block(&app, 1, vec![]);
+        app.commit();
+
+        // a tx can't be replayed, neither in its block nor in a later one
+        let tx = send_tx(&alice, &bob, 0x10, 0, 0);
+        let block = finalize_block(&app, 2, vec![tx.clone(), tx.clone()]);
+        let codes: Vec<_> = block.tx_results.iter().map(|result| result.code).collect();
+        assert_eq!(codes, [0, TxError::WRONG_SEQUENCE]);
+        app.commit();
+        let block = finalize_block(&app, 3, vec![tx]);
+        assert_eq!(block.tx_results[0].code, TxError::WRONG_SEQUENCE);
+        app.commit();
+
+        // the sequences of all the signers are incremented
+        let cosigned = cosign(&send_tx(&alice, &bob, 0x10, 0, 1), &bob, 0, &[&alice, &bob]);
+        assert_eq!(check_tx(&app, &cosigned, CheckTxType::New), 0);
+        let block = finalize_block(&app, 4, vec![cosigned]);
+        assert_eq!(block.tx_results[0].code, 0, "{}", block.tx_results[0].log);
+        app.commit();
+        let block = finalize_block(
+            &app,
+            5,
+            vec![
+                send_tx(&bob, &alice, 0x10, 0, 0),
+                send_tx(&alice, &bob, 0x10, 0, 2),
+                send_tx(&bob, &alice, 0x10, 0, 1),
+            ],
+        );
+        let codes: Vec<_> = block.tx_results.iter().map(|result| result.code).collect();
+        assert_eq!(codes, [TxError::WRONG_SEQUENCE, 0, 0]);
+        app.commit();
+
+        // neither can a tx whose message failed, as its sequence was still used
+        let failing = send_tx(&alice, &bob, 0x1000, 0, 3);
+        let block = finalize_block(&app, 6, vec![failing.clone(), failing.clone()]);
+        let codes: Vec<_> = block.tx_results.iter().map(|result| result.code).collect();
+        assert_eq!(codes, [2, TxError::WRONG_SEQUENCE]);
+        app.commit();
+        let block = finalize_block(&app, 7, vec![failing]);
+        assert_eq!(block.tx_results[0].code, TxError::WRONG_SEQUENCE);
+    }
+
+    /// A module that extends votes with their height, and proposes the extensions of the last
+    /// block as transactions.
+    struct Oracle {
+        store: SharedStore<ModuleStore<InMemoryStore>>,
+    }
+
+    impl Module for Oracle {
+        type Store = ModuleStore<InMemoryStore>;
+
+        fn extend_vote(&self, request: &ExtendVote) -> Vec<u8> {
+            request.height.value().to_be_bytes().to_vec()
+        }
+
+        fn verify_vote_extension(&self, request: &VerifyVoteExtension) -> Result<(), Error> {
+            if request.vote_extension.as_ref() == request.height.value().to_be_bytes() {
+                Ok(())
+            } else {
+                Err(Error::Custom {
+                    reason: "not the vote height".to_owned(),
+                })
+            }
+        }
+
+        fn prepare_proposal(&self, local_last_commit: &ExtendedCommitInfo) -> Vec<Vec<u8>> {
+            local_last_commit
+                .votes
+                .iter()
+                .filter(|vote| !vote.vote_extension.is_empty())
+                .map(|vote| vote.vote_extension.to_vec())
+                .collect()
+        }
+
+        fn store_mut(&mut self) -> &mut SharedStore<Self::Store> {
+            &mut self.store
+        }
+
+        fn store(&self) -> &SharedStore<Self::Store> {
+            &self.store
+        }
+    }
+
+    #[test]
+    fn test_vote_extensions() {
+        let builder = Builder::new(InMemoryStore::default());
+        let id = Identifier::from("oracle".to_owned());
+        let oracle = Oracle {
+            store: builder.module_store(&id),
+        };
+        let app = builder.add_module(id, oracle).build();
+
+        let extended = Application::extend_vote(
+            &app,
+            RequestExtendVote {
+                height: 5,
+                time: Some(Timestamp::default()),
+                proposer_address: vec![0; 20].into(),
+                ..Default::default()
+            },
+        )
+        .vote_extension;
+        let extension = VoteExtension::decode(&extended).unwrap();
+        assert_eq!(extension.get("oracle"), 5u64.to_be_bytes());
+
+        let verify = |height, vote_extension: Vec<u8>| {
+            Application::verify_vote_extension(
+                &app,
+                RequestVerifyVoteExtension {
+                    validator_address: vec![0; 20].into(),
+                    height,
+                    vote_extension: vote_extension.into(),
+                    ..Default::default()
+                },
+            )
+            .status
+        };
+        assert_eq!(verify(5, extended.to_vec()), VerifyStatus::Accept as i32);
+        assert_eq!(verify(6, extended.to_vec()), VerifyStatus::Reject as i32);
+        // malformed extensions and extensions of unknown modules are rejected
+        assert_eq!(verify(5, vec![0xff]), VerifyStatus::Reject as i32);
+        let mut unknown = extension.clone();
+        unknown.extensions.insert("bank".to_owned(), vec![1]);
+        assert_eq!(verify(5, unknown.encode()), VerifyStatus::Reject as i32);
+
+        // the module only sees its own extensions, and its transactions come first
+        let vote = |vote_extension: Vec<u8>| ExtendedVoteInfo {
+            validator: Some(Validator {
+                address: vec![0; 20].into(),
+                power: 1,
+            }),
+            vote_extension: vote_extension.into(),
+            block_id_flag: BlockIdFlag::Commit as i32,
+            ..Default::default()
+        };
+        let txs = Application::prepare_proposal(
+            &app,
+            RequestPrepareProposal {
+                max_tx_bytes: 1024,
+                txs: vec![vec![1].into()],
+                local_last_commit: Some(ProtoExtendedCommitInfo {
+                    round: 0,
+                    votes: vec![vote(extended.to_vec()), vote(vec![0xff])],
+                }),
+                ..Default::default()
+            },
+        )
+        .txs;
+        assert_eq!(txs, [5u64.to_be_bytes().to_vec(), vec![1]]);
+    }
+
+    /// A module that sets the voting power of a validator to the height of every block.
+    struct Validators {
+        store: SharedStore<ModuleStore<InMemoryStore>>,
+    }
+
+    impl Module for Validators {
+        type Store = ModuleStore<InMemoryStore>;
+
+        fn end_block(&mut self, height: tendermint::block::Height) -> EndBlock {
+            EndBlock {
+                validator_updates: vec![validator::Update {
+                    pub_key: PublicKey::from_raw_ed25519(&[1; 32]).unwrap(),
+                    power: u32::try_from(height.value()).unwrap().into(),
+                }],
+                consensus_param_updates: None,
+                events: vec![Event::new("power", [("height", height.to_string())])],
+            }
+        }
+
+        fn store_mut(&mut self) -> &mut SharedStore<Self::Store> {
+            &mut self.store
+        }
+
+        fn store(&self) -> &SharedStore<Self::Store> {
+            &self.store
+        }
+    }
+
+    #[test]
+    fn test_end_block() {
+        // the modules don't change the validator set or the consensus parameters by default, as
+        // with the `EndBlock` of 0.37
+        let block = finalize_block(&app(), 1, vec![]);
+        assert!(block.validator_updates.is_empty());
+        assert_eq!(block.consensus_param_updates, None);
+
+        let builder = Builder::new(InMemoryStore::default());
+        let id = Identifier::from("validators".to_owned());
+        let validators = Validators {
+            store: builder.module_store(&id),
+        };
+        let with_validators = builder.add_module(id, validators).build();
+        let block = finalize_block(&with_validators, 3, vec![]);
+        let powers: Vec<_> = block
+            .validator_updates
+            .iter()
+            .map(|update| update.power)
+            .collect();
+        assert_eq!(powers, [3]);
+        assert_eq!(block.consensus_param_updates, None);
+        let kinds: Vec<_> = block
+            .events
+            .iter()
+            .map(|event| event.r#type.as_str())
+            .collect();
+        assert_eq!(kinds, ["power"]);
     }
 }
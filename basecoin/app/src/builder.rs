@@ -1,21 +1,50 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Write};
 use std::sync::{Arc, RwLock};
 
+use basecoin_modules::auth::{tx_signers, Error as AuthError, ACCOUNT_PREFIX};
+use basecoin_modules::bank::Error as BankError;
 use basecoin_modules::context::Module;
 use basecoin_modules::error::Error;
-use basecoin_modules::types::{IdentifiedModule, ModuleList, ModuleStore};
-use basecoin_store::context::ProvableStore;
-use basecoin_store::impls::SharedStore;
-use basecoin_store::types::{Identifier, MainStore};
+use basecoin_modules::types::{IdentifiedModule, ModuleList, ModuleStore, SignedTx};
+use basecoin_store::context::{ProvableStore, Store};
+use basecoin_store::impls::{PrefixStore, SharedStore};
+use basecoin_store::types::{Height, Identifier, MainStore, Path, PruningStrategy, RawHeight};
 use basecoin_store::utils::{SharedRw, SharedRwExt};
-use cosmrs::AccountId;
+use cosmrs::proto::cosmos::tx::v1beta1::TxRaw;
+use cosmrs::tx::{Fee, SignerInfo, SignerPublicKey};
+use cosmrs::{AccountId, Tx};
 use ibc_proto::google::protobuf::Any;
+use prost::Message;
+use tendermint::abci::response::EndBlock;
 use tendermint::abci::Event;
-use tracing::error;
+use tendermint::block::{Header, Height as BlockHeight};
+use tendermint::merkle::proof::{ProofOp, ProofOps};
+use tracing::{error, info};
+
+use crate::commit_info::{CommitInfo, StoreInfo};
+use crate::error::TxError;
+
+/// Path of the chain identifier in the main store.
+const CHAIN_ID_PATH: &str = "chain_id";
+
+/// How the module stores are laid out.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum StoreLayout {
+    /// Every module has a Merkle tree of its own, whose root hash is written to the main store
+    /// under the module identifier on commit. Proofs of module state chain a proof in the module
+    /// tree with a proof of the module root hash in the main tree, as in the Cosmos SDK.
+    #[default]
+    MultiTree,
+    /// All the modules share the main Merkle tree, each under its identifier as a key prefix.
+    /// Proofs of module state are single proofs in the main tree.
+    SingleTree,
+}
 
 pub struct Builder<S> {
     store: MainStore<S>,
     modules: SharedRw<ModuleList<S>>,
+    pruning: PruningStrategy,
+    layout: StoreLayout,
 }
 
 impl<S: Default + ProvableStore> Builder<S> {
@@ -24,18 +53,54 @@ impl<S: Default + ProvableStore> Builder<S> {
         Self {
             store: SharedStore::new(store),
             modules: Arc::new(RwLock::new(vec![])),
+            pruning: PruningStrategy::default(),
+            layout: StoreLayout::default(),
         }
     }
 
+    /// Sets the strategy used to prune the main store and the module stores after every commit.
+    pub fn pruning(mut self, pruning: PruningStrategy) -> Self {
+        self.pruning = pruning;
+        self
+    }
+
+    /// Sets the layout of the module stores. Must be set before any module store is created.
+    pub fn layout(mut self, layout: StoreLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
     /// Returns a share to the module's store if a module with specified identifier was previously
     /// added, otherwise creates a new module store and returns it.
     pub fn module_store(&self, prefix: &Identifier) -> SharedStore<ModuleStore<S>> {
+        self.module_store_with(prefix, S::default)
+    }
+
+    /// Same as [`Self::module_store`], but uses `new_store` to create the module store if a
+    /// module with specified identifier wasn't previously added. `new_store` isn't called in the
+    /// [`StoreLayout::SingleTree`] layout, where the module store is a prefix of the main store.
+    pub fn module_store_with(
+        &self,
+        prefix: &Identifier,
+        new_store: impl FnOnce() -> S,
+    ) -> SharedStore<ModuleStore<S>> {
         let modules = self.modules.read_access();
         modules
             .iter()
             .find(|m| &m.id == prefix)
             .map(|IdentifiedModule { module, .. }| module.store().share())
-            .unwrap_or_else(|| SharedStore::new(S::default()))
+            .unwrap_or_else(|| match self.layout {
+                StoreLayout::MultiTree => {
+                    SharedStore::new(PrefixStore::unprefixed(SharedStore::new(new_store())))
+                }
+                // the snapshot of the module store is derived from the one of the main store
+                StoreLayout::SingleTree => {
+                    let prefix: Path = prefix.clone().into();
+                    SharedStore::derive(&self.store, move |store| {
+                        PrefixStore::new(store, prefix.clone())
+                    })
+                }
+            })
     }
 
     #[inline]
@@ -61,10 +126,51 @@ impl<S: Default + ProvableStore> Builder<S> {
         BaseCoinApp {
             store: self.store,
             modules: self.modules,
+            pruning: self.pruning,
+            layout: self.layout,
+            chain_id: Default::default(),
         }
     }
 }
 
+/// A transaction that passed CheckTx.
+#[derive(Clone, Debug)]
+pub(crate) struct CheckedTx {
+    pub gas_wanted: u64,
+    /// Priority of the transaction in the mempool, see [`fee_priority`].
+    pub priority: i64,
+    /// Address of the first signer.
+    pub sender: String,
+}
+
+/// Returns the lowest fee per unit of gas across the denominations of `fee`, as in the Cosmos SDK.
+fn fee_priority(fee: &Fee) -> i64 {
+    if fee.gas_limit == 0 {
+        return 0;
+    }
+    fee.amount
+        .iter()
+        .map(|coin| coin.amount / u128::from(fee.gas_limit))
+        .min()
+        .map_or(0, |priority| priority.try_into().unwrap_or(i64::MAX))
+}
+
+/// Returns the code of the failure of a transaction with module error `e`.
+fn tx_error_code(e: &Error) -> u32 {
+    match e {
+        Error::Auth(
+            AuthError::SignatureCountMismatch { .. }
+            | AuthError::UnsupportedSignMode { .. }
+            | AuthError::InvalidSignature { .. },
+        )
+        | Error::Bank(BankError::UnsignedFeePayer { .. } | BankError::UnsignedSender { .. }) => {
+            TxError::UNAUTHORIZED
+        }
+        Error::Auth(AuthError::SequenceMismatch { .. }) => TxError::WRONG_SEQUENCE,
+        _ => 2,
+    }
+}
+
 /// BaseCoin ABCI application.
 ///
 /// Can be safely cloned and sent across threads, but not shared.
@@ -72,6 +178,11 @@ impl<S: Default + ProvableStore> Builder<S> {
 pub struct BaseCoinApp<S> {
     pub store: MainStore<S>,
     pub modules: SharedRw<ModuleList<S>>,
+    pub pruning: PruningStrategy,
+    pub layout: StoreLayout,
+    /// Chain identifier received in `InitChain`, which transactions are signed for. It's kept in
+    /// the main store at [`CHAIN_ID_PATH`] and cached here once read.
+    pub(crate) chain_id: SharedRw<Option<tendermint::chain::Id>>,
 }
 
 impl<S: Default + Debug + ProvableStore> BaseCoinApp<S> {
@@ -106,4 +217,374 @@ impl<S: Default + Debug + ProvableStore> BaseCoinApp<S> {
             Err(Error::NotHandled)
         }
     }
+
+    /// Decodes and executes a transaction, delivering each of its messages to the modules.
+    ///
+    /// The `deliver_tx` hooks of the modules are run first, and their changes, e.g. the sequence
+    /// increments, are applied to the module stores as soon as they all succeed, so that a failing
+    /// message doesn't make the transaction replayable. The changes of the messages are applied
+    /// only if all of them succeed, otherwise they are reset. Returns the events of the hooks and
+    /// of the messages.
+    pub(crate) fn deliver_tx(&self, tx: &[u8]) -> Result<Vec<Event>, TxError> {
+        let tx = self.decode_tx(tx)?;
+
+        // Extract `AccountId` of first signer
+        let signer = match tx.tx.auth_info.signer_infos.first() {
+            Some(&SignerInfo {
+                public_key: Some(SignerPublicKey::Single(pubkey)),
+                ..
+            }) => pubkey
+                .account_id(ACCOUNT_PREFIX)
+                .map_err(|_| TxError::new(2, "Invalid signer"))?,
+            _ => return Err(TxError::new(2, "Empty signers")),
+        };
+
+        if tx.tx.body.messages.is_empty() {
+            return Err(TxError::new(2, "Empty Tx"));
+        }
+
+        let fail = |e: Error| {
+            // reset changes from the hooks or from the other messages in this tx
+            let mut modules = self.modules.write_access();
+            for IdentifiedModule { module, .. } in modules.iter_mut() {
+                module.store_mut().reset();
+            }
+
+            // probably the main store doesn't need to be reset.
+            // currently the only time it is written while committing a block.
+            // but doing it nonetheless, just in case.
+            self.store.write_access().reset();
+            TxError::new(tx_error_code(&e), format!("deliver failed with error: {e}"))
+        };
+
+        // run the deliver-tx hooks of the modules before any message, e.g. to verify signatures
+        let hook_events: Result<Vec<_>, _> = {
+            let mut modules = self.modules.write_access();
+            modules
+                .iter_mut()
+                .map(|IdentifiedModule { module, .. }| module.deliver_tx(&tx))
+                .collect()
+        };
+        let mut events: Vec<_> = hook_events.map_err(fail)?.into_iter().flatten().collect();
+        // persists changes from the hooks, which are kept even if a message fails
+        self.apply_stores();
+
+        for message in tx.tx.body.messages {
+            let message = Any {
+                type_url: message.type_url,
+                value: message.value,
+            };
+
+            // try to deliver message to every module
+            match self.deliver_msg(message, &signer) {
+                // success - append events and continue with next message
+                Ok(mut msg_events) => events.append(&mut msg_events),
+                // return on first error -
+                // either an error that occurred during execution of this message OR no module
+                // could handle this message
+                Err(e) => return Err(fail(e)),
+            }
+        }
+
+        // persists changes from all the messages in this tx
+        self.apply_stores();
+
+        Ok(events)
+    }
+
+    /// Applies the pending changes of the module stores and of the main store.
+    fn apply_stores(&self) {
+        let mut modules = self.modules.write_access();
+        for IdentifiedModule { module, .. } in modules.iter_mut() {
+            module
+                .store_mut()
+                .apply()
+                .expect("failed to apply to module state");
+        }
+
+        // probably the main store doesn't need to be applied.
+        // currently the only time it is written while committing a block.
+        // but doing it nonetheless, just in case.
+        self.store
+            .write_access()
+            .apply()
+            .expect("failed to commit to state");
+    }
+}
+
+impl<S: ProvableStore> BaseCoinApp<S> {
+    /// Sets the chain identifier received in `InitChain`. It's applied to the pending state of the
+    /// main store, so that it's committed with the first block and known again after a restart.
+    pub(crate) fn set_chain_id(&self, chain_id: tendermint::chain::Id) {
+        let mut state = self.store.write_access();
+        state
+            .set(
+                CHAIN_ID_PATH.to_owned().into(),
+                chain_id.as_str().as_bytes().to_vec(),
+            )
+            .expect("failed to set chain identifier");
+        state.apply().expect("failed to apply to state");
+        *self.chain_id.write_access() = Some(chain_id);
+    }
+
+    /// Returns the chain identifier received in `InitChain`, if any.
+    pub fn chain_id(&self) -> Option<tendermint::chain::Id> {
+        if let Some(chain_id) = self.chain_id.read_access().clone() {
+            return Some(chain_id);
+        }
+        let chain_id = self
+            .store
+            .get(Height::Pending, &CHAIN_ID_PATH.to_owned().into())?;
+        let chain_id: tendermint::chain::Id = String::from_utf8(chain_id).ok()?.parse().ok()?;
+        *self.chain_id.write_access() = Some(chain_id.clone());
+        Some(chain_id)
+    }
+
+    /// Decodes a transaction, along with the bytes that its signers signed for this chain.
+    fn decode_tx(&self, tx: &[u8]) -> Result<SignedTx, TxError> {
+        let decode_failed =
+            |e: String| TxError::new(1, format!("failed to decode incoming tx bytes: {e}"));
+        let raw = TxRaw::decode(tx).map_err(|e| decode_failed(e.to_string()))?;
+        let tx = Tx::try_from(tx).map_err(|e| decode_failed(e.to_string()))?;
+        Ok(SignedTx {
+            tx,
+            body_bytes: raw.body_bytes,
+            auth_info_bytes: raw.auth_info_bytes,
+            chain_id: self.chain_id().map(|id| id.to_string()).unwrap_or_default(),
+        })
+    }
+
+    /// Checks a transaction for the mempool against the check states of the modules.
+    ///
+    /// Each message is checked with the `check` hook of every module, and then the whole
+    /// transaction with their `check_tx` hook. The check states are only updated with the
+    /// transaction if all the checks pass.
+    pub(crate) fn check_tx(&self, tx: &[u8], recheck: bool) -> Result<CheckedTx, TxError> {
+        let tx = self.decode_tx(tx)?;
+
+        if tx.tx.auth_info.signer_infos.is_empty() {
+            return Err(TxError::new(2, "Empty signers"));
+        }
+        let signers = tx_signers(&tx.tx).ok_or_else(|| TxError::new(2, "Invalid signer"))?;
+
+        if tx.tx.body.messages.is_empty() {
+            return Err(TxError::new(2, "Empty Tx"));
+        }
+
+        let check_failed =
+            |e: Error| TxError::new(tx_error_code(&e), format!("check failed with error: {e}"));
+        let mut modules = self.modules.write_access();
+        for message in tx.tx.body.messages.iter() {
+            let message = Any {
+                type_url: message.type_url.clone(),
+                value: message.value.clone(),
+            };
+            for IdentifiedModule { module, .. } in modules.iter() {
+                match module.check(message.clone()) {
+                    Ok(()) | Err(Error::NotHandled) => continue,
+                    Err(e) => return Err(check_failed(e)),
+                }
+            }
+        }
+        for IdentifiedModule { module, .. } in modules.iter() {
+            module.check_tx(&tx, recheck).map_err(check_failed)?;
+        }
+
+        for IdentifiedModule { module, .. } in modules.iter_mut() {
+            module.on_checked_tx(&tx);
+        }
+
+        Ok(CheckedTx {
+            gas_wanted: tx.tx.auth_info.fee.gas_limit,
+            priority: fee_priority(&tx.tx.auth_info.fee),
+            sender: signers[0].to_string(),
+        })
+    }
+
+    /// Resets the check states of the modules to their latest committed state.
+    pub(crate) fn reset_check_state(&self) {
+        let mut modules = self.modules.write_access();
+        for IdentifiedModule { module, .. } in modules.iter_mut() {
+            module.reset_check_state();
+        }
+    }
+
+    /// Runs the begin-block hook of every module for the block with specified `header`, and
+    /// returns their events.
+    pub fn begin_block(&self, header: &Header) -> Vec<Event> {
+        let mut modules = self.modules.write_access();
+        modules
+            .iter_mut()
+            .flat_map(|IdentifiedModule { module, .. }| module.begin_block(header))
+            .collect()
+    }
+
+    /// Runs the end-block hook of every module for the block at specified `height`, and gathers
+    /// their validator updates and events. The consensus parameter updates of a module replace
+    /// the ones of the modules before it.
+    pub fn end_block(&self, height: BlockHeight) -> EndBlock {
+        let mut modules = self.modules.write_access();
+        let mut end_block = EndBlock::default();
+        for IdentifiedModule { module, .. } in modules.iter_mut() {
+            let EndBlock {
+                validator_updates,
+                consensus_param_updates,
+                events,
+            } = module.end_block(height);
+            end_block.validator_updates.extend(validator_updates);
+            if consensus_param_updates.is_some() {
+                end_block.consensus_param_updates = consensus_param_updates;
+            }
+            end_block.events.extend(events);
+        }
+        end_block
+    }
+
+    /// Returns the app hash that committing the stores would yield, without committing them.
+    ///
+    /// In the multi-tree layout, the working hash of every module store is first recorded in the
+    /// main store, under the module identifier.
+    pub(crate) fn working_app_hash(&self) -> Vec<u8> {
+        let modules = self.modules.read_access();
+        let mut state = self.store.write_access();
+        if self.layout == StoreLayout::MultiTree {
+            for IdentifiedModule { id, module } in modules.iter() {
+                state
+                    .set(id.clone().into(), module.store().working_hash())
+                    .expect("failed to update sub-store commitment");
+            }
+        }
+        state
+            .apply()
+            .expect("failed to apply sub-store commitments");
+        state.working_hash()
+    }
+
+    /// Commits the module stores and the main store. Returns the app hash.
+    pub(crate) fn commit_stores(&self) -> Vec<u8> {
+        // records the sub-store commitments of the multi-tree layout, if not already done
+        self.working_app_hash();
+        let mut modules = self.modules.write_access();
+
+        // in the single-tree layout, the module stores are committed with the main store
+        if self.layout == StoreLayout::MultiTree {
+            for IdentifiedModule { module, .. } in modules.iter_mut() {
+                module
+                    .store_mut()
+                    .commit()
+                    .expect("failed to commit to module state");
+            }
+        }
+
+        // committing through the shared store refreshes the snapshot that serves the reads of
+        // committed heights, from which the snapshots of the module stores of the single-tree
+        // layout are derived
+        let mut state = self.store.share();
+        let data = state.commit().expect("failed to commit to state");
+        let version = state.current_height();
+        info!(
+            "Committed height {} with hash({})",
+            version,
+            data.iter().fold(String::new(), |mut acc, b| {
+                // write!-ing into a String can never fail
+                let _ = write!(acc, "{b:02X}");
+                acc
+            })
+        );
+        data
+    }
+
+    /// Prunes the module stores and the main store according to the pruning strategy. Returns the
+    /// retain height.
+    pub(crate) fn prune_stores(&self) -> RawHeight {
+        if self.layout == StoreLayout::MultiTree {
+            let mut modules = self.modules.write_access();
+            for IdentifiedModule { module, .. } in modules.iter_mut() {
+                self.pruning
+                    .prune(module.store_mut())
+                    .expect("failed to prune module state");
+            }
+        }
+        self.pruning
+            .prune(&mut self.store.share())
+            .expect("failed to prune state")
+    }
+
+    /// Returns the commit info of specified `height`, or of the latest height for the `Pending`
+    /// and `Latest` heights, or `None` if the main store doesn't retain the height.
+    ///
+    /// Commit infos are read from the committed state of the main store, so that they're
+    /// persisted and pruned along with it. In the multi-tree layout, it holds the root hash of
+    /// every module store under the module identifier. In the single-tree layout, the root hash
+    /// of every module store is the root hash of the main store.
+    pub fn commit_info(&self, height: Height) -> Option<CommitInfo> {
+        let version = match height {
+            Height::Stable(height) => height,
+            Height::Pending | Height::Latest => self.store.current_height(),
+        };
+        if version == 0 {
+            return None;
+        }
+        let height = Height::Stable(version);
+        let main_hash = match self.layout {
+            StoreLayout::MultiTree => None,
+            StoreLayout::SingleTree => Some(self.store.root_hash_at(height)?),
+        };
+
+        let modules = self.modules.read_access();
+        let store_infos = modules
+            .iter()
+            .map(|IdentifiedModule { id, .. }| {
+                let hash = match &main_hash {
+                    Some(hash) => hash.clone(),
+                    None => self.store.get(height, &id.clone().into())?,
+                };
+                Some(StoreInfo {
+                    name: id.to_string(),
+                    version,
+                    hash,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(CommitInfo {
+            version,
+            store_infos,
+        })
+    }
+
+    /// Returns the proof operations of a query answered by the module `id` with `module_ops`.
+    ///
+    /// In the multi-tree layout, a proof of the module root hash in the main store is appended to
+    /// them. In the single-tree layout, their keys are prefixed with the module identifier, as
+    /// they are proofs in the main store.
+    pub(crate) fn proof_ops(
+        &self,
+        id: &Identifier,
+        height: Height,
+        module_ops: Option<Vec<ProofOp>>,
+    ) -> ProofOps {
+        let mut ops = module_ops.unwrap_or_default();
+        match self.layout {
+            StoreLayout::MultiTree => {
+                let proof = self.store.get_proof(height, &id.clone().into()).unwrap();
+                let mut buffer = Vec::new();
+                proof.encode(&mut buffer).unwrap(); // safety - cannot fail since buf is a vector
+
+                ops.push(ProofOp {
+                    field_type: "".to_string(),
+                    key: id.to_string().into_bytes(),
+                    data: buffer,
+                });
+            }
+            StoreLayout::SingleTree => {
+                for op in ops.iter_mut() {
+                    let mut key = id.to_string().into_bytes();
+                    key.push(b'/');
+                    key.append(&mut op.key);
+                    op.key = key;
+                }
+            }
+        }
+        ProofOps { ops }
+    }
 }
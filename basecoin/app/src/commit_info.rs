@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+use basecoin_store::types::RawHeight;
+use serde_json::{json, Value};
+
+/// ABCI query path of the commit info at the queried height.
+pub const COMMIT_INFO_QUERY_PATH: &str = "/store/commit_info";
+
+/// The commitment of a module store at some height.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StoreInfo {
+    /// Identifier of the module.
+    pub name: String,
+    /// Committed height.
+    pub version: RawHeight,
+    /// Root hash of the module store, which is the root hash of the main store in the
+    /// [`StoreLayout::SingleTree`](crate::StoreLayout::SingleTree) layout.
+    pub hash: Vec<u8>,
+}
+
+/// The commitments of the module stores at some height.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommitInfo {
+    /// Committed height.
+    pub version: RawHeight,
+    pub store_infos: Vec<StoreInfo>,
+}
+
+impl CommitInfo {
+    /// Returns the JSON representation of the commit info, with hex-encoded hashes.
+    pub fn to_json(&self) -> Value {
+        let store_infos: Vec<_> = self
+            .store_infos
+            .iter()
+            .map(|info| {
+                json!({
+                    "name": info.name,
+                    "version": info.version,
+                    "hash": hex(&info.hash),
+                })
+            })
+            .collect();
+        json!({
+            "version": self.version,
+            "store_infos": store_infos,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut acc, b| {
+        // write!-ing into a String can never fail
+        let _ = write!(acc, "{b:02X}");
+        acc
+    })
+}
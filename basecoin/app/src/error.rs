@@ -1,6 +1,37 @@
+/// Failure of a transaction, reported to CometBFT with a non-zero code.
+#[derive(Debug)]
+pub(crate) struct TxError {
+    pub code: u32,
+    pub log: String,
+}
+
+impl TxError {
+    /// Code of the failures of transactions with missing or invalid signatures, which is the
+    /// code of `ErrUnauthorized` in the Cosmos SDK.
+    pub const UNAUTHORIZED: u32 = 4;
+
+    /// Code of the failures of transactions whose signers don't declare the sequences of their
+    /// accounts, which is the code of `ErrWrongSequence` in the Cosmos SDK.
+    pub const WRONG_SEQUENCE: u32 = 32;
+
+    pub fn new(code: u32, log: impl ToString) -> Self {
+        Self {
+            code,
+            log: log.to_string(),
+        }
+    }
+}
+
 #[cfg(any(feature = "v0_37", feature = "v0_38"))]
 pub(crate) trait ResponseFromErrorExt {
     fn from_error(code: u32, log: impl ToString) -> Self;
+
+    fn from_tx_error(error: TxError) -> Self
+    where
+        Self: Sized,
+    {
+        Self::from_error(error.code, error.log)
+    }
 }
 
 #[cfg(any(feature = "v0_37", feature = "v0_38"))]
@@ -27,6 +58,6 @@ const _: () = {
 
 #[cfg(feature = "v0_38")]
 const _: () = {
-    use tendermint_proto::abci::{ResponseCheckTx, ResponseQuery};
-    impl_response_error_for!(ResponseQuery, ResponseCheckTx);
+    use tendermint_proto::abci::{ExecTxResult, ResponseCheckTx, ResponseQuery};
+    impl_response_error_for!(ResponseQuery, ResponseCheckTx, ExecTxResult);
 };
@@ -13,4 +13,8 @@ mod error;
 pub mod service;
 
 mod builder;
-pub use builder::{BaseCoinApp, Builder};
+mod commit_info;
+mod vote_extension;
+pub use builder::{BaseCoinApp, Builder, StoreLayout};
+pub use commit_info::{CommitInfo, StoreInfo, COMMIT_INFO_QUERY_PATH};
+pub use vote_extension::VoteExtension;
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+
+use basecoin_modules::error::Error;
+use basecoin_modules::types::IdentifiedModule;
+use basecoin_store::context::ProvableStore;
+use basecoin_store::utils::SharedRwExt;
+use tendermint::abci::request::{ExtendVote, VerifyVoteExtension};
+use tendermint::abci::types::ExtendedCommitInfo;
+
+use crate::builder::BaseCoinApp;
+
+/// The vote extension of the app, which gathers the vote extensions of the modules by module
+/// identifier. Modules without extension are left out.
+///
+/// It is encoded as its entries in ascending identifier order, with the identifier and the
+/// extension of each entry prefixed with their length as a big-endian `u32`. The encoding is
+/// canonical, so a decoded extension is always encoded back to the same bytes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VoteExtension {
+    pub extensions: BTreeMap<String, Vec<u8>>,
+}
+
+impl VoteExtension {
+    /// Returns the extension of the module `id`, which is empty if it has none.
+    pub fn get(&self, id: &str) -> &[u8] {
+        self.extensions
+            .get(id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        for (id, extension) in self.extensions.iter() {
+            if extension.is_empty() {
+                continue;
+            }
+            encode_bytes(id.as_bytes(), &mut buf);
+            encode_bytes(extension, &mut buf);
+        }
+        buf
+    }
+
+    /// Decodes an extension encoded with [`Self::encode`], or returns `None` if `bytes` isn't a
+    /// canonical encoding.
+    pub fn decode(mut bytes: &[u8]) -> Option<Self> {
+        let mut extensions = BTreeMap::new();
+        while !bytes.is_empty() {
+            let id = String::from_utf8(decode_bytes(&mut bytes)?.to_vec()).ok()?;
+            let extension = decode_bytes(&mut bytes)?.to_vec();
+            let unordered = extensions
+                .last_key_value()
+                .is_some_and(|(last, _)| last >= &id);
+            if extension.is_empty() || unordered {
+                return None;
+            }
+            extensions.insert(id, extension);
+        }
+        Some(Self { extensions })
+    }
+}
+
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    let len = u32::try_from(bytes.len()).expect("vote extension longer than u32::MAX bytes");
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn decode_bytes<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let (len, tail) = bytes.split_at(4);
+    let len = u32::from_be_bytes(len.try_into().expect("slice has length 4")) as usize;
+    if tail.len() < len {
+        return None;
+    }
+    let (head, tail) = tail.split_at(len);
+    *bytes = tail;
+    Some(head)
+}
+
+impl<S: ProvableStore> BaseCoinApp<S> {
+    /// Gathers the extensions of the modules of the precommit vote for the block of `request`.
+    pub fn extend_vote(&self, request: &ExtendVote) -> VoteExtension {
+        let modules = self.modules.read_access();
+        let extensions = modules
+            .iter()
+            .map(|IdentifiedModule { id, module }| (id.to_string(), module.extend_vote(request)))
+            .filter(|(_, extension)| !extension.is_empty())
+            .collect();
+        VoteExtension { extensions }
+    }
+
+    /// Verifies the vote extension of `request`, giving every module its own extension. The
+    /// extension is invalid if it's malformed or has an extension of an unknown module.
+    pub fn verify_vote_extension(&self, request: &VerifyVoteExtension) -> Result<(), Error> {
+        let extension =
+            VoteExtension::decode(&request.vote_extension).ok_or_else(|| Error::Custom {
+                reason: "malformed vote extension".to_owned(),
+            })?;
+
+        let modules = self.modules.read_access();
+        if let Some(id) = extension.extensions.keys().find(|id| {
+            !modules
+                .iter()
+                .any(|module| module.id.as_str() == id.as_str())
+        }) {
+            return Err(Error::Custom {
+                reason: format!("vote extension of unknown module `{id}`"),
+            });
+        }
+
+        for IdentifiedModule { id, module } in modules.iter() {
+            let request = VerifyVoteExtension {
+                vote_extension: extension.get(id).to_vec().into(),
+                ..request.clone()
+            };
+            module.verify_vote_extension(&request)?;
+        }
+        Ok(())
+    }
+
+    /// Hands the extended commit info of the previous block to every module, with their own
+    /// extensions of the votes, and returns the transactions they add to the proposed block, in
+    /// module order.
+    pub fn prepare_proposal(&self, local_last_commit: &ExtendedCommitInfo) -> Vec<Vec<u8>> {
+        let extensions: Vec<_> = local_last_commit
+            .votes
+            .iter()
+            .map(|vote| VoteExtension::decode(&vote.vote_extension).unwrap_or_default())
+            .collect();
+
+        let modules = self.modules.read_access();
+        modules
+            .iter()
+            .flat_map(|IdentifiedModule { id, module }| {
+                let mut commit = local_last_commit.clone();
+                for (vote, extension) in commit.votes.iter_mut().zip(&extensions) {
+                    vote.vote_extension = extension.get(id).to_vec().into();
+                }
+                module.prepare_proposal(&commit)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vote_extension_encoding() {
+        let extension = VoteExtension {
+            extensions: [
+                ("oracle".to_owned(), vec![1, 2]),
+                ("ibc".to_owned(), vec![3]),
+            ]
+            .into(),
+        };
+        let bytes = extension.encode();
+        assert_eq!(VoteExtension::decode(&bytes), Some(extension.clone()));
+        assert_eq!(extension.get("ibc"), [3]);
+        assert!(extension.get("bank").is_empty());
+
+        assert_eq!(VoteExtension::decode(&[]), Some(VoteExtension::default()));
+        assert_eq!(VoteExtension::decode(&bytes[..bytes.len() - 1]), None);
+
+        // entries must be in ascending identifier order and have an extension
+        let mut unordered = vec![];
+        for entry in [&b"oracle"[..], &[1, 2], b"ibc", &[3]] {
+            encode_bytes(entry, &mut unordered);
+        }
+        assert_eq!(VoteExtension::decode(&unordered), None);
+        let mut empty = vec![];
+        encode_bytes(b"bank", &mut empty);
+        encode_bytes(&[], &mut empty);
+        assert_eq!(VoteExtension::decode(&empty), None);
+    }
+}
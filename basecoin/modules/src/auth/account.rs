@@ -1,5 +1,6 @@
 use basecoin_store::types::Path;
-use cosmrs::AccountId;
+use cosmrs::tx::{SignerInfo, SignerPublicKey};
+use cosmrs::{AccountId, Tx};
 use ibc_proto::cosmos::auth::v1beta1::BaseAccount;
 use ibc_proto::google::protobuf::Any;
 use prost::Message;
@@ -7,10 +8,25 @@ use prost::Message;
 use super::context::Account;
 
 /// Address of the account that the relayer uses to sign basecoin transactions.
-/// This is hardcoded as we don't verify signatures currently.
 pub const RELAYER_ACCOUNT: &str = "cosmos12xpmzmfpf7tn57xg93rne2hc2q26lcfql5efws";
 pub const ACCOUNT_PREFIX: &str = "cosmos";
 
+/// Returns the addresses of the signers of `tx`, in the order of its signer infos, or `None` if a
+/// signer doesn't have a single public key.
+pub fn tx_signers(tx: &Tx) -> Option<Vec<AccountId>> {
+    tx.auth_info
+        .signer_infos
+        .iter()
+        .map(|signer_info| match signer_info {
+            SignerInfo {
+                public_key: Some(SignerPublicKey::Single(pubkey)),
+                ..
+            } => pubkey.account_id(ACCOUNT_PREFIX).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
 #[derive(Clone, derive_more::Display)]
 pub struct AccountsPath(pub AccountId);
 
@@ -20,18 +36,24 @@ impl From<AccountsPath> for Path {
     }
 }
 
+/// Path of the number of the next account to be created, which makes account numbers unique.
+#[derive(Clone, derive_more::Display)]
+#[display("nextAccountNumber")]
+pub struct NextAccountNumberPath;
+
 #[derive(Clone)]
 pub struct AuthAccount {
     address: AccountId,
-    number: u64,
+    pub number: u64,
     pub sequence: u64,
 }
 
 impl AuthAccount {
-    pub fn new(address: AccountId) -> Self {
+    /// Creates the account of `address` with the unique account `number`, which signatures commit to.
+    pub fn new(address: AccountId, number: u64) -> Self {
         Self {
             address,
-            number: 0,
+            number,
             sequence: 0,
         }
     }
@@ -0,0 +1,37 @@
+use basecoin_store::types::TypedStoreError;
+use cosmrs::AccountId;
+pub use displaydoc::Display;
+
+pub use crate::error::Error as AppError;
+
+#[derive(Debug, Display)]
+pub enum Error {
+    /// account `{account}` doesn't exist
+    NonExistentAccount { account: AccountId },
+    /// account sequence mismatch, expected {expected}, got {got} for signer `{signer}`
+    SequenceMismatch {
+        signer: AccountId,
+        expected: u64,
+        got: u64,
+    },
+    /// expected `{expected}` signatures, got `{got}`
+    SignatureCountMismatch { expected: usize, got: usize },
+    /// signer `{signer}` doesn't sign with `SIGN_MODE_DIRECT`
+    UnsupportedSignMode { signer: AccountId },
+    /// invalid signature of signer `{signer}`
+    InvalidSignature { signer: AccountId },
+    /// store error: `{0}`
+    Store(TypedStoreError),
+}
+
+impl From<TypedStoreError> for Error {
+    fn from(e: TypedStoreError) -> Self {
+        Self::Store(e)
+    }
+}
+
+impl From<Error> for AppError {
+    fn from(e: Error) -> Self {
+        Self::Auth(e)
+    }
+}
@@ -1,29 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 
 use basecoin_store::context::{ProvableStore, Store};
-use basecoin_store::impls::SharedStore;
-use basecoin_store::types::{Height, ProtobufStore, TypedStore};
+use basecoin_store::impls::{BranchStore, SharedStore};
+use basecoin_store::types::{Height, JsonStore, ProtobufStore, TypedStore, TypedStoreError};
+use cosmrs::tx::SignerInfo;
 use cosmrs::AccountId;
 use ibc_proto::cosmos::auth::v1beta1::query_server::QueryServer;
 use ibc_proto::cosmos::auth::v1beta1::BaseAccount;
-use ibc_proto::google::protobuf::Any;
 use serde_json::Value;
 use tendermint::abci::Event;
 use tracing::{debug, trace};
 
-use crate::auth::account::{AccountsPath, AuthAccount};
+use crate::auth::account::{tx_signers, AccountsPath, AuthAccount, NextAccountNumberPath};
 use crate::auth::context::{Account, AccountKeeper, AccountReader};
+use crate::auth::error::Error;
 use crate::auth::service::AuthService;
+use crate::auth::signature::{sign_doc_bytes, verify_signature};
 use crate::bank::Denom;
 use crate::context::Module;
 use crate::error::Error as AppError;
+use crate::types::SignedTx;
+
+/// Accounts kept in a store of type `T`.
+type Accounts<T> = ProtobufStore<T, AccountsPath, AuthAccount, BaseAccount>;
+
+/// Accounts of the check state, which is a branch of the latest committed state.
+type CheckAccounts<S> = Accounts<BranchStore<SharedStore<S>>>;
 
 #[derive(Clone)]
 pub struct Auth<S> {
     store: SharedStore<S>,
     account_reader: AuthAccountReader<S>,
     account_keeper: AuthAccountKeeper<S>,
+    check_accounts: CheckAccounts<S>,
 }
 
 impl<S: ProvableStore> Auth<S> {
@@ -34,8 +44,10 @@ impl<S: ProvableStore> Auth<S> {
                 account_store: TypedStore::new(store.clone()),
             },
             account_keeper: AuthAccountKeeper {
-                account_store: TypedStore::new(store),
+                account_store: TypedStore::new(store.clone()),
+                account_number_store: TypedStore::new(store.clone()),
             },
+            check_accounts: Self::new_check_accounts(&store),
         }
     }
 
@@ -54,43 +66,132 @@ impl<S: ProvableStore> Auth<S> {
     }
 }
 
+impl<S: Store> Auth<S> {
+    /// Returns the accounts of a new check state, on top of the latest committed state if the
+    /// store supports snapshots, or of its pending state otherwise.
+    fn new_check_accounts(store: &SharedStore<S>) -> CheckAccounts<S> {
+        let committed = store.snapshot().unwrap_or_else(|| store.share());
+        TypedStore::new(BranchStore::new(committed))
+    }
+}
+
+/// Returns the signers of `tx`, with their signer infos and their accounts in `accounts`.
+fn signer_accounts<'a, T: Store>(
+    accounts: &Accounts<T>,
+    tx: &'a SignedTx,
+) -> Result<Vec<(AccountId, &'a SignerInfo, AuthAccount)>, AppError> {
+    let signers = tx_signers(&tx.tx).ok_or_else(|| AppError::Custom {
+        reason: "invalid signer".to_string(),
+    })?;
+    signers
+        .into_iter()
+        .zip(&tx.tx.auth_info.signer_infos)
+        .map(|(signer, signer_info)| -> Result<_, AppError> {
+            let account = accounts
+                .get_existing(Height::Pending, &AccountsPath(signer.clone()))
+                .map_err(|e| match e {
+                    TypedStoreError::NotFound { .. } => Error::NonExistentAccount {
+                        account: signer.clone(),
+                    },
+                    e => e.into(),
+                })?;
+            Ok((signer, signer_info, account))
+        })
+        .collect()
+}
+
+/// Checks that every signer of `tx` declares the sequence of its account in `accounts`, so that
+/// a transaction can't be executed more than once.
+fn check_sequences<T: Store>(accounts: &Accounts<T>, tx: &SignedTx) -> Result<(), AppError> {
+    for (signer, signer_info, account) in signer_accounts(accounts, tx)? {
+        if account.sequence != signer_info.sequence {
+            return Err(Error::SequenceMismatch {
+                signer,
+                expected: account.sequence,
+                got: signer_info.sequence,
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Verifies the signatures of all the signers of `tx`, against their accounts in `accounts`.
+fn verify_signatures<T: Store>(accounts: &Accounts<T>, tx: &SignedTx) -> Result<(), AppError> {
+    let signer_accounts = signer_accounts(accounts, tx)?;
+    if tx.tx.signatures.len() != signer_accounts.len() {
+        return Err(Error::SignatureCountMismatch {
+            expected: signer_accounts.len(),
+            got: tx.tx.signatures.len(),
+        }
+        .into());
+    }
+
+    for ((signer, signer_info, account), signature) in
+        signer_accounts.into_iter().zip(&tx.tx.signatures)
+    {
+        let sign_doc = sign_doc_bytes(tx, account.number);
+        verify_signature(&signer, signer_info, &sign_doc, signature)?;
+    }
+    Ok(())
+}
+
+/// Increments the sequences of the accounts of all the signers of `tx` in `accounts`, once per
+/// transaction.
+fn increment_sequences<T: Store>(
+    accounts: &mut Accounts<T>,
+    tx: &SignedTx,
+) -> Result<(), AppError> {
+    for (signer, _, mut account) in signer_accounts(accounts, tx)? {
+        account.sequence += 1;
+        accounts.set(AccountsPath(signer), account)?;
+    }
+    Ok(())
+}
+
 impl<S: Store> Module for Auth<S> {
     type Store = S;
 
     fn init(&mut self, app_state: Value) {
         debug!("Initializing auth module");
         // safety - we panic on errors to prevent chain creation with invalid genesis config
-        let accounts: HashMap<String, HashMap<Denom, String>> =
+        // the accounts are numbered in the order of their addresses, so that all nodes agree
+        let accounts: BTreeMap<String, HashMap<Denom, String>> =
             serde_json::from_value(app_state).unwrap();
 
-        for (account, _) in accounts {
+        for account in accounts.into_keys() {
             trace!("Adding account: {}", account);
 
             let account_id = AccountId::from_str(&account).unwrap();
             self.account_keeper
-                .set_account(AuthAccount::new(account_id.clone()))
+                .create_account(account_id)
                 .map_err(|_| "Failed to create account")
                 .unwrap();
         }
     }
 
-    fn deliver(&mut self, _message: Any, signer: &AccountId) -> Result<Vec<Event>, AppError> {
-        let mut account = self
-            .account_reader
-            .get_account(signer.clone())
-            .map_err(|_| AppError::Custom {
-                reason: "unknown signer".to_string(),
-            })?;
-        account.sequence += 1;
+    fn check_tx(&self, tx: &SignedTx, recheck: bool) -> Result<(), AppError> {
+        check_sequences(&self.check_accounts, tx)?;
+        // the signatures of a rechecked tx were verified when it entered the mempool
+        if !recheck {
+            verify_signatures(&self.check_accounts, tx)?;
+        }
+        Ok(())
+    }
+
+    fn on_checked_tx(&mut self, tx: &SignedTx) {
+        increment_sequences(&mut self.check_accounts, tx).expect("failed to update check state");
+    }
 
-        self.account_keeper
-            .set_account(account)
-            .map_err(|_| AppError::Custom {
-                reason: "failed to increment signer sequence".to_string(),
-            })?;
+    fn deliver_tx(&mut self, tx: &SignedTx) -> Result<Vec<Event>, AppError> {
+        check_sequences(&self.account_reader.account_store, tx)?;
+        verify_signatures(&self.account_reader.account_store, tx)?;
+        increment_sequences(&mut self.account_keeper.account_store, tx)?;
+        Ok(vec![])
+    }
 
-        // we're only intercepting the deliverTx here, so return unhandled.
-        Err(AppError::NotHandled)
+    fn reset_check_state(&mut self) {
+        self.check_accounts = Self::new_check_accounts(&self.store);
     }
 
     fn store_mut(&mut self) -> &mut SharedStore<S> {
@@ -108,31 +209,46 @@ pub struct AuthAccountReader<S> {
 }
 
 impl<S: Store> AccountReader for AuthAccountReader<S> {
-    type Error = ();
+    type Error = TypedStoreError;
     type Address = AccountId;
     type Account = AuthAccount;
 
     fn get_account(&self, address: Self::Address) -> Result<Self::Account, Self::Error> {
         self.account_store
-            .get(Height::Pending, &AccountsPath(address))
-            .ok_or(())
+            .get_existing(Height::Pending, &AccountsPath(address))
     }
 }
 
 #[derive(Clone)]
 pub struct AuthAccountKeeper<S> {
     account_store: ProtobufStore<SharedStore<S>, AccountsPath, AuthAccount, BaseAccount>,
+    account_number_store: JsonStore<SharedStore<S>, NextAccountNumberPath, u64>,
+}
+
+impl<S: Store> AuthAccountKeeper<S> {
+    /// Creates and stores the account of `address`, with the next account number.
+    pub fn create_account(&mut self, address: AccountId) -> Result<AuthAccount, TypedStoreError> {
+        let number = self
+            .account_number_store
+            .get(Height::Pending, &NextAccountNumberPath)?
+            .unwrap_or_default();
+        self.account_number_store
+            .set(NextAccountNumberPath, number + 1)?;
+
+        let account = AuthAccount::new(address, number);
+        self.set_account(account.clone())?;
+        Ok(account)
+    }
 }
 
 impl<S: Store> AccountKeeper for AuthAccountKeeper<S> {
-    type Error = ();
+    type Error = TypedStoreError;
     type Account = AuthAccount;
 
     fn set_account(&mut self, account: Self::Account) -> Result<(), Self::Error> {
         self.account_store
             .set(AccountsPath(account.address().clone()), account)
             .map(|_| ())
-            .map_err(|_| ())
     }
 
     fn remove_account(&mut self, _account: Self::Account) -> Result<(), Self::Error> {
@@ -1,12 +1,16 @@
 mod account;
 mod context;
+mod error;
 mod impls;
 mod service;
+mod signature;
 
 pub use account::*;
 pub use context::*;
+pub use error::*;
 pub use impls::*;
 pub use service::*;
+pub use signature::*;
 
 /// Re-exports `auth` module proto types for convenience.
 pub mod proto {
@@ -1,4 +1,5 @@
 use basecoin_store::context::ProvableStore;
+use basecoin_store::types::TypedStoreError;
 use ibc_proto::cosmos::auth::v1beta1::query_server::Query;
 use ibc_proto::cosmos::auth::v1beta1::{
     AddressBytesToStringRequest, AddressBytesToStringResponse, AddressStringToBytesRequest,
@@ -13,7 +14,6 @@ use tonic::{Request, Response, Status};
 use tracing::debug;
 
 use super::impls::AuthAccountReader;
-use crate::auth::account::RELAYER_ACCOUNT;
 use crate::auth::context::AccountReader;
 
 pub struct AuthService<S> {
@@ -31,12 +31,22 @@ impl<S: ProvableStore> Query for AuthService<S> {
 
     async fn account(
         &self,
-        _request: Request<QueryAccountRequest>,
+        request: Request<QueryAccountRequest>,
     ) -> Result<Response<QueryAccountResponse>, Status> {
         debug!("Got auth account request");
 
-        let account_id = RELAYER_ACCOUNT.parse().unwrap();
-        let account = self.account_reader.get_account(account_id).unwrap();
+        let account_id = request
+            .into_inner()
+            .address
+            .parse()
+            .map_err(|_| Status::invalid_argument("invalid account address"))?;
+        let account = self
+            .account_reader
+            .get_account(account_id)
+            .map_err(|e| match e {
+                TypedStoreError::NotFound { .. } => Status::not_found(e.to_string()),
+                _ => Status::internal(e.to_string()),
+            })?;
 
         Ok(Response::new(QueryAccountResponse {
             account: Some(account.into()),
@@ -0,0 +1,56 @@
+use cosmrs::proto::cosmos::tx::signing::v1beta1::SignMode;
+use cosmrs::proto::cosmos::tx::v1beta1::{mode_info, ModeInfo, SignDoc};
+use cosmrs::tx::{SignerInfo, SignerPublicKey};
+use cosmrs::AccountId;
+use prost::Message;
+use tendermint::crypto::default::signature::Verifier;
+use tendermint::crypto::signature::Verifier as _;
+use tendermint::{PublicKey, Signature};
+
+use crate::auth::error::Error;
+use crate::types::SignedTx;
+
+/// Returns the encoded `SIGN_MODE_DIRECT` sign doc of `tx` for the signer whose account number is
+/// `account_number`.
+pub fn sign_doc_bytes(tx: &SignedTx, account_number: u64) -> Vec<u8> {
+    SignDoc {
+        body_bytes: tx.body_bytes.clone(),
+        auth_info_bytes: tx.auth_info_bytes.clone(),
+        chain_id: tx.chain_id.clone(),
+        account_number,
+    }
+    .encode_to_vec()
+}
+
+/// Verifies that `signature` is the `SIGN_MODE_DIRECT` signature of `sign_doc` by the single key
+/// of `signer_info`, which belongs to `signer`.
+pub fn verify_signature(
+    signer: &AccountId,
+    signer_info: &SignerInfo,
+    sign_doc: &[u8],
+    signature: &[u8],
+) -> Result<(), Error> {
+    let direct = ModeInfo {
+        sum: Some(mode_info::Sum::Single(mode_info::Single {
+            mode: SignMode::Direct.into(),
+        })),
+    };
+    if ModeInfo::from(signer_info.mode_info.clone()) != direct {
+        return Err(Error::UnsupportedSignMode {
+            signer: signer.clone(),
+        });
+    }
+
+    let invalid_signature = || Error::InvalidSignature {
+        signer: signer.clone(),
+    };
+    let Some(SignerPublicKey::Single(public_key)) = &signer_info.public_key else {
+        return Err(invalid_signature());
+    };
+    let signature = match Signature::new(signature) {
+        Ok(Some(signature)) => signature,
+        _ => return Err(invalid_signature()),
+    };
+    Verifier::verify(PublicKey::from(*public_key), sign_doc, &signature)
+        .map_err(|_| invalid_signature())
+}
@@ -4,14 +4,19 @@ use std::str::FromStr;
 use basecoin_store::types::Height;
 
 pub trait BankReader {
+    type Error: Debug;
     type Address;
     type Denom;
     type Coin;
     type Coins: IntoIterator<Item = Self::Coin>;
 
-    fn get_all_balances_at_height(&self, height: Height, address: Self::Address) -> Self::Coins;
+    fn get_all_balances_at_height(
+        &self,
+        height: Height,
+        address: Self::Address,
+    ) -> Result<Self::Coins, Self::Error>;
 
-    fn get_all_balances(&self, address: Self::Address) -> Self::Coins {
+    fn get_all_balances(&self, address: Self::Address) -> Result<Self::Coins, Self::Error> {
         self.get_all_balances_at_height(Height::Pending, address)
     }
 }
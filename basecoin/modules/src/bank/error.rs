@@ -1,3 +1,4 @@
+use basecoin_store::types::TypedStoreError;
 use cosmrs::AccountId;
 pub use displaydoc::Display;
 
@@ -11,12 +12,24 @@ pub enum Error {
     MsgValidationFailure { reason: String },
     /// account `{account}` doesn't exist
     NonExistentAccount { account: AccountId },
+    /// sender `{sender}` didn't sign the transaction
+    UnsignedSender { sender: AccountId },
     /// insufficient funds in sender account
     InsufficientSourceFunds,
+    /// insufficient funds to pay the fee
+    InsufficientFeeFunds,
+    /// fee payer `{payer}` didn't sign the transaction
+    UnsignedFeePayer { payer: AccountId },
     /// receiver account funds overflow
     DestFundOverflow,
-    /// Store error: `{reason}`
-    Store { reason: String },
+    /// store error: `{0}`
+    Store(TypedStoreError),
+}
+
+impl From<TypedStoreError> for Error {
+    fn from(e: TypedStoreError) -> Self {
+        Self::Store(e)
+    }
 }
 
 impl From<Error> for AppError {
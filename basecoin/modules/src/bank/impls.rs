@@ -3,7 +3,7 @@ use std::fmt::Debug;
 use std::str::FromStr;
 
 use basecoin_store::context::{ProvableStore, Store};
-use basecoin_store::impls::SharedStore;
+use basecoin_store::impls::{BranchStore, SharedStore};
 use basecoin_store::types::{Height, JsonStore, Path, TypedStore};
 use basecoin_store::utils::{Async, Codec, JsonCodec};
 use cosmrs::bank::MsgSend;
@@ -19,10 +19,10 @@ use super::context::{BankKeeper, BankReader};
 use super::error::Error;
 use super::service::BankService;
 use super::util::{Balances, BalancesPath, Coin, Denom};
-use crate::auth::{AccountKeeper, AccountReader, AuthAccount, ACCOUNT_PREFIX};
+use crate::auth::{tx_signers, AccountKeeper, AccountReader, AuthAccount, ACCOUNT_PREFIX};
 use crate::context::Module;
 pub use crate::error::Error as AppError;
-use crate::types::QueryResult;
+use crate::types::{QueryResult, SignedTx};
 
 #[derive(Clone)]
 pub struct BankBalanceReader<S> {
@@ -30,16 +30,22 @@ pub struct BankBalanceReader<S> {
 }
 
 impl<S: Store> BankReader for BankBalanceReader<S> {
+    type Error = Error;
     type Address = AccountId;
     type Denom = Denom;
     type Coin = Coin;
     type Coins = Vec<Coin>;
 
-    fn get_all_balances_at_height(&self, height: Height, address: Self::Address) -> Self::Coins {
-        self.balance_store
-            .get(height, &BalancesPath(address))
+    fn get_all_balances_at_height(
+        &self,
+        height: Height,
+        address: Self::Address,
+    ) -> Result<Self::Coins, Self::Error> {
+        Ok(self
+            .balance_store
+            .get(height, &BalancesPath(address))?
             .map(|b| b.0)
-            .unwrap_or_default()
+            .unwrap_or_default())
     }
 }
 
@@ -63,14 +69,14 @@ impl<S: Store> BankKeeper for BankBalanceKeeper<S> {
         let src_balance_path = BalancesPath(from);
         let mut src_balances = self
             .balance_store
-            .get(Height::Pending, &src_balance_path)
+            .get(Height::Pending, &src_balance_path)?
             .map(|b| b.0)
             .unwrap_or_default();
 
         let dst_balance_path = BalancesPath(to);
         let mut dst_balances = self
             .balance_store
-            .get(Height::Pending, &dst_balance_path)
+            .get(Height::Pending, &dst_balance_path)?
             .map(|b| b.0)
             .unwrap_or_default();
 
@@ -99,17 +105,9 @@ impl<S: Store> BankKeeper for BankBalanceKeeper<S> {
 
         // Store the updated account balances
         self.balance_store
-            .set(src_balance_path, Balances(src_balances))
-            .map(|_| ())
-            .map_err(|e| Error::Store {
-                reason: format!("{e:?}"),
-            })?;
+            .set(src_balance_path, Balances(src_balances))?;
         self.balance_store
-            .set(dst_balance_path, Balances(dst_balances))
-            .map(|_| ())
-            .map_err(|e| Error::Store {
-                reason: format!("{e:?}"),
-            })?;
+            .set(dst_balance_path, Balances(dst_balances))?;
 
         Ok(())
     }
@@ -122,7 +120,7 @@ impl<S: Store> BankKeeper for BankBalanceKeeper<S> {
         let balance_path = BalancesPath(account);
         let mut balances = self
             .balance_store
-            .get(Height::Pending, &balance_path)
+            .get(Height::Pending, &balance_path)?
             .map(|b| b.0)
             .unwrap_or_default();
 
@@ -145,12 +143,7 @@ impl<S: Store> BankKeeper for BankBalanceKeeper<S> {
         }
 
         // Store the updated account balances
-        self.balance_store
-            .set(balance_path, Balances(balances))
-            .map(|_| ())
-            .map_err(|e| Error::Store {
-                reason: format!("{e:?}"),
-            })?;
+        self.balance_store.set(balance_path, Balances(balances))?;
 
         Ok(())
     }
@@ -163,7 +156,7 @@ impl<S: Store> BankKeeper for BankBalanceKeeper<S> {
         let balance_path = BalancesPath(account);
         let mut balances = self
             .balance_store
-            .get(Height::Pending, &balance_path)
+            .get(Height::Pending, &balance_path)?
             .map(|b| b.0)
             .unwrap_or_default();
 
@@ -178,17 +171,15 @@ impl<S: Store> BankKeeper for BankBalanceKeeper<S> {
         }
 
         // Store the updated account balances
-        self.balance_store
-            .set(balance_path, Balances(balances))
-            .map(|_| ())
-            .map_err(|e| Error::Store {
-                reason: format!("{e:?}"),
-            })?;
+        self.balance_store.set(balance_path, Balances(balances))?;
 
         Ok(())
     }
 }
 
+/// Balances of the check state, which is a branch of the latest committed state.
+type CheckBalances<S> = JsonStore<BranchStore<SharedStore<S>>, BalancesPath, Balances>;
+
 /// The bank module
 #[derive(Clone)]
 pub struct Bank<S, AR, AK> {
@@ -200,6 +191,7 @@ pub struct Bank<S, AR, AK> {
     account_reader: AR,
     #[allow(dead_code)]
     account_keeper: AK,
+    check_balances: CheckBalances<S>,
 }
 
 impl<S, AR, AK> Bank<S, AR, AK>
@@ -215,10 +207,11 @@ where
                 balance_store: TypedStore::new(store.clone()),
             },
             balance_keeper: BankBalanceKeeper {
-                balance_store: TypedStore::new(store),
+                balance_store: TypedStore::new(store.clone()),
             },
             account_reader,
             account_keeper,
+            check_balances: Self::new_check_balances(&store),
         }
     }
 
@@ -249,6 +242,48 @@ where
         }
         Message::decode(message.value.as_ref()).map_err(|_| Error::MsgDecodeFailure.into())
     }
+
+    /// Returns the balances of a new check state, on top of the latest committed state if the
+    /// store supports snapshots, or of its pending state otherwise.
+    fn new_check_balances(store: &SharedStore<S>) -> CheckBalances<S> {
+        let committed = store.snapshot().unwrap_or_else(|| store.share());
+        TypedStore::new(BranchStore::new(committed))
+    }
+}
+
+/// Returns the fee payer of `tx`, which is the first signer unless set, with its balances in
+/// `balances` once the fee is paid, or `None` if `tx` has no payer. The payer must be one of the
+/// signers of `tx`.
+fn pay_fee<T: Store>(
+    balances: &JsonStore<T, BalancesPath, Balances>,
+    tx: &SignedTx,
+) -> Result<Option<(AccountId, Vec<Coin>)>, Error> {
+    let tx = &tx.tx;
+    let fee = &tx.auth_info.fee;
+    let signers = tx_signers(tx).unwrap_or_default();
+    let Some(payer) = fee.payer.clone().or_else(|| signers.first().cloned()) else {
+        return Ok(None);
+    };
+    if !signers.contains(&payer) {
+        return Err(Error::UnsignedFeePayer { payer });
+    }
+
+    let mut payer_balances = balances
+        .get(Height::Pending, &BalancesPath(payer.clone()))?
+        .map(|b| b.0)
+        .unwrap_or_default();
+    for Coin { denom, amount } in fee.amount.iter().map(Coin::from) {
+        if amount.is_zero() {
+            continue;
+        }
+        let balance = payer_balances
+            .iter_mut()
+            .find(|c| c.denom == denom)
+            .filter(|c| c.amount >= amount)
+            .ok_or(Error::InsufficientFeeFunds)?;
+        balance.amount -= amount;
+    }
+    Ok(Some((payer, payer_balances)))
 }
 
 impl<S, AR, AK> Module for Bank<S, AR, AK>
@@ -261,12 +296,19 @@ where
 {
     type Store = S;
 
-    fn deliver(&mut self, message: Any, _signer: &AccountId) -> Result<Vec<Event>, AppError> {
+    fn deliver(&mut self, message: Any, signer: &AccountId) -> Result<Vec<Event>, AppError> {
         let message: MsgSend = Self::decode::<proto::cosmos::bank::v1beta1::MsgSend>(message)?
             .try_into()
             .map_err(|e| Error::MsgValidationFailure {
                 reason: format!("{e:?}"),
             })?;
+        // only the owner of the coins can send them
+        if message.from_address != *signer {
+            return Err(Error::UnsignedSender {
+                sender: message.from_address,
+            }
+            .into());
+        }
         self.account_reader
             .get_account(message.from_address.clone().into())
             .map_err(|_| Error::NonExistentAccount {
@@ -323,10 +365,14 @@ where
 
         let balance = self
             .balance_reader
-            .get_all_balances_at_height(height, account_id);
+            .get_all_balances_at_height(height, account_id)?;
 
         Ok(QueryResult {
-            data: JsonCodec::encode(&balance).unwrap().into_bytes(),
+            data: JsonCodec::encode(&balance)
+                .map_err(|e| AppError::Custom {
+                    reason: e.to_string(),
+                })?
+                .into_bytes(),
             proof: None,
         })
     }
@@ -343,6 +389,41 @@ where
         Ok(())
     }
 
+    /// Checks that the fee payer, which is the first signer unless set, signed the transaction and
+    /// can pay the fee.
+    fn check_tx(&self, tx: &SignedTx, _recheck: bool) -> Result<(), AppError> {
+        pay_fee(&self.check_balances, tx)?;
+        Ok(())
+    }
+
+    /// Debits the fee from the balances of the fee payer in the check state, so that the fees of
+    /// the transactions of the mempool are all funded.
+    fn on_checked_tx(&mut self, tx: &SignedTx) {
+        if let Some((payer, balances)) =
+            pay_fee(&self.check_balances, tx).expect("failed to update check state")
+        {
+            self.check_balances
+                .set(BalancesPath(payer), Balances(balances))
+                .expect("failed to update check state");
+        }
+    }
+
+    fn reset_check_state(&mut self) {
+        self.check_balances = Self::new_check_balances(&self.store);
+    }
+
+    /// Debits the fee from the balances of the fee payer, which stays paid even if a message of
+    /// the transaction fails.
+    fn deliver_tx(&mut self, tx: &SignedTx) -> Result<Vec<Event>, AppError> {
+        if let Some((payer, balances)) = pay_fee(&self.balance_keeper.balance_store, tx)? {
+            self.balance_keeper
+                .balance_store
+                .set(BalancesPath(payer), Balances(balances))
+                .map_err(Error::from)?;
+        }
+        Ok(vec![])
+    }
+
     fn begin_block(&mut self, _header: &tendermint::block::Header) -> Vec<Event> {
         vec![]
     }
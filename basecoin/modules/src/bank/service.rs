@@ -37,7 +37,10 @@ impl<S: ProvableStore> Query for BankService<S> {
             .parse()
             .map_err(|e| Status::invalid_argument(format!("{e}")))?;
         let denom = Denom(request.get_ref().denom.clone());
-        let balances = self.bank_reader.get_all_balances(account_id);
+        let balances = self
+            .bank_reader
+            .get_all_balances(account_id)
+            .map_err(|e| Status::internal(e.to_string()))?;
 
         Ok(Response::new(QueryBalanceResponse {
             balance: balances
@@ -4,11 +4,14 @@ use basecoin_store::impls::SharedStore;
 use basecoin_store::types::{Height, Identifier as StoreIdentifier, Path};
 use cosmrs::AccountId;
 use ibc_proto::google::protobuf::Any;
+use tendermint::abci::request::{ExtendVote, VerifyVoteExtension};
+use tendermint::abci::response::EndBlock;
+use tendermint::abci::types::ExtendedCommitInfo;
 use tendermint::abci::Event;
-use tendermint::block::Header;
+use tendermint::block::{Header, Height as BlockHeight};
 
 use crate::error::Error;
-use crate::types::QueryResult;
+use crate::types::{QueryResult, SignedTx};
 
 pub trait Module: Send + Sync + AsAny {
     /// The module's store type.
@@ -22,6 +25,33 @@ pub trait Module: Send + Sync + AsAny {
         Ok(())
     }
 
+    /// Checks a whole transaction in CheckTx, after the [`check`](Self::check) of each of its
+    /// messages passed, e.g. its signatures, sequences and fee.
+    ///
+    /// The transaction is checked against the module's check state, which is its latest committed
+    /// state with the changes of the transactions checked since, see
+    /// [`on_checked_tx`](Self::on_checked_tx). `recheck` is set when a transaction of the mempool
+    /// is checked again after a commit.
+    fn check_tx(&self, _tx: &SignedTx, _recheck: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Updates the module's check state with a transaction that passed the checks of all the
+    /// modules, e.g. increments the sequences of its signers.
+    fn on_checked_tx(&mut self, _tx: &SignedTx) {}
+
+    /// Resets the module's check state to its latest committed state, after every commit.
+    fn reset_check_state(&mut self) {}
+
+    /// Processes a whole transaction in DeliverTx before any of its messages is
+    /// [delivered](Self::deliver), e.g. verifies its signatures, and returns the resulting events.
+    /// The transaction fails without executing its messages if any module returns an error,
+    /// otherwise the changes of the hooks are kept even if one of its messages fails.
+    /// *NOTE* - Implementations MUST be deterministic!
+    fn deliver_tx(&mut self, _tx: &SignedTx) -> Result<Vec<Event>, Error> {
+        Ok(vec![])
+    }
+
     /// Execute specified `Message`, modify state accordingly and return resulting `Events`
     /// Similar to [ABCI DeliverTx method](https://docs.tendermint.com/master/spec/abci/abci.html#delivertx)
     /// *NOTE* - Implementations MUST be deterministic!
@@ -63,6 +93,49 @@ pub trait Module: Send + Sync + AsAny {
         vec![]
     }
 
+    /// Similar to [ABCI EndBlock method](https://docs.tendermint.com/master/spec/abci/abci.html#endblock)
+    /// With CometBFT 0.38, the hook is run at the end of `FinalizeBlock`, after the transactions
+    /// of the block.
+    /// *NOTE* - Implementations MUST be deterministic!
+    ///
+    /// ## Return
+    /// * Updates of the validator set and of the consensus parameters, and resulting events if any
+    fn end_block(&mut self, _height: BlockHeight) -> EndBlock {
+        EndBlock::default()
+    }
+
+    /// Similar to [ABCI ExtendVote method](https://docs.cometbft.com/v0.38/spec/abci/abci++_methods#extendvote)
+    /// The extensions of all the modules are gathered into the vote extension of the app, which
+    /// is only supported by CometBFT 0.38 and later. Implementations MUST NOT modify the state.
+    ///
+    /// ## Return
+    /// * The module's extension of the precommit vote, or an empty one if it has none
+    fn extend_vote(&self, _request: &ExtendVote) -> Vec<u8> {
+        vec![]
+    }
+
+    /// Similar to [ABCI VerifyVoteExtension method](https://docs.cometbft.com/v0.38/spec/abci/abci++_methods#verifyvoteextension)
+    /// The `vote_extension` of the `request` is the module's own extension of the vote, which is
+    /// empty if it has none.
+    /// *NOTE* - Implementations MUST be deterministic!
+    ///
+    /// ## Return
+    /// * An error if the extension is invalid, which rejects the whole vote
+    fn verify_vote_extension(&self, _request: &VerifyVoteExtension) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Receives the extended commit info of the previous block when this node prepares a block
+    /// proposal, see the [ABCI PrepareProposal method](https://docs.cometbft.com/v0.38/spec/abci/abci++_methods#prepareproposal).
+    /// The `vote_extension` of each vote is the module's own extension of the vote, which is empty
+    /// if it has none or if the vote extension of the app is malformed.
+    ///
+    /// ## Return
+    /// * Transactions to include at the start of the proposed block, if any
+    fn prepare_proposal(&self, _local_last_commit: &ExtendedCommitInfo) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
     /// Return a mutable reference to the module's store
     fn store_mut(&mut self) -> &mut SharedStore<Self::Store>;
 
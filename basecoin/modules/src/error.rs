@@ -1,5 +1,7 @@
+use basecoin_store::types::TypedStoreError;
 use displaydoc::Display;
 
+use crate::auth::Error as AuthError;
 use crate::bank::Error as BankError;
 use crate::gov::Error as GovError;
 use crate::ibc::Error as IbcError;
@@ -10,10 +12,20 @@ pub enum Error {
     NotHandled,
     /// custom error: `{reason}`
     Custom { reason: String },
+    /// auth module error: `{0}`
+    Auth(AuthError),
     /// bank module error: `{0}`
     Bank(BankError),
     /// IBC module error: `{0}`
     Ibc(IbcError),
     /// Governance module error: `{0}`
     Gov(GovError),
+    /// store error: `{0}`
+    Store(TypedStoreError),
+}
+
+impl From<TypedStoreError> for Error {
+    fn from(e: TypedStoreError) -> Self {
+        Self::Store(e)
+    }
 }
@@ -89,9 +89,7 @@ where
 
                     let proposal = message.proposal(self.proposal_counter);
 
-                    self.proposal
-                        .set(ProposalPath::sdk_path(), proposal)
-                        .unwrap();
+                    self.proposal.set(ProposalPath::sdk_path(), proposal)?;
 
                     self.proposal_counter += 1;
 
@@ -1,7 +1,8 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::Bound;
 
 use basecoin_store::context::Store;
-use basecoin_store::types::Height;
+use basecoin_store::types::{Height, Path as StorePath};
 use ibc::core::client::context::{
     ClientExecutionContext, ClientValidationContext, ExtClientValidationContext,
 };
@@ -9,14 +10,152 @@ use ibc::core::client::types::Height as IbcHeight;
 use ibc::core::host::types::error::HostError;
 use ibc::core::host::types::identifiers::ClientId;
 use ibc::core::host::types::path::{
-    ClientConsensusStatePath, ClientStatePath, ClientUpdateHeightPath, ClientUpdateTimePath, Path,
+    ClientConsensusStatePath, ClientStatePath, ClientUpdateHeightPath, ClientUpdateTimePath,
 };
 use ibc::core::host::ValidationContext;
 use ibc::primitives::Timestamp;
 
+use super::error::store_error;
 use super::impls::{AnyConsensusState, IbcContext};
 use super::AnyClientState;
 
+/// An entry of the index of the heights at which a client has a consensus state.
+///
+/// [`ClientConsensusStatePath`]s can't be used to look up neighbouring consensus states, as their
+/// heights sort in lexicographic order (e.g. `1-10` before `1-9`). The heights of this index are
+/// zero-padded so that its entries sort in numerical order of heights instead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ConsensusHeightPath {
+    pub client_id: ClientId,
+    pub revision_number: u64,
+    pub revision_height: u64,
+}
+
+impl ConsensusHeightPath {
+    pub fn new(client_id: &ClientId, height: &IbcHeight) -> Self {
+        Self {
+            client_id: client_id.clone(),
+            revision_number: height.revision_number(),
+            revision_height: height.revision_height(),
+        }
+    }
+
+    fn prefix(client_id: &ClientId) -> String {
+        format!("clients/{client_id}/consensusHeights")
+    }
+
+    /// Parses the height of `path`, if it is an index entry of the specified client.
+    fn parse_height(client_id: &ClientId, path: &StorePath) -> Option<IbcHeight> {
+        let path = path.to_string();
+        let height = path
+            .strip_prefix(&Self::prefix(client_id))?
+            .strip_prefix('/')?;
+        let (revision_number, revision_height) = height.split_once('-')?;
+        IbcHeight::new(revision_number.parse().ok()?, revision_height.parse().ok()?).ok()
+    }
+}
+
+impl From<&ClientConsensusStatePath> for ConsensusHeightPath {
+    fn from(path: &ClientConsensusStatePath) -> Self {
+        Self {
+            client_id: path.client_id.clone(),
+            revision_number: path.revision_number,
+            revision_height: path.revision_height,
+        }
+    }
+}
+
+impl Display for ConsensusHeightPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{:020}-{:020}",
+            Self::prefix(&self.client_id),
+            self.revision_number,
+            self.revision_height
+        )
+    }
+}
+
+impl<S> IbcContext<S>
+where
+    S: Store + Debug,
+{
+    /// Returns the heights at which the client has a consensus state, in ascending order.
+    fn consensus_heights(
+        &self,
+        client_id: &ClientId,
+    ) -> impl Iterator<Item = Result<IbcHeight, HostError>> + '_ {
+        let client_id = client_id.clone();
+        self.consensus_height_index
+            .iter_prefix_at(
+                Height::Pending,
+                &ConsensusHeightPath::prefix(&client_id).into(),
+            )
+            .filter_map(move |entry| match entry {
+                Ok((path, _)) => ConsensusHeightPath::parse_height(&client_id, &path).map(Ok),
+                Err(e) => Some(Err(store_error(e))),
+            })
+    }
+
+    /// Returns the lowest height greater than `height` at which the client has a consensus
+    /// state.
+    pub(crate) fn next_consensus_height(
+        &self,
+        client_id: &ClientId,
+        height: &IbcHeight,
+    ) -> Result<Option<IbcHeight>, HostError> {
+        let from: StorePath = ConsensusHeightPath::new(client_id, height)
+            .to_string()
+            .into();
+        let Some(entry) = self
+            .consensus_height_index
+            .range_at(Height::Pending, (Bound::Excluded(from), Bound::Unbounded))
+            .next()
+        else {
+            return Ok(None);
+        };
+        let (path, _) = entry.map_err(store_error)?;
+        Ok(ConsensusHeightPath::parse_height(client_id, &path))
+    }
+
+    /// Returns the greatest height lower than `height` at which the client has a consensus
+    /// state.
+    pub(crate) fn prev_consensus_height(
+        &self,
+        client_id: &ClientId,
+        height: &IbcHeight,
+    ) -> Result<Option<IbcHeight>, HostError> {
+        let to: StorePath = ConsensusHeightPath::new(client_id, height)
+            .to_string()
+            .into();
+        let Some(entry) = self
+            .consensus_height_index
+            .range_at(Height::Pending, (Bound::Unbounded, Bound::Excluded(to)))
+            .next_back()
+        else {
+            return Ok(None);
+        };
+        let (path, _) = entry.map_err(store_error)?;
+        Ok(ConsensusHeightPath::parse_height(client_id, &path))
+    }
+
+    fn consensus_state_at(
+        &self,
+        client_id: &ClientId,
+        height: &IbcHeight,
+    ) -> Result<AnyConsensusState, HostError> {
+        ClientValidationContext::consensus_state(
+            self,
+            &ClientConsensusStatePath::new(
+                client_id.clone(),
+                height.revision_number(),
+                height.revision_height(),
+            ),
+        )
+    }
+}
+
 impl<S> ClientValidationContext for IbcContext<S>
 where
     S: Store + Debug,
@@ -27,6 +166,7 @@ where
     fn client_state(&self, client_id: &ClientId) -> Result<Self::ClientStateRef, HostError> {
         self.client_state_store
             .get(Height::Pending, &ClientStatePath(client_id.clone()))
+            .map_err(store_error)?
             .ok_or_else(|| {
                 HostError::missing_state(format!("client state for client_id {client_id}"))
             })
@@ -44,6 +184,7 @@ where
         let consensus_state = self
             .consensus_state_store
             .get(Height::Pending, client_cons_state_path)
+            .map_err(store_error)?
             .ok_or_else(|| {
                 HostError::missing_state(format!(
                     "consensus state for client_id {} at height {height}",
@@ -69,6 +210,7 @@ where
         let processed_timestamp = self
             .client_processed_times
             .get(Height::Pending, &client_update_time_path)
+            .map_err(store_error)?
             .ok_or_else(|| {
                 HostError::missing_state(format!(
                     "update time for client_id {client_id} at height {height}"
@@ -82,6 +224,7 @@ where
         let processed_height = self
             .client_processed_heights
             .get(Height::Pending, &client_update_height_path)
+            .map_err(store_error)?
             .ok_or_else(|| {
                 HostError::missing_state(format!(
                     "update height for client_id {client_id} at height {height}"
@@ -116,6 +259,9 @@ where
         consensus_state_path: ClientConsensusStatePath,
         consensus_state: Self::ConsensusStateRef,
     ) -> Result<(), HostError> {
+        self.consensus_height_index
+            .set_path(ConsensusHeightPath::from(&consensus_state_path))
+            .map_err(|e| HostError::failed_to_store(format!("consensus height: {e:?}")))?;
         self.consensus_state_store
             .set(consensus_state_path, consensus_state)
             .map_err(|e| HostError::failed_to_store(format!("consensus state: {e:?}")))?;
@@ -126,8 +272,12 @@ where
         &mut self,
         consensus_state_path: ClientConsensusStatePath,
     ) -> Result<(), HostError> {
-        self.consensus_state_store.delete(consensus_state_path);
-        Ok(())
+        self.consensus_height_index
+            .delete(ConsensusHeightPath::from(&consensus_state_path))
+            .map_err(|e| HostError::failed_to_store(format!("consensus height: {e:?}")))?;
+        self.consensus_state_store
+            .delete(consensus_state_path)
+            .map_err(|e| HostError::failed_to_store(format!("consensus state: {e:?}")))
     }
 
     /// Called upon successful client update. Implementations are expected to
@@ -179,15 +329,25 @@ where
             height.revision_number(),
             height.revision_height(),
         );
-        self.client_processed_times.delete(client_update_time_path);
+        self.client_processed_times
+            .delete(client_update_time_path)
+            .map_err(|e| {
+                HostError::failed_to_store(format!(
+                    "update time for client {client_id} at height {height}: {e:?}"
+                ))
+            })?;
         let client_update_height_path = ClientUpdateHeightPath::new(
-            client_id,
+            client_id.clone(),
             height.revision_number(),
             height.revision_height(),
         );
         self.client_processed_heights
-            .delete(client_update_height_path);
-        Ok(())
+            .delete(client_update_height_path)
+            .map_err(|e| {
+                HostError::failed_to_store(format!(
+                    "update height for client {client_id} at height {height}: {e:?}"
+                ))
+            })
     }
 }
 
@@ -204,29 +364,7 @@ where
     }
 
     fn consensus_state_heights(&self, client_id: &ClientId) -> Result<Vec<IbcHeight>, HostError> {
-        let path = format!("clients/{}/consensusStates", client_id)
-            .try_into()
-            .map_err(|e| HostError::invalid_state(format!("consensus state path: {e:?}")))?;
-
-        self.consensus_state_store
-            .get_keys(&path)
-            .into_iter()
-            .flat_map(|path| {
-                if let Ok(Path::ClientConsensusState(consensus_path)) = path.try_into() {
-                    Some(consensus_path)
-                } else {
-                    None
-                }
-            })
-            .map(|consensus_path| {
-                let height = IbcHeight::new(
-                    consensus_path.revision_number,
-                    consensus_path.revision_height,
-                )
-                .map_err(|e| HostError::invalid_state(format!("consensus state height: {e:?}")))?;
-                Ok(height)
-            })
-            .collect()
+        self.consensus_heights(client_id).collect()
     }
 
     fn next_consensus_state(
@@ -234,34 +372,9 @@ where
         client_id: &ClientId,
         height: &IbcHeight,
     ) -> Result<Option<Self::ConsensusStateRef>, HostError> {
-        let path = format!("clients/{client_id}/consensusStates")
-            .try_into()
-            .unwrap(); // safety - path must be valid since ClientId and height are valid Identifiers
-
-        let keys = self.store.get_keys(&path);
-        let found_path = keys.into_iter().find_map(|path| {
-            if let Ok(Path::ClientConsensusState(path)) = path.try_into() {
-                if height > &IbcHeight::new(path.revision_number, path.revision_height).unwrap() {
-                    return Some(path);
-                }
-            }
-            None
-        });
-
-        if let Some(path) = found_path {
-            let consensus_state = self
-                .consensus_state_store
-                .get(Height::Pending, &path)
-                .ok_or_else(|| {
-                    HostError::missing_state(format!(
-                        "consensus state for client_id {client_id} at height {height}"
-                    ))
-                })?;
-
-            Ok(Some(consensus_state))
-        } else {
-            Ok(None)
-        }
+        self.next_consensus_height(client_id, height)?
+            .map(|next_height| self.consensus_state_at(client_id, &next_height))
+            .transpose()
     }
 
     fn prev_consensus_state(
@@ -269,36 +382,86 @@ where
         client_id: &ClientId,
         height: &IbcHeight,
     ) -> Result<Option<Self::ConsensusStateRef>, HostError> {
-        let path = format!("clients/{client_id}/consensusStates")
-            .try_into()
-            .unwrap(); // safety - path must be valid since ClientId and height are valid Identifiers
-
-        let keys = self.store.get_keys(&path);
-        let pos = keys.iter().position(|path| {
-            if let Ok(Path::ClientConsensusState(path)) = path.clone().try_into() {
-                height >= &IbcHeight::new(path.revision_number, path.revision_height).unwrap()
-            } else {
-                false
-            }
-        });
-
-        if let Some(pos) = pos {
-            if pos > 0 {
-                let prev_path = match keys[pos - 1].clone().try_into() {
-                    Ok(Path::ClientConsensusState(p)) => p,
-                    _ => unreachable!(), // safety - path retrieved from store
-                };
-                let consensus_state = self
-                    .consensus_state_store
-                    .get(Height::Pending, &prev_path)
-                    .ok_or_else(|| {
-                        HostError::missing_state(format!(
-                            "consensus state for client_id {client_id} at height {height}"
-                        ))
-                    })?;
-                return Ok(Some(consensus_state));
-            }
+        self.prev_consensus_height(client_id, height)?
+            .map(|prev_height| self.consensus_state_at(client_id, &prev_height))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use basecoin_store::impls::{InMemoryStore, SharedStore};
+
+    use super::*;
+
+    fn height(revision_number: u64, revision_height: u64) -> IbcHeight {
+        IbcHeight::new(revision_number, revision_height).unwrap()
+    }
+
+    #[test]
+    fn consensus_heights_in_numerical_order() {
+        let mut ctx = IbcContext::new(SharedStore::new(InMemoryStore::default()));
+        let client_id: ClientId = "07-tendermint-1".parse().unwrap();
+        let other_client_id: ClientId = "07-tendermint-10".parse().unwrap();
+
+        let heights = [(2, 1), (1, 100), (1, 9), (1, 11), (1, 1), (1, 99), (1, 10)];
+        for (revision_number, revision_height) in heights {
+            let path =
+                ConsensusHeightPath::new(&client_id, &height(revision_number, revision_height));
+            ctx.consensus_height_index.set_path(path).unwrap();
         }
-        Ok(None)
+        let path = ConsensusHeightPath::new(&other_client_id, &height(1, 50));
+        ctx.consensus_height_index.set_path(path).unwrap();
+
+        let expected = [(1, 1), (1, 9), (1, 10), (1, 11), (1, 99), (1, 100), (2, 1)]
+            .map(|(revision_number, revision_height)| height(revision_number, revision_height));
+        assert_eq!(
+            ctx.consensus_heights(&client_id)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            expected
+        );
+
+        let next = |h| ctx.next_consensus_height(&client_id, &h).unwrap();
+        assert_eq!(next(height(1, 1)), Some(height(1, 9)));
+        assert_eq!(next(height(1, 9)), Some(height(1, 10)));
+        assert_eq!(next(height(1, 12)), Some(height(1, 99)));
+        assert_eq!(next(height(1, 99)), Some(height(1, 100)));
+        assert_eq!(next(height(1, 1000)), Some(height(2, 1)));
+        assert_eq!(next(height(2, 1)), None);
+
+        let prev = |h| ctx.prev_consensus_height(&client_id, &h).unwrap();
+        assert_eq!(prev(height(1, 1)), None);
+        assert_eq!(prev(height(1, 10)), Some(height(1, 9)));
+        assert_eq!(prev(height(1, 50)), Some(height(1, 11)));
+        assert_eq!(prev(height(1, 100)), Some(height(1, 99)));
+        assert_eq!(prev(height(2, 1)), Some(height(1, 100)));
+        assert_eq!(prev(height(3, 0)), Some(height(2, 1)));
+
+        assert_eq!(
+            ctx.consensus_heights(&other_client_id)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            [height(1, 50)]
+        );
+        assert_eq!(
+            ctx.next_consensus_height(&other_client_id, &height(1, 1))
+                .unwrap(),
+            Some(height(1, 50))
+        );
+        assert_eq!(
+            ctx.prev_consensus_height(&other_client_id, &height(1, 50))
+                .unwrap(),
+            None
+        );
+
+        ctx.consensus_height_index
+            .delete(ConsensusHeightPath::new(&client_id, &height(1, 10)))
+            .unwrap();
+        assert_eq!(
+            ctx.next_consensus_height(&client_id, &height(1, 9))
+                .unwrap(),
+            Some(height(1, 11))
+        );
     }
 }
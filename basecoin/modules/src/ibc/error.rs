@@ -1,4 +1,6 @@
+use basecoin_store::types::TypedStoreError;
 use ibc::core::handler::types::error::HandlerError;
+use ibc::core::host::types::error::HostError;
 
 pub use crate::error::Error as AppError;
 
@@ -9,3 +11,14 @@ impl From<Error> for AppError {
         Self::Ibc(e)
     }
 }
+
+/// Maps an error of the IBC stores onto the matching [`HostError`].
+pub(crate) fn store_error(e: TypedStoreError) -> HostError {
+    match e {
+        TypedStoreError::NotFound { .. } => HostError::missing_state(e.to_string()),
+        TypedStoreError::DecodeFailed { .. } => HostError::invalid_state(e.to_string()),
+        TypedStoreError::EncodeFailed { .. } | TypedStoreError::Backend { .. } => {
+            HostError::failed_to_store(e.to_string())
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::ops::Deref;
+use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
@@ -63,6 +64,8 @@ use tracing::debug;
 use crate::bank::BankBalanceKeeper;
 use crate::context::{Identifiable, Module};
 use crate::error::Error as AppError;
+use crate::ibc::client_contexts::ConsensusHeightPath;
+use crate::ibc::error::store_error;
 use crate::ibc::router::IbcRouter;
 use crate::ibc::transfer::IbcTransferModule;
 use crate::types::QueryResult;
@@ -381,6 +384,8 @@ where
     /// A typed-store for AnyConsensusState
     pub(crate) consensus_state_store:
         ProtobufStore<SharedStore<S>, ClientConsensusStatePath, AnyConsensusState, Any>,
+    /// A typed-set indexing the heights of each client's consensus states in numerical order
+    pub(crate) consensus_height_index: TypedSet<SharedStore<S>, ConsensusHeightPath>,
     /// A typed-store for ConnectionEnd
     connection_end_store:
         ProtobufStore<SharedStore<S>, ConnectionPath, ConnectionEnd, RawConnectionEnd>,
@@ -415,17 +420,36 @@ where
         let mut conn_counter = TypedStore::new(store.clone());
         let mut channel_counter = TypedStore::new(store.clone());
 
-        client_counter
-            .set(NextClientSequencePath, 0)
-            .expect("no error");
+        // only initialize the counters if they weren't restored from a persisted store
+        if client_counter
+            .get(Height::Pending, &NextClientSequencePath)
+            .expect("invalid counter")
+            .is_none()
+        {
+            client_counter
+                .set(NextClientSequencePath, 0)
+                .expect("no error");
+        }
 
-        conn_counter
-            .set(NextConnectionSequencePath, 0)
-            .expect("no error");
+        if conn_counter
+            .get(Height::Pending, &NextConnectionSequencePath)
+            .expect("invalid counter")
+            .is_none()
+        {
+            conn_counter
+                .set(NextConnectionSequencePath, 0)
+                .expect("no error");
+        }
 
-        channel_counter
-            .set(NextChannelSequencePath, 0)
-            .expect("no error");
+        if channel_counter
+            .get(Height::Pending, &NextChannelSequencePath)
+            .expect("invalid counter")
+            .is_none()
+        {
+            channel_counter
+                .set(NextChannelSequencePath, 0)
+                .expect("no error");
+        }
 
         Self {
             client_counter,
@@ -436,6 +460,7 @@ where
             consensus_states: Default::default(),
             client_state_store: TypedStore::new(store.clone()),
             consensus_state_store: TypedStore::new(store.clone()),
+            consensus_height_index: TypedStore::new(store.clone()),
             connection_end_store: TypedStore::new(store.clone()),
             connection_ids_store: TypedStore::new(store.clone()),
             channel_end_store: TypedStore::new(store.clone()),
@@ -500,12 +525,14 @@ where
     fn client_counter(&self) -> Result<u64, HostError> {
         self.client_counter
             .get(Height::Pending, &NextClientSequencePath)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("client counter"))
     }
 
     fn connection_end(&self, conn_id: &ConnectionId) -> Result<ConnectionEnd, HostError> {
         self.connection_end_store
             .get(Height::Pending, &ConnectionPath::new(conn_id))
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state(format!("connection end: {}", conn_id)))
     }
 
@@ -524,6 +551,7 @@ where
     fn connection_counter(&self) -> Result<u64, HostError> {
         self.conn_counter
             .get(Height::Pending, &NextConnectionSequencePath)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("connection counter"))
     }
 
@@ -537,6 +565,7 @@ where
                 Height::Pending,
                 &ChannelEndPath::new(&channel_end_path.0, &channel_end_path.1),
             )
+            .map_err(store_error)?
             .ok_or_else(|| {
                 HostError::missing_state(format!(
                     "port: {}, channel_id: {}",
@@ -551,6 +580,7 @@ where
                 Height::Pending,
                 &SeqSendPath::new(&seq_send_path.0, &seq_send_path.1),
             )
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("sequence send"))
     }
 
@@ -560,6 +590,7 @@ where
                 Height::Pending,
                 &SeqRecvPath::new(&seq_recv_path.0, &seq_recv_path.1),
             )
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("sequence recv"))
     }
 
@@ -569,6 +600,7 @@ where
                 Height::Pending,
                 &SeqAckPath::new(&seq_ack_path.0, &seq_ack_path.1),
             )
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("sequence ack"))
     }
 
@@ -585,6 +617,7 @@ where
                     commitment_path.sequence,
                 ),
             )
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("packet commitment"))
     }
 
@@ -611,6 +644,7 @@ where
                 Height::Pending,
                 &AckPath::new(&ack_path.port_id, &ack_path.channel_id, ack_path.sequence),
             )
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state(format!("packet ack: {:?}", ack_path.sequence)))
     }
 
@@ -620,6 +654,7 @@ where
     fn channel_counter(&self) -> Result<u64, HostError> {
         self.channel_counter
             .get(Height::Pending, &NextChannelSequencePath)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("channel counter"))
     }
 
@@ -646,6 +681,56 @@ where
     }
 }
 
+/// A key of a listing of the entries of an IBC store, i.e. an [`IbcPath`] of the variant `P`.
+///
+/// As the paths of several stores share the same prefix (e.g. `clients`), listings parse the
+/// paths of the entries under the prefix as this key, to only decode the entries of their store.
+struct IbcKey<P>(P);
+
+/// The [`IbcPath`] variants that stores are keyed by.
+trait IbcPathVariant: Sized {
+    fn from_ibc_path(path: IbcPath) -> Option<Self>;
+}
+
+macro_rules! impl_ibc_path_variant {
+    ($($variant:ident($path:ty)),* $(,)?) => {
+        $(
+            impl IbcPathVariant for $path {
+                fn from_ibc_path(path: IbcPath) -> Option<Self> {
+                    match path {
+                        IbcPath::$variant(path) => Some(path),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_ibc_path_variant!(
+    ClientState(ClientStatePath),
+    ClientConsensusState(ClientConsensusStatePath),
+    Connection(ConnectionPath),
+    ChannelEnd(ChannelEndPath),
+    Commitment(CommitmentPath),
+    Ack(AckPath),
+);
+
+impl<P: IbcPathVariant> FromStr for IbcKey<P> {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = IbcPath::from_str(s).map_err(|_| ())?;
+        P::from_ibc_path(path).map(Self).ok_or(())
+    }
+}
+
+/// The height at which gRPC queries are served.
+///
+/// Proofs for queried entries are generated at the host height, i.e. the latest committed height,
+/// so listings and lookups are done at the same height to be consistent with them.
+const QUERY_HEIGHT: Height = Height::Latest;
+
 /// Trait to complete the gRPC service blanket implementations.
 impl<S> QueryContext for IbcContext<S>
 where
@@ -656,22 +741,9 @@ where
         let path = "clients".to_owned().into();
 
         self.client_state_store
-            .get_keys(&path)
-            .into_iter()
-            .filter_map(|path| {
-                if let Ok(IbcPath::ClientState(client_path)) = path.try_into() {
-                    Some(client_path)
-                } else {
-                    None
-                }
-            })
-            .map(|client_state_path| {
-                let client_state = self
-                    .client_state_store
-                    .get(Height::Pending, &client_state_path)
-                    .ok_or_else(|| {
-                        HostError::missing_state(format!("client state: {:?}", client_state_path.0))
-                    })?;
+            .entries_at::<IbcKey<ClientStatePath>>(QUERY_HEIGHT, &path, None, None)
+            .map(|entry| {
+                let (IbcKey(client_state_path), client_state) = entry.map_err(store_error)?;
                 Ok((client_state_path.0, client_state))
             })
             .collect()
@@ -687,31 +759,15 @@ where
             .map_err(HostError::invalid_state)?;
 
         self.consensus_state_store
-            .get_keys(&path)
-            .into_iter()
-            .flat_map(|path| {
-                if let Ok(IbcPath::ClientConsensusState(consensus_path)) = path.try_into() {
-                    Some(consensus_path)
-                } else {
-                    None
-                }
-            })
-            .map(|consensus_path| {
+            .entries_at::<IbcKey<ClientConsensusStatePath>>(QUERY_HEIGHT, &path, None, None)
+            .map(|entry| {
+                let (IbcKey(consensus_path), consensus_state) = entry.map_err(store_error)?;
                 let height = IbcHeight::new(
                     consensus_path.revision_number,
                     consensus_path.revision_height,
                 )
                 .map_err(HostError::invalid_state)?;
-                let client_state = self
-                    .consensus_state_store
-                    .get(Height::Pending, &consensus_path)
-                    .ok_or_else(|| {
-                        HostError::missing_state(format!(
-                            "consensus state for client {} at height {:?}",
-                            client_id, height
-                        ))
-                    })?;
-                Ok((height, client_state))
+                Ok((height, consensus_state))
             })
             .collect()
     }
@@ -723,7 +779,7 @@ where
             .map_err(HostError::invalid_state)?;
 
         self.consensus_state_store
-            .get_keys(&path)
+            .get_keys_at(QUERY_HEIGHT, &path)
             .into_iter()
             .flat_map(|path| {
                 if let Ok(IbcPath::ClientConsensusState(consensus_path)) = path.try_into() {
@@ -747,22 +803,9 @@ where
         let path = "connections".to_owned().into();
 
         self.connection_end_store
-            .get_keys(&path)
-            .into_iter()
-            .flat_map(|path| {
-                if let Ok(IbcPath::Connection(connection_path)) = path.try_into() {
-                    Some(connection_path)
-                } else {
-                    None
-                }
-            })
-            .map(|connection_path| {
-                let connection_end = self
-                    .connection_end_store
-                    .get(Height::Pending, &connection_path)
-                    .ok_or_else(|| {
-                        HostError::missing_state(format!("connection: {:?}", connection_path.0))
-                    })?;
+            .entries_at::<IbcKey<ConnectionPath>>(QUERY_HEIGHT, &path, None, None)
+            .map(|entry| {
+                let (IbcKey(connection_path), connection_end) = entry.map_err(store_error)?;
                 Ok(IdentifiedConnectionEnd {
                     connection_id: connection_path.0,
                     connection_end,
@@ -777,7 +820,8 @@ where
 
         Ok(self
             .connection_ids_store
-            .get(Height::Pending, &client_connection_path)
+            .get(QUERY_HEIGHT, &client_connection_path)
+            .map_err(store_error)?
             .unwrap_or_default())
     }
 
@@ -786,25 +830,9 @@ where
         let path = "channelEnds".to_owned().into();
 
         self.channel_end_store
-            .get_keys(&path)
-            .into_iter()
-            .flat_map(|path| {
-                if let Ok(IbcPath::ChannelEnd(channel_path)) = path.try_into() {
-                    Some(channel_path)
-                } else {
-                    None
-                }
-            })
-            .map(|channel_path| {
-                let channel_end = self
-                    .channel_end_store
-                    .get(Height::Pending, &channel_path)
-                    .ok_or_else(|| {
-                        HostError::missing_state(format!(
-                            "port: {}, channel_id: {}",
-                            channel_path.0, channel_path.1
-                        ))
-                    })?;
+            .entries_at::<IbcKey<ChannelEndPath>>(QUERY_HEIGHT, &path, None, None)
+            .map(|entry| {
+                let (IbcKey(channel_path), channel_end) = entry.map_err(store_error)?;
                 Ok(IdentifiedChannelEnd {
                     port_id: channel_path.0,
                     channel_id: channel_path.1,
@@ -826,30 +854,17 @@ where
         .into();
 
         self.packet_commitment_store
-            .get_keys(&path_prefix)
-            .into_iter()
-            .flat_map(|path| {
-                if let Ok(IbcPath::Commitment(commitment_path)) = path.try_into() {
-                    Some(commitment_path)
-                } else {
-                    None
-                }
-            })
-            .filter(|commitment_path| {
-                self.packet_commitment_store
-                    .get(Height::Pending, commitment_path)
-                    .is_some()
-            })
-            .map(|commitment_path| {
-                self.get_packet_commitment(&commitment_path)
-                    .map(|packet| PacketState {
-                        seq: commitment_path.sequence,
-                        port_id: commitment_path.port_id,
-                        chan_id: commitment_path.channel_id,
-                        data: packet.as_ref().into(),
-                    })
+            .entries_at::<IbcKey<CommitmentPath>>(QUERY_HEIGHT, &path_prefix, None, None)
+            .map(|entry| {
+                let (IbcKey(commitment_path), packet) = entry.map_err(store_error)?;
+                Ok(PacketState {
+                    seq: commitment_path.sequence,
+                    port_id: commitment_path.port_id,
+                    chan_id: commitment_path.channel_id,
+                    data: packet.as_ref().into(),
+                })
             })
-            .collect::<Result<Vec<_>, _>>()
+            .collect()
     }
 
     /// PacketAcknowledgements returns all the packet acknowledgements associated with a channel.
@@ -859,7 +874,14 @@ where
         channel_end_path: &ChannelEndPath,
         sequences: impl ExactSizeIterator<Item = Sequence>,
     ) -> Result<Vec<PacketState>, HostError> {
-        let collected_paths: Vec<_> = if sequences.len() == 0 {
+        let packet_state = |ack_path: AckPath, packet: AcknowledgementCommitment| PacketState {
+            seq: ack_path.sequence,
+            port_id: ack_path.port_id,
+            chan_id: ack_path.channel_id,
+            data: packet.as_ref().into(),
+        };
+
+        if sequences.len() == 0 {
             // if sequences is empty, return all the acks
             let ack_path_prefix = format!(
                 "acks/ports/{}/channels/{}/sequences",
@@ -867,41 +889,27 @@ where
             )
             .into();
 
-            self.packet_ack_store
-                .get_keys(&ack_path_prefix)
-                .into_iter()
-                .flat_map(|path| {
-                    if let Ok(IbcPath::Ack(ack_path)) = path.try_into() {
-                        Some(ack_path)
-                    } else {
-                        None
-                    }
+            return self
+                .packet_ack_store
+                .entries_at::<IbcKey<AckPath>>(QUERY_HEIGHT, &ack_path_prefix, None, None)
+                .map(|entry| {
+                    let (IbcKey(ack_path), packet) = entry.map_err(store_error)?;
+                    Ok(packet_state(ack_path, packet))
                 })
-                .collect()
-        } else {
-            sequences
-                .into_iter()
-                .map(|seq| AckPath::new(&channel_end_path.0, &channel_end_path.1, seq))
-                .collect()
-        };
+                .collect();
+        }
 
-        collected_paths
+        sequences
             .into_iter()
-            .filter(|ack_path| {
+            .map(|seq| AckPath::new(&channel_end_path.0, &channel_end_path.1, seq))
+            .filter_map(|ack_path| {
                 self.packet_ack_store
-                    .get(Height::Pending, ack_path)
-                    .is_some()
+                    .get(QUERY_HEIGHT, &ack_path)
+                    .map_err(store_error)
+                    .transpose()
+                    .map(|packet| packet.map(|packet| packet_state(ack_path, packet)))
             })
-            .map(|ack_path| {
-                self.get_packet_acknowledgement(&ack_path)
-                    .map(|packet| PacketState {
-                        seq: ack_path.sequence,
-                        port_id: ack_path.port_id,
-                        chan_id: ack_path.channel_id,
-                        data: packet.as_ref().into(),
-                    })
-            })
-            .collect::<Result<Vec<_>, _>>()
+            .collect()
     }
 
     /// UnreceivedPackets returns all the unreceived IBC packets associated with
@@ -919,9 +927,9 @@ where
             .into_iter()
             .map(|seq| ReceiptPath::new(&channel_end_path.0, &channel_end_path.1, seq))
             .filter(|receipt_path| {
-                self.packet_receipt_store
-                    .get(Height::Pending, receipt_path)
-                    .is_none()
+                !self
+                    .packet_receipt_store
+                    .is_path_set(QUERY_HEIGHT, receipt_path)
             })
             .map(|receipts_path| receipts_path.sequence)
             .collect())
@@ -943,7 +951,7 @@ where
             .into();
 
             self.packet_commitment_store
-                .get_keys(&commitment_path_prefix)
+                .get_keys_at(QUERY_HEIGHT, &commitment_path_prefix)
                 .into_iter()
                 .flat_map(|path| {
                     if let Ok(IbcPath::Commitment(commitment_path)) = path.try_into() {
@@ -960,15 +968,16 @@ where
                 .collect()
         };
 
-        Ok(collected_paths
+        collected_paths
             .into_iter()
-            .filter(|commitment_path: &CommitmentPath| -> bool {
+            .filter_map(|commitment_path: CommitmentPath| {
                 self.packet_commitment_store
-                    .get(Height::Pending, commitment_path)
-                    .is_some()
+                    .get(QUERY_HEIGHT, &commitment_path)
+                    .map_err(store_error)
+                    .transpose()
+                    .map(|commitment| commitment.map(|_| commitment_path.sequence))
             })
-            .map(|commitment_path| commitment_path.sequence)
-            .collect())
+            .collect()
     }
 }
 
@@ -989,6 +998,7 @@ where
         let current_sequence = self
             .client_counter
             .get(Height::Pending, &NextClientSequencePath)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("client counter"))?;
 
         self.client_counter
@@ -1019,6 +1029,7 @@ where
         let mut conn_ids: Vec<ConnectionId> = self
             .connection_ids_store
             .get(Height::Pending, client_connection_path)
+            .map_err(store_error)?
             .unwrap_or_default();
         conn_ids.push(conn_id);
         self.connection_ids_store
@@ -1034,6 +1045,7 @@ where
         let current_sequence = self
             .conn_counter
             .get(Height::Pending, &NextConnectionSequencePath)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("connection counter"))?;
 
         self.conn_counter
@@ -1055,8 +1067,9 @@ where
     }
 
     fn delete_packet_commitment(&mut self, key: &CommitmentPath) -> Result<(), HostError> {
-        self.packet_commitment_store.delete(key.clone());
-        Ok(())
+        self.packet_commitment_store
+            .delete(key.clone())
+            .map_err(|e| HostError::failed_to_store(format!("packet commitment: {e:?}")))
     }
 
     fn store_packet_receipt(
@@ -1082,8 +1095,9 @@ where
     }
 
     fn delete_packet_acknowledgement(&mut self, ack_path: &AckPath) -> Result<(), HostError> {
-        self.packet_ack_store.delete(ack_path.clone());
-        Ok(())
+        self.packet_ack_store
+            .delete(ack_path.clone())
+            .map_err(|e| HostError::failed_to_store(format!("packet ack: {e:?}")))
     }
 
     /// Stores the given channel_end at a path associated with the port_id and channel_id.
@@ -1135,6 +1149,7 @@ where
         let current_sequence = self
             .channel_counter
             .get(Height::Pending, &NextChannelSequencePath)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("channel counter"))?;
 
         self.channel_counter
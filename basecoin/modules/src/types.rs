@@ -1,10 +1,14 @@
+use basecoin_store::impls::{PrefixStore, SharedStore};
 use basecoin_store::types::Identifier;
+use cosmrs::Tx;
 use tendermint::merkle::proof::ProofOp;
 
 use crate::context::Module;
 
 pub type ModuleList<S> = Vec<IdentifiedModule<S>>;
-pub type ModuleStore<S> = S;
+/// The store of a module, which is either a tree of its own or a prefix of a tree shared by all
+/// the modules.
+pub type ModuleStore<S> = PrefixStore<SharedStore<S>>;
 
 pub struct IdentifiedModule<S> {
     pub id: Identifier,
@@ -15,3 +19,14 @@ pub struct QueryResult {
     pub data: Vec<u8>,
     pub proof: Option<Vec<ProofOp>>,
 }
+
+/// A decoded transaction, along with what its signers signed in `SIGN_MODE_DIRECT`.
+pub struct SignedTx {
+    pub tx: Tx,
+    /// Encoded body of the transaction, as signed.
+    pub body_bytes: Vec<u8>,
+    /// Encoded auth info of the transaction, as signed.
+    pub auth_info_bytes: Vec<u8>,
+    /// Identifier of the chain executing the transaction, which its signatures commit to.
+    pub chain_id: String,
+}
@@ -30,7 +30,7 @@ use super::query::UPGRADE_PLAN_QUERY_PATH;
 use super::service::UpgradeService;
 use crate::context::Module;
 use crate::error::Error as AppError;
-use crate::ibc::{AnyClientState, AnyConsensusState, IbcContext};
+use crate::ibc::{store_error, AnyClientState, AnyConsensusState, IbcContext};
 use crate::types::QueryResult;
 
 #[derive(Clone)]
@@ -141,7 +141,7 @@ where
         if path.to_string() == UPGRADE_PLAN_QUERY_PATH {
             let plan: Any = self
                 .upgrade_plan
-                .get(Height::Pending, &UpgradePlanPath::sdk_pending_path())
+                .get(Height::Pending, &UpgradePlanPath::sdk_pending_path())?
                 .ok_or_else(|| AppError::Custom {
                     reason: "Data not found".to_string(),
                 })?
@@ -237,6 +237,7 @@ where
         let upgrade_plan = self
             .upgrade_plan
             .get(Height::Pending, &UpgradePlanPath::sdk_pending_path())
+            .map_err(store_error)?
             .ok_or_else(|| HostError::invalid_state("No UpgradePlan"))?;
         Ok(upgrade_plan)
     }
@@ -248,6 +249,7 @@ where
         let upgraded_tm_client_state = self
             .upgraded_client_state_store
             .get(Height::Pending, upgrade_path)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("Upgraded ClientState"))?;
         Ok(upgraded_tm_client_state)
     }
@@ -259,6 +261,7 @@ where
         let upgraded_tm_consensus_state = self
             .upgraded_consensus_state_store
             .get(Height::Pending, upgrade_path)
+            .map_err(store_error)?
             .ok_or_else(|| HostError::missing_state("Upgraded ConsensusState"))?;
         Ok(upgraded_tm_consensus_state)
     }
@@ -290,7 +293,10 @@ where
     fn clear_upgrade_plan(&mut self, plan_height: u64) -> Result<(), HostError> {
         let path = UpgradePlanPath::sdk_pending_path();
 
-        let upgrade_plan = self.upgrade_plan.get(Height::Pending, &path);
+        let upgrade_plan = self
+            .upgrade_plan
+            .get(Height::Pending, &path)
+            .map_err(store_error)?;
 
         if upgrade_plan.is_none() {
             return Err(HostError::invalid_state("No upgrade plan set"));
@@ -299,15 +305,19 @@ where
         let upgraded_client_state_path = UpgradeClientStatePath::new_with_default_path(plan_height);
 
         self.upgraded_client_state_store
-            .delete(upgraded_client_state_path);
+            .delete(upgraded_client_state_path)
+            .map_err(|e| HostError::failed_to_store(format!("UpgradedClientState: {e:?}")))?;
 
         let upgraded_cons_state_path =
             UpgradeConsensusStatePath::new_with_default_path(plan_height);
 
         self.upgraded_consensus_state_store
-            .delete(upgraded_cons_state_path);
+            .delete(upgraded_cons_state_path)
+            .map_err(|e| HostError::failed_to_store(format!("UpgradeConsensusState: {e:?}")))?;
 
-        self.upgrade_plan.delete(path);
+        self.upgrade_plan
+            .delete(path)
+            .map_err(|e| HostError::failed_to_store(format!("UpgradePlan: {e:?}")))?;
 
         Ok(())
     }
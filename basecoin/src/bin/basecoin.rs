@@ -39,7 +39,10 @@ async fn main() {
     match &cli.command {
         Commands::Start => {
             tracing::info!("Starting app and waiting for CometBFT to connect...");
-            default_app_runner(cfg.server).await
+            if let Err(e) = default_app_runner(cfg.server, cfg.store).await {
+                tracing::error!("{e}");
+                std::process::exit(1);
+            }
         }
         Commands::Query(q) => {
             let query_res = match q {
@@ -1,6 +1,9 @@
 pub use std::path::Path;
+use std::path::PathBuf;
 
+use basecoin_app::StoreLayout;
 use basecoin_modules::error::Error;
+use basecoin_store::types::{PruningStrategy, RawHeight};
 use serde_derive::{Deserialize, Serialize};
 use tendermint_rpc::Url;
 use tracing_subscriber::filter::LevelFilter;
@@ -10,6 +13,8 @@ pub struct Config {
     pub global: GlobalConfig,
     pub server: ServerConfig,
     pub cometbft: CometbftConfig,
+    #[serde(default)]
+    pub store: StoreConfig,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -52,6 +57,74 @@ pub struct CometbftConfig {
     pub grpc_addr: Url,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StoreConfig {
+    /// Directory where committed state is persisted. State is kept in memory only if unset.
+    pub data_dir: Option<PathBuf>,
+    /// Merkle tree backing the application stores.
+    #[serde(default)]
+    pub backend: StoreBackend,
+    /// Committed heights kept by the application stores.
+    #[serde(default)]
+    pub pruning: Pruning,
+    /// Layout of the module stores.
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum StoreBackend {
+    /// The AVL tree of the `InMemoryStore`, the only backend that supports `data_dir`.
+    #[default]
+    Avl,
+    /// An IAVL tree, whose proofs verify with the ICS23 `IavlSpec`.
+    Iavl,
+    /// A sparse Merkle tree, whose root only depends on the stored entries.
+    Smt,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Pruning {
+    /// Keep every committed height.
+    #[default]
+    Nothing,
+    /// Only keep the latest committed height.
+    Everything,
+    /// Keep the given number of latest committed heights.
+    KeepRecent(RawHeight),
+    /// Keep the latest committed height and every multiple of the given height.
+    KeepEvery(RawHeight),
+}
+
+impl From<Pruning> for PruningStrategy {
+    fn from(pruning: Pruning) -> Self {
+        match pruning {
+            Pruning::Nothing => Self::Nothing,
+            Pruning::Everything => Self::Everything,
+            Pruning::KeepRecent(n) => Self::KeepRecent(n),
+            Pruning::KeepEvery(k) => Self::KeepEvery(k),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Layout {
+    /// A Merkle tree per module, committed to the main tree.
+    #[default]
+    MultiTree,
+    /// A single Merkle tree shared by all modules under key prefixes.
+    SingleTree,
+}
+
+impl From<Layout> for StoreLayout {
+    fn from(layout: Layout) -> Self {
+        match layout {
+            Layout::MultiTree => Self::MultiTree,
+            Layout::SingleTree => Self::SingleTree,
+        }
+    }
+}
+
 /// Attempt to load and parse the TOML config file as a `Config`.
 pub fn load_config(path: impl AsRef<Path>) -> Result<Config, Error> {
     let config_toml = std::fs::read_to_string(&path).map_err(|e| Error::Custom {
@@ -5,11 +5,12 @@ use basecoin_modules::auth::{AuthAccountKeeper, AuthAccountReader};
 use basecoin_modules::bank::Bank;
 use basecoin_modules::context::{prefix, Identifiable};
 use basecoin_modules::ibc::Ibc;
+use basecoin_modules::types::ModuleStore;
 use basecoin_store::context::ProvableStore;
 use basecoin_store::utils::SharedRwExt;
 
 /// Gives access to the IBC module.
-pub fn ibc<S>(app: BaseCoinApp<S>) -> Ibc<S>
+pub fn ibc<S>(app: BaseCoinApp<S>) -> Ibc<ModuleStore<S>>
 where
     S: ProvableStore + Default + Debug,
 {
@@ -18,12 +19,19 @@ where
     modules
         .iter()
         .find(|m| m.id == prefix::Ibc {}.identifier())
-        .and_then(|m| m.module.as_any().downcast_ref::<Ibc<S>>().cloned())
+        .and_then(|m| {
+            m.module
+                .as_any()
+                .downcast_ref::<Ibc<ModuleStore<S>>>()
+                .cloned()
+        })
         .expect("IBC module not found")
 }
 
 /// Gives access to the Bank module.
-pub fn bank<S>(app: BaseCoinApp<S>) -> Bank<S, AuthAccountReader<S>, AuthAccountKeeper<S>>
+pub fn bank<S>(
+    app: BaseCoinApp<S>,
+) -> Bank<ModuleStore<S>, AuthAccountReader<ModuleStore<S>>, AuthAccountKeeper<ModuleStore<S>>>
 where
     S: ProvableStore + Default + Debug,
 {
@@ -35,7 +43,11 @@ where
         .and_then(|m| {
             m.module
                 .as_any()
-                .downcast_ref::<Bank<S, AuthAccountReader<S>, AuthAccountKeeper<S>>>()
+                .downcast_ref::<Bank<
+                    ModuleStore<S>,
+                    AuthAccountReader<ModuleStore<S>>,
+                    AuthAccountKeeper<ModuleStore<S>>,
+                >>()
                 .cloned()
         })
         .expect("Bank module not found")
@@ -1,37 +1,151 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::sync::{Arc, RwLock};
+
 use basecoin_app::Builder;
 use basecoin_modules::auth::Auth;
 use basecoin_modules::bank::Bank;
 use basecoin_modules::context::{prefix, Identifiable};
+use basecoin_modules::error::Error;
 use basecoin_modules::gov::Governance;
 use basecoin_modules::ibc::Ibc;
 use basecoin_modules::staking::Staking;
 use basecoin_modules::upgrade::Upgrade;
-use basecoin_store::impls::InMemoryStore;
+use basecoin_store::context::{ProvableStore, Store};
+use basecoin_store::impls::{IavlStore, PersistentStore, SmtStore};
+use basecoin_store::types::Identifier;
+use basecoin_store::utils::{SharedRw, SharedRwExt};
 use ibc_proto::cosmos::base::tendermint::v1beta1::service_server::ServiceServer as HealthServer;
 use ibc_proto::cosmos::tx::v1beta1::service_server::ServiceServer as TxServer;
 
-use crate::config::ServerConfig;
+use crate::config::{ServerConfig, StoreBackend, StoreConfig};
+
+/// Opens the store named `name` under the configured data directory, or an in-memory store if
+/// no data directory is configured.
+fn open_store(store_cfg: &StoreConfig, name: &str) -> PersistentStore {
+    match &store_cfg.data_dir {
+        Some(data_dir) => PersistentStore::open(data_dir.join(name))
+            .unwrap_or_else(|e| panic!("failed to open `{name}` store: {e}")),
+        None => PersistentStore::default(),
+    }
+}
+
+/// Opens the stores found under the configured data directory, and rolls back the stores that
+/// are ahead of the others to the latest height committed by all of them.
+///
+/// The stores of the app are committed one after the other, so a crash in the middle of a block
+/// commit leaves some of them one height ahead. The stores are only pruned once all of them are
+/// committed, so the previous height of a store that is ahead is still retained, whatever the
+/// pruning strategy. Stores that were never committed are left out.
+///
+/// Fails, rather than starting from an inconsistent state, if the stores can't be brought back
+/// to a common height, with an error explaining how to recover.
+fn open_stores(store_cfg: &StoreConfig) -> Result<BTreeMap<String, PersistentStore>, Error> {
+    let mut stores = BTreeMap::new();
+    let Some(Ok(entries)) = store_cfg.data_dir.as_ref().map(fs::read_dir) else {
+        return Ok(stores);
+    };
+    for entry in entries {
+        let path = entry.expect("failed to read data directory").path();
+        if let (true, Some(name)) = (path.is_dir(), path.file_name().and_then(|n| n.to_str())) {
+            stores.insert(name.to_owned(), open_store(store_cfg, name));
+        }
+    }
+
+    let heights = || {
+        stores
+            .values()
+            .map(Store::current_height)
+            .filter(|height| *height > 0)
+    };
+    let (Some(min), Some(max)) = (heights().min(), heights().max()) else {
+        return Ok(stores);
+    };
+    let unrecoverable = |reason: String| Error::Custom {
+        reason: format!(
+            "{reason}; the stores can't be brought back to a common height, restore the data \
+             directory from a backup or remove it and sync the node again"
+        ),
+    };
+    if max - min > 1 {
+        return Err(unrecoverable(format!(
+            "stores are at heights {min} to {max}, which a crash during a commit can't explain"
+        )));
+    }
+    for (name, store) in stores.iter_mut() {
+        if store.current_height() > min {
+            tracing::warn!(
+                "rolling back `{name}` store from height {} to {min}, the height of the other stores",
+                store.current_height()
+            );
+            store
+                .rollback(min)
+                .map_err(|e| unrecoverable(format!("failed to roll back `{name}` store: {e}")))?;
+        }
+    }
+    Ok(stores)
+}
+
+/// Runs the application with the configured stores. Fails if the persisted stores can't be
+/// opened.
+pub async fn default_app_runner(
+    server_cfg: ServerConfig,
+    store_cfg: StoreConfig,
+) -> Result<(), Error> {
+    if store_cfg.data_dir.is_some() && store_cfg.backend != StoreBackend::Avl {
+        tracing::warn!(
+            "`data_dir` is ignored by the {:?} store backend, state is kept in memory only",
+            store_cfg.backend
+        );
+    }
+
+    // instantiate the application with the KV store implementation of choice
+    match store_cfg.backend {
+        StoreBackend::Avl => {
+            let stores: SharedRw<_> = Arc::new(RwLock::new(open_stores(&store_cfg)?));
+            app_runner(server_cfg, &store_cfg, |name| {
+                let store = stores.write_access().remove(name);
+                store.unwrap_or_else(|| open_store(&store_cfg, name))
+            })
+            .await
+        }
+        StoreBackend::Iavl => app_runner(server_cfg, &store_cfg, |_| IavlStore::default()).await,
+        StoreBackend::Smt => app_runner(server_cfg, &store_cfg, |_| SmtStore::default()).await,
+    }
+    Ok(())
+}
 
-pub async fn default_app_runner(server_cfg: ServerConfig) {
-    // instantiate the application with a KV store implementation of choice
-    let app_builder = Builder::new(InMemoryStore::default());
+/// Runs the application over stores opened by `open_store`, which is given the name of each store.
+async fn app_runner<S>(
+    server_cfg: ServerConfig,
+    store_cfg: &StoreConfig,
+    open_store: impl Fn(&str) -> S,
+) where
+    S: Default + Debug + ProvableStore,
+{
+    let app_builder = Builder::new(open_store("main"))
+        .pruning(store_cfg.pruning.into())
+        .layout(store_cfg.layout.into());
+    let module_store =
+        |id: Identifier| app_builder.module_store_with(&id, || open_store(id.as_str()));
 
     // instantiate modules and setup inter-module communication (if required)
-    let auth = Auth::new(app_builder.module_store(&prefix::Auth {}.identifier()));
+    let auth = Auth::new(module_store(prefix::Auth {}.identifier()));
     let bank = Bank::new(
-        app_builder.module_store(&prefix::Bank {}.identifier()),
+        module_store(prefix::Bank {}.identifier()),
         auth.account_reader().clone(),
         auth.account_keeper().clone(),
     );
-    let staking = Staking::new(app_builder.module_store(&prefix::Staking {}.identifier()));
+    let staking = Staking::new(module_store(prefix::Staking {}.identifier()));
     let ibc = Ibc::new(
-        app_builder.module_store(&prefix::Ibc {}.identifier()),
+        module_store(prefix::Ibc {}.identifier()),
         bank.bank_keeper().clone(),
     );
-    let upgrade = Upgrade::new(app_builder.module_store(&prefix::Upgrade {}.identifier()));
+    let upgrade = Upgrade::new(module_store(prefix::Upgrade {}.identifier()));
 
     let governance = Governance::new(
-        app_builder.module_store(&prefix::Governance {}.identifier()),
+        module_store(prefix::Governance {}.identifier()),
         upgrade.clone(),
         ibc.clone(),
     );
@@ -122,3 +236,59 @@ pub async fn default_app_runner(server_cfg: ServerConfig) {
 
     grpc_server.await.unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use basecoin_store::types::{Path, PruningStrategy};
+
+    use super::*;
+
+    /// Commits a block to `store` and prunes it as the app does once all its stores are committed.
+    fn commit_block(store: &mut PersistentStore, pruning: Option<PruningStrategy>) {
+        let height = store.current_height() + 1;
+        store
+            .set(
+                Path::from("height".to_owned()),
+                height.to_be_bytes().to_vec(),
+            )
+            .unwrap();
+        store.commit().unwrap();
+        if let Some(pruning) = pruning {
+            pruning.prune(store).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_restart_after_crash_with_pruning() {
+        let data_dir = std::env::temp_dir().join(format!("basecoin-runner-{}", std::process::id()));
+        let store_cfg = StoreConfig {
+            data_dir: Some(data_dir.clone()),
+            ..StoreConfig::default()
+        };
+        let pruning = Some(PruningStrategy::Everything);
+
+        let (mut main, mut bank) = (
+            open_store(&store_cfg, "main"),
+            open_store(&store_cfg, "bank"),
+        );
+        for _ in 0..3 {
+            commit_block(&mut bank, pruning);
+            commit_block(&mut main, pruning);
+        }
+        let root_hash = bank.root_hash();
+        // the process is killed after committing the bank store, before pruning the stores
+        commit_block(&mut bank, None);
+
+        let stores = open_stores(&store_cfg).unwrap();
+        assert!(stores.values().all(|store| store.current_height() == 3));
+        assert_eq!(stores["bank"].root_hash(), root_hash);
+
+        // the previous height of a store that is ahead isn't retained if it was pruned, which
+        // can't be recovered from
+        let mut bank = open_store(&store_cfg, "bank");
+        commit_block(&mut bank, pruning);
+        assert!(open_stores(&store_cfg).is_err());
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
@@ -176,3 +176,171 @@ pub async fn query_account(grpc_addr: Url, address: String) -> Result<BaseAccoun
         reason: format!("failed to decode account: {e}"),
     })
 }
+
+#[cfg(all(test, feature = "v0_37"))]
+mod tests {
+    use std::str::FromStr;
+
+    use basecoin_app::abci::v0_37::impls::{check_tx, commit, deliver_tx, init_chain};
+    use basecoin_app::{BaseCoinApp, Builder};
+    use basecoin_modules::auth::{Auth, RELAYER_ACCOUNT};
+    use basecoin_modules::bank::Bank;
+    use basecoin_modules::context::{prefix, Identifiable};
+    use basecoin_store::impls::InMemoryStore;
+    use hdpath::StandardHDPath;
+    use ibc_proto::cosmos::bank::v1beta1::MsgSend;
+    use ibc_proto::cosmos::base::v1beta1::Coin;
+    use serde_json::json;
+    use tendermint_proto::v0_37::abci::{RequestCheckTx, RequestDeliverTx, RequestInitChain};
+
+    use super::*;
+
+    const CHAIN_ID: &str = "basecoin-test";
+    const RECEIVER: &str = "cosmos1t2e0nyjhwn3revunvf2uperhftvhzu4euuzva9";
+
+    fn app() -> BaseCoinApp<InMemoryStore> {
+        let builder = Builder::new(InMemoryStore::default());
+        let auth = Auth::new(builder.module_store(&prefix::Auth {}.identifier()));
+        let bank = Bank::new(
+            builder.module_store(&prefix::Bank {}.identifier()),
+            auth.account_reader().clone(),
+            auth.account_keeper().clone(),
+        );
+        let app = builder
+            .add_module(prefix::Auth {}.identifier(), auth)
+            .add_module(prefix::Bank {}.identifier(), bank)
+            .build();
+
+        let app_state = json!({
+            (RELAYER_ACCOUNT): { "basecoin": "0x100" },
+            (RECEIVER): { "basecoin": "0x10" },
+        });
+        init_chain(
+            &app,
+            RequestInitChain {
+                chain_id: CHAIN_ID.to_owned(),
+                app_state_bytes: app_state.to_string().into_bytes().into(),
+                ..Default::default()
+            },
+        );
+        commit(&app);
+        app
+    }
+
+    fn coin(amount: &str) -> Coin {
+        Coin {
+            denom: "basecoin".to_owned(),
+            amount: amount.to_owned(),
+        }
+    }
+
+    fn relayer_key() -> KeyPair {
+        KeyPair::from_seed_file(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../ci/user_seed.json"),
+            &StandardHDPath::from_str("m/44'/118'/0'/0/0").unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Number of the relayer account, which is the first genesis account in the order of the
+    /// addresses.
+    const RELAYER_ACCOUNT_NUMBER: u64 = 0;
+
+    /// Signs a transfer from the relayer account for the chain `chain_id` with `sign_tx`.
+    fn transfer(chain_id: &str) -> Vec<u8> {
+        transfer_from(&relayer_key().account, RELAYER_ACCOUNT_NUMBER, chain_id)
+    }
+
+    /// Signs a transfer from `from_address` with the key of the relayer account and
+    /// `account_number`, for the chain `chain_id` with `sign_tx`.
+    fn transfer_from(from_address: &str, account_number: u64, chain_id: &str) -> Vec<u8> {
+        let key = relayer_key();
+        let account_info = BaseAccount {
+            address: key.account.clone(),
+            pub_key: None,
+            account_number,
+            sequence: 0,
+        };
+        let msg = MsgSend {
+            from_address: from_address.to_owned(),
+            to_address: RECEIVER.to_owned(),
+            amount: vec![coin("10")],
+        };
+        let fee = Fee {
+            amount: vec![coin("1")],
+            gas_limit: 100_000,
+            payer: String::new(),
+            granter: String::new(),
+        };
+        sign_tx(
+            &key,
+            &chain_id.parse().unwrap(),
+            &account_info,
+            vec![Any {
+                type_url: "/cosmos.bank.v1beta1.MsgSend".to_owned(),
+                value: msg.encode_to_vec(),
+            }],
+            fee,
+            String::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_signed_tx() {
+        let app = app();
+
+        // a tx signed for another chain or with the number of another account doesn't verify
+        let other_chain = transfer("other-chain");
+        let other_number = transfer_from(RELAYER_ACCOUNT, RELAYER_ACCOUNT_NUMBER + 1, CHAIN_ID);
+        for forged in [other_chain, other_number] {
+            let response = check_tx(
+                &app,
+                RequestCheckTx {
+                    tx: forged.clone().into(),
+                    ..Default::default()
+                },
+            );
+            assert_eq!(response.code, 4, "{}", response.log);
+            let response = deliver_tx(&app, RequestDeliverTx { tx: forged.into() });
+            assert_eq!(response.code, 4, "{}", response.log);
+        }
+
+        let signed = transfer(CHAIN_ID);
+        let response = check_tx(
+            &app,
+            RequestCheckTx {
+                tx: signed.clone().into(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(response.code, 0, "{}", response.log);
+        let response = deliver_tx(
+            &app,
+            RequestDeliverTx {
+                tx: signed.clone().into(),
+            },
+        );
+        assert_eq!(response.code, 0, "{}", response.log);
+
+        // the sequence of the signer was used by the tx, so it can't be replayed
+        let response = deliver_tx(&app, RequestDeliverTx { tx: signed.into() });
+        assert_eq!(response.code, 32, "{}", response.log);
+        assert!(response
+            .log
+            .contains("account sequence mismatch, expected 1, got 0"));
+    }
+
+    #[test]
+    fn test_foreign_sender() {
+        let app = app();
+
+        // the relayer can't send the coins of another account
+        let foreign = transfer_from(RECEIVER, RELAYER_ACCOUNT_NUMBER, CHAIN_ID);
+        let response = deliver_tx(&app, RequestDeliverTx { tx: foreign.into() });
+        assert_eq!(response.code, 4, "{}", response.log);
+        assert!(response
+            .log
+            .contains(&format!("sender `{RECEIVER}` didn't sign the transaction")));
+    }
+}
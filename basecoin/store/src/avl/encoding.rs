@@ -0,0 +1,314 @@
+//! Deterministic binary encoding of AVL trees.
+//!
+//! A tree is encoded as the [`ENCODING_VERSION`] byte followed by its nodes in pre-order. Every
+//! node is encoded as a presence byte (`0` for an empty subtree, `1` otherwise), followed by its
+//! key and value, each prefixed with its length as a big-endian `u32`, and then its left and right
+//! subtrees. Heights and hashes are not encoded: they only depend on the shape of the tree and on
+//! its keys and values, so they are recomputed when decoding.
+//!
+//! The encoding of a tree only depends on its shape, keys and values, so equal trees are always
+//! encoded to the same bytes.
+//!
+//! A tree can also be encoded as a delta to a base tree it was derived from (see
+//! [`encode_delta`]), which only holds the nodes that aren't shared with the base tree and refers
+//! to the other ones by merkle hash.
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use displaydoc::Display as DisplayDoc;
+
+use crate::avl::{AsBytes, AvlNode, AvlTree, NodeRef};
+
+/// Version of the tree encoding, which is the first byte of every encoded tree.
+pub const ENCODING_VERSION: u8 = 1;
+
+/// Maximum height of a decoded tree. A balanced tree this high has way more nodes than could fit
+/// in memory, so this only bounds the recursion depth on malformed inputs.
+const MAX_HEIGHT: u32 = 128;
+
+#[derive(Debug, DisplayDoc, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// unexpected end of input
+    UnexpectedEnd,
+    /// unsupported encoding version `{0}`
+    UnsupportedVersion(u8),
+    /// invalid node tag `{0}`
+    InvalidTag(u8),
+    /// `{0}` trailing bytes
+    TrailingBytes(usize),
+    /// keys are not in strictly ascending order
+    UnorderedKeys,
+    /// tree is higher than `{MAX_HEIGHT}`
+    TooHigh,
+    /// the latest height of a history is pruned
+    PrunedLatest,
+    /// reference to an unknown node
+    UnknownNode,
+}
+
+/// Nodes by merkle hash, which the references of the deltas decoded with [`decode_delta`] are
+/// resolved with.
+pub(crate) type NodeIndex<K, V> = HashMap<Vec<u8>, Arc<AvlNode<K, V>>>;
+
+impl<K, V> AvlTree<K, V>
+where
+    K: Ord + AsBytes + for<'a> From<&'a [u8]>,
+    V: Borrow<[u8]> + for<'a> From<&'a [u8]>,
+{
+    /// Encodes the tree, see the [module documentation](self) for the format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![ENCODING_VERSION];
+        encode_node(&self.root, &mut buf);
+        buf
+    }
+
+    /// Decodes a tree encoded with [`Self::encode`], rebuilding the exact same shape, heights and
+    /// hashes.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        read_version(&mut bytes)?;
+        let root = decode_node(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(bytes.len()));
+        }
+        Ok(Self { root })
+    }
+}
+
+/// Appends the encoding of `node` and its subtrees to `buf`, without version byte.
+pub(crate) fn encode_node<K, V>(node: &NodeRef<K, V>, buf: &mut Vec<u8>)
+where
+    K: Ord + AsBytes,
+    V: Borrow<[u8]>,
+{
+    match node {
+        None => buf.push(0),
+        Some(node) => {
+            buf.push(1);
+            encode_bytes(node.key.as_bytes().as_ref(), buf);
+            encode_bytes(node.value.borrow(), buf);
+            encode_node(&node.left, buf);
+            encode_node(&node.right, buf);
+        }
+    }
+}
+
+/// Decodes a node encoded with [`encode_node`] from the front of `bytes`, checking that the keys
+/// of the decoded subtree are ordered.
+pub(crate) fn decode_node<K, V>(bytes: &mut &[u8]) -> Result<NodeRef<K, V>, DecodeError>
+where
+    K: Ord + AsBytes + for<'a> From<&'a [u8]>,
+    V: Borrow<[u8]> + for<'a> From<&'a [u8]>,
+{
+    decode_subtree(bytes, None, None, 0)
+}
+
+fn decode_subtree<K, V>(
+    bytes: &mut &[u8],
+    lower: Option<&K>,
+    upper: Option<&K>,
+    depth: u32,
+) -> Result<NodeRef<K, V>, DecodeError>
+where
+    K: Ord + AsBytes + for<'a> From<&'a [u8]>,
+    V: Borrow<[u8]> + for<'a> From<&'a [u8]>,
+{
+    match take(bytes, 1)?[0] {
+        0 => Ok(None),
+        1 => {
+            if depth > MAX_HEIGHT {
+                return Err(DecodeError::TooHigh);
+            }
+            let key = K::from(decode_bytes(bytes)?);
+            if lower.is_some_and(|lower| &key <= lower) || upper.is_some_and(|upper| &key >= upper)
+            {
+                return Err(DecodeError::UnorderedKeys);
+            }
+            let value = V::from(decode_bytes(bytes)?);
+
+            let mut node = AvlNode::new(key, value);
+            let left = decode_subtree(bytes, lower, Some(&node.key), depth + 1)?;
+            let right = decode_subtree(bytes, Some(&node.key), upper, depth + 1)?;
+            node.left = left;
+            node.right = right;
+            node.update();
+            Ok(Some(Arc::new(node)))
+        }
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
+
+/// Appends the encoding of `tree` as a delta to `base` to `buf`, with version byte.
+///
+/// Only the nodes of `tree` that aren't shared with `base` are encoded, children first. Every node
+/// is encoded as a `1` byte, followed by its key and value as in [`encode_node`] and by references
+/// to its left and right children. The nodes are followed by a `0` byte and a reference to the
+/// root. A reference is a `0` byte for an empty subtree, or a `1` byte followed by the merkle hash
+/// of the node prefixed with its length as a big-endian `u32`.
+///
+/// The shared subtrees are only referenced, so that the delta of a tree derived from `base` by
+/// copy-on-write costs O(changes · log n), and decoding it shares them again.
+pub(crate) fn encode_delta<K, V>(tree: &NodeRef<K, V>, base: &NodeRef<K, V>, buf: &mut Vec<u8>)
+where
+    K: Ord + AsBytes,
+    V: Borrow<[u8]>,
+{
+    buf.push(ENCODING_VERSION);
+    encode_new_nodes(tree, base, buf);
+    buf.push(0);
+    encode_ref(tree, buf);
+}
+
+fn encode_new_nodes<K, V>(node: &NodeRef<K, V>, base: &NodeRef<K, V>, buf: &mut Vec<u8>)
+where
+    K: Ord + AsBytes,
+    V: Borrow<[u8]>,
+{
+    let Some(node) = node else {
+        return;
+    };
+    // the subtrees of a shared node are shared as well
+    if is_shared(node, base) {
+        return;
+    }
+    encode_new_nodes(&node.left, base, buf);
+    encode_new_nodes(&node.right, base, buf);
+    buf.push(1);
+    encode_bytes(node.key.as_bytes().as_ref(), buf);
+    encode_bytes(node.value.borrow(), buf);
+    encode_ref(&node.left, buf);
+    encode_ref(&node.right, buf);
+}
+
+/// Returns whether `node` is a node of `base`, which can only be the node of `base` with the same
+/// key.
+fn is_shared<K: Ord, V>(node: &Arc<AvlNode<K, V>>, base: &NodeRef<K, V>) -> bool {
+    let mut base = base;
+    while let Some(base_node) = base {
+        match base_node.key.cmp(&node.key) {
+            Ordering::Greater => base = &base_node.left,
+            Ordering::Less => base = &base_node.right,
+            Ordering::Equal => return Arc::ptr_eq(base_node, node),
+        }
+    }
+    false
+}
+
+fn encode_ref<K, V>(node: &NodeRef<K, V>, buf: &mut Vec<u8>)
+where
+    K: Ord + AsBytes,
+    V: Borrow<[u8]>,
+{
+    match node {
+        None => buf.push(0),
+        Some(node) => {
+            buf.push(1);
+            encode_bytes(node.merkle_hash().as_bytes(), buf);
+        }
+    }
+}
+
+/// Decodes a delta encoded with [`encode_delta`], resolving its references with `index`, which
+/// must hold all the nodes of its base tree. The decoded nodes are added to `index`, so that the
+/// deltas of the following trees can be decoded with it, and they share the nodes of `index`.
+pub(crate) fn decode_delta<K, V>(
+    mut bytes: &[u8],
+    index: &mut NodeIndex<K, V>,
+) -> Result<NodeRef<K, V>, DecodeError>
+where
+    K: Ord + AsBytes + for<'a> From<&'a [u8]>,
+    V: Borrow<[u8]> + for<'a> From<&'a [u8]>,
+{
+    read_version(&mut bytes)?;
+    loop {
+        match take(&mut bytes, 1)?[0] {
+            0 => break,
+            1 => {
+                let key = K::from(decode_bytes(&mut bytes)?);
+                let value = V::from(decode_bytes(&mut bytes)?);
+                let mut node = AvlNode::new(key, value);
+                node.left = decode_ref(&mut bytes, index)?;
+                node.right = decode_ref(&mut bytes, index)?;
+                if node.left.as_ref().is_some_and(|left| left.key >= node.key)
+                    || node
+                        .right
+                        .as_ref()
+                        .is_some_and(|right| right.key <= node.key)
+                {
+                    return Err(DecodeError::UnorderedKeys);
+                }
+                node.update();
+                if node.height > MAX_HEIGHT {
+                    return Err(DecodeError::TooHigh);
+                }
+                index.insert(node.merkle_hash().as_bytes().to_vec(), Arc::new(node));
+            }
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+    let root = decode_ref(&mut bytes, index)?;
+    if !bytes.is_empty() {
+        return Err(DecodeError::TrailingBytes(bytes.len()));
+    }
+    Ok(root)
+}
+
+fn decode_ref<K: Ord, V>(
+    bytes: &mut &[u8],
+    index: &NodeIndex<K, V>,
+) -> Result<NodeRef<K, V>, DecodeError> {
+    match take(bytes, 1)?[0] {
+        0 => Ok(None),
+        1 => {
+            let hash = decode_bytes(bytes)?;
+            let node = index.get(hash).ok_or(DecodeError::UnknownNode)?;
+            Ok(Some(node.clone()))
+        }
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
+
+/// Adds all the nodes of the tree rooted at `node` to `index`.
+pub(crate) fn index_nodes<K, V>(node: &NodeRef<K, V>, index: &mut NodeIndex<K, V>)
+where
+    K: Ord + AsBytes,
+    V: Borrow<[u8]>,
+{
+    if let Some(node) = node {
+        index_nodes(&node.left, index);
+        index_nodes(&node.right, index);
+        index.insert(node.merkle_hash().as_bytes().to_vec(), node.clone());
+    }
+}
+
+/// Reads the version byte from the front of `bytes`.
+pub(crate) fn read_version(bytes: &mut &[u8]) -> Result<(), DecodeError> {
+    match take(bytes, 1)?[0] {
+        ENCODING_VERSION => Ok(()),
+        version => Err(DecodeError::UnsupportedVersion(version)),
+    }
+}
+
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    let len = u32::try_from(bytes.len()).expect("key or value longer than u32::MAX bytes");
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn decode_bytes<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    let len = take(bytes, 4)?;
+    let len = u32::from_be_bytes(len.try_into().expect("slice has length 4"));
+    take(bytes, len as usize)
+}
+
+/// Splits `len` bytes off the front of `bytes`.
+pub(crate) fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if bytes.len() < len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
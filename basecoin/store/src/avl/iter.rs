@@ -0,0 +1,240 @@
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::ops::Bound;
+use std::sync::Arc;
+
+use crate::avl::node::AvlNode;
+use crate::avl::AsBytes;
+
+/// A pointer to a node that can be used to walk down the tree, either borrowed or owned.
+pub(crate) trait NodePtr: Sized {
+    type Key: Ord;
+    type Value;
+
+    fn node(&self) -> &AvlNode<Self::Key, Self::Value>;
+
+    fn left(&self) -> Option<Self>;
+
+    fn right(&self) -> Option<Self>;
+}
+
+impl<'a, K: Ord, V> NodePtr for &'a AvlNode<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn node(&self) -> &AvlNode<K, V> {
+        self
+    }
+
+    fn left(&self) -> Option<Self> {
+        let node: &'a AvlNode<K, V> = self;
+        node.left.as_deref()
+    }
+
+    fn right(&self) -> Option<Self> {
+        let node: &'a AvlNode<K, V> = self;
+        node.right.as_deref()
+    }
+}
+
+impl<K: Ord, V> NodePtr for Arc<AvlNode<K, V>> {
+    type Key = K;
+    type Value = V;
+
+    fn node(&self) -> &AvlNode<K, V> {
+        self
+    }
+
+    fn left(&self) -> Option<Self> {
+        self.left.clone()
+    }
+
+    fn right(&self) -> Option<Self> {
+        self.right.clone()
+    }
+}
+
+/// Returns true if `key` is before the `start` bound of a range.
+pub(crate) fn is_before<Q: Ord + ?Sized>(start: Bound<&Q>, key: &Q) -> bool {
+    match start {
+        Bound::Included(start) => key < start,
+        Bound::Excluded(start) => key <= start,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Returns true if `key` is after the `end` bound of a range.
+pub(crate) fn is_after<Q: Ord + ?Sized>(end: Bound<&Q>, key: &Q) -> bool {
+    match end {
+        Bound::Included(end) => key > end,
+        Bound::Excluded(end) => key >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+/// A double-ended in-order traversal of the nodes within a range of keys.
+///
+/// The range is described by two monotonic predicates on keys, only evaluated while seeking the
+/// first and last nodes of the range: `is_before_start` holds for all keys before the range,
+/// and `is_after_end` holds for all keys after it. Each stack holds the path to the next node
+/// to be yielded from its end, so that walking the range costs O(log n) per step, and the
+/// traversal stops as soon as the front and back ends meet.
+#[derive(Clone, Debug)]
+pub(crate) struct RawRange<P> {
+    front: Vec<P>,
+    back: Vec<P>,
+    done: bool,
+}
+
+impl<P: NodePtr + Clone> RawRange<P> {
+    pub(crate) fn new(
+        root: Option<P>,
+        is_before_start: impl Fn(&P::Key) -> bool,
+        is_after_end: impl Fn(&P::Key) -> bool,
+    ) -> Self {
+        let mut front = vec![];
+        let mut node_ref = root.clone();
+        while let Some(node) = node_ref {
+            if is_before_start(&node.node().key) {
+                node_ref = node.right();
+            } else {
+                node_ref = node.left();
+                front.push(node);
+            }
+        }
+
+        let mut back = vec![];
+        let mut node_ref = root;
+        while let Some(node) = node_ref {
+            if is_after_end(&node.node().key) {
+                node_ref = node.left();
+            } else {
+                node_ref = node.right();
+                back.push(node);
+            }
+        }
+
+        Self {
+            front,
+            back,
+            done: false,
+        }
+    }
+
+    /// Checks whether the next nodes of both ends haven't crossed, and marks the traversal as
+    /// done if they are the same node.
+    fn check_remaining(&mut self) -> bool {
+        if self.done {
+            return false;
+        }
+        let (Some(front), Some(back)) = (self.front.last(), self.back.last()) else {
+            self.done = true;
+            return false;
+        };
+        match front.node().key.cmp(&back.node().key) {
+            Ordering::Less => true,
+            Ordering::Equal => {
+                self.done = true;
+                true
+            }
+            Ordering::Greater => {
+                self.done = true;
+                false
+            }
+        }
+    }
+
+    pub(crate) fn next(&mut self) -> Option<P> {
+        if !self.check_remaining() {
+            return None;
+        }
+        let node = self.front.pop()?;
+        let mut node_ref = node.right();
+        while let Some(next) = node_ref {
+            node_ref = next.left();
+            self.front.push(next);
+        }
+        Some(node)
+    }
+
+    pub(crate) fn next_back(&mut self) -> Option<P> {
+        if !self.check_remaining() {
+            return None;
+        }
+        let node = self.back.pop()?;
+        let mut node_ref = node.left();
+        while let Some(next) = node_ref {
+            node_ref = next.right();
+            self.back.push(next);
+        }
+        Some(node)
+    }
+}
+
+/// An iterator over a range of entries of an [`AvlTree`](super::AvlTree), in ascending key
+/// order. Use [`Iterator::rev`] to iterate in descending order.
+#[derive(Clone, Debug)]
+pub struct Range<'a, K: Ord, V>(pub(crate) RawRange<&'a AvlNode<K, V>>);
+
+impl<'a, K: Ord, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|node| (&node.key, &node.value))
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|node| (&node.key, &node.value))
+    }
+}
+
+/// An owning iterator over a range of entries of an [`AvlTree`](super::AvlTree), in ascending
+/// key order. Use [`Iterator::rev`] to iterate in descending order.
+///
+/// The iterator holds references to the nodes of the tree it was created from, so it is not
+/// affected by later changes to that tree.
+#[derive(Clone, Debug)]
+pub struct IntoRange<K: Ord, V>(pub(crate) RawRange<Arc<AvlNode<K, V>>>);
+
+impl<K: Ord + Clone, V: Clone> Iterator for IntoRange<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|node| (node.key.clone(), node.value.clone()))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> DoubleEndedIterator for IntoRange<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0
+            .next_back()
+            .map(|node| (node.key.clone(), node.value.clone()))
+    }
+}
+
+/// Returns the predicates delimiting the keys that start with `prefix`.
+///
+/// Assumes that all keys starting with `prefix` are contiguous in key order, and not smaller
+/// than `prefix` itself.
+pub(crate) fn prefix_bounds<'p, K, Q>(
+    prefix: &'p Q,
+) -> (impl Fn(&K) -> bool + 'p, impl Fn(&K) -> bool + 'p)
+where
+    K: Borrow<Q>,
+    Q: Ord + AsBytes + ?Sized,
+{
+    let is_before_start = move |key: &K| key.borrow() < prefix;
+    let is_after_end = move |key: &K| {
+        key.borrow() > prefix
+            && !key
+                .borrow()
+                .as_bytes()
+                .as_ref()
+                .starts_with(prefix.as_bytes().as_ref())
+    };
+    (is_before_start, is_after_end)
+}
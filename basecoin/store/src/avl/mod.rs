@@ -13,12 +13,21 @@
 //! For more info, see [AVL Tree on wikipedia](https://en.wikipedia.org/wiki/AVL_tree),
 
 mod as_bytes;
+mod encoding;
+mod iter;
 mod node;
 mod proof;
 mod tree;
 
 pub use as_bytes::{AsBytes, ByteSlice};
-pub use node::AvlNode;
+pub(crate) use encoding::{
+    decode_delta, decode_node, encode_delta, encode_node, index_nodes, read_version, take,
+    NodeIndex,
+};
+pub use encoding::{DecodeError, ENCODING_VERSION};
+pub(crate) use iter::{is_after, is_before};
+pub use iter::{IntoRange, Range};
+pub use node::{AvlNode, NodeRef};
 pub use proof::get_proof_spec;
 pub use tree::AvlTree;
 
@@ -1,5 +1,6 @@
 use core::borrow::Borrow;
 use core::mem;
+use std::sync::{Arc, OnceLock};
 
 use sha2::{Digest, Sha256};
 use tendermint::hash::Hash;
@@ -8,74 +9,114 @@ use super::proof::EMPTY_CHILD;
 use crate::avl::as_bytes::AsBytes;
 use crate::avl::{proof, HASH_ALGO};
 
-pub type NodeRef<T, V> = Option<Box<AvlNode<T, V>>>;
+/// A reference-counted pointer to a node, or `None` for an empty subtree.
+///
+/// Nodes are immutable once shared: mutating a node through [`Arc::make_mut`] clones it if other
+/// trees still reference it (copy-on-write), so that cloned trees share all unchanged subtrees.
+pub type NodeRef<T, V> = Option<Arc<AvlNode<T, V>>>;
 
 /// A node in the AVL Tree.
-#[derive(Eq, PartialEq, Debug, Clone)]
+///
+/// The hashes of a node are computed lazily: every mutation of a node ends with
+/// [`AvlNode::update`], which marks them dirty, and they are only computed, together with the
+/// dirty hashes of its subtrees, the first time they are needed, e.g. by [`AvlTree::root_hash`](super::AvlTree::root_hash) or by a
+/// proof. Nodes that are overwritten before then are never hashed.
+#[derive(Debug, Clone)]
 pub struct AvlNode<K: Ord, V> {
     pub key: K,
     pub value: V,
-    pub hash: Hash,
-    pub merkle_hash: Hash,
     pub height: u32,
     pub left: NodeRef<K, V>,
     pub right: NodeRef<K, V>,
+    /// The hashes of the node, or an empty cell if they are dirty.
+    hashes: OnceLock<NodeHashes>,
 }
 
+/// The hashes of a node.
+#[derive(Debug, Clone)]
+struct NodeHashes {
+    /// The hash of the key and value of the node.
+    hash: Hash,
+    /// The hash of the node and of its subtrees.
+    merkle_hash: Hash,
+}
+
+/// Nodes are compared by their contents, whether their hashes were computed or not.
+impl<K: Ord, V: PartialEq> PartialEq for AvlNode<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+            && self.value == other.value
+            && self.height == other.height
+            && self.left == other.left
+            && self.right == other.right
+    }
+}
+
+impl<K: Ord, V: Eq> Eq for AvlNode<K, V> {}
+
 /// Wrap a key + value couple into a `NodeRef`.
 #[allow(clippy::unnecessary_wraps)]
 pub fn as_node_ref<K: Ord + AsBytes, V>(key: K, value: V) -> NodeRef<K, V>
 where
     V: Borrow<[u8]>,
 {
-    Some(Box::new(AvlNode::new(key, value)))
+    Some(Arc::new(AvlNode::new(key, value)))
 }
 
 impl<K: Ord + AsBytes, V> AvlNode<K, V>
 where
     V: Borrow<[u8]>,
 {
-    fn new(key: K, value: V) -> Self {
-        let mut sha = Sha256::new();
-        sha.update(proof::LEAF_PREFIX);
-        sha.update(key.as_bytes().as_ref());
-        sha.update(value.borrow());
-        let hash = sha.finalize();
-
-        let mut sha = Sha256::new();
-        sha.update(EMPTY_CHILD);
-        sha.update(hash);
-        sha.update(EMPTY_CHILD);
-        let merkle_hash = sha.finalize();
-
-        let merkle_hash = Hash::from_bytes(HASH_ALGO, &merkle_hash).unwrap();
-        let hash = Hash::from_bytes(HASH_ALGO, &hash).unwrap();
-
+    pub(crate) fn new(key: K, value: V) -> Self {
         Self {
             key,
             value,
-            hash,
-            merkle_hash,
             height: 0,
             left: None,
             right: None,
+            hashes: OnceLock::new(),
         }
     }
 
     /// Set the value of the current node.
     pub(crate) fn set_value(&mut self, value: V) -> V {
-        let hash = Self::local_hash(&self.key, &value);
-        self.hash = hash;
+        self.hashes = OnceLock::new();
         mem::replace(&mut self.value, value)
     }
 
+    /// The hash of the key and value of this node.
+    pub fn hash(&self) -> &Hash {
+        &self.hashes().hash
+    }
+
+    /// The merkle hash of this node, which commits to its key, value and subtrees.
+    pub fn merkle_hash(&self) -> &Hash {
+        &self.hashes().merkle_hash
+    }
+
+    /// Returns the hashes of this node, computing them and the dirty hashes of its subtrees if
+    /// needed.
+    fn hashes(&self) -> &NodeHashes {
+        self.hashes.get_or_init(|| {
+            let hash = Self::local_hash(&self.key, &self.value);
+
+            let mut sha = Sha256::new();
+            sha.update(self.left_hash().unwrap_or(&EMPTY_CHILD));
+            sha.update(hash.as_bytes());
+            sha.update(self.right_hash().unwrap_or(&EMPTY_CHILD));
+            let merkle_hash = Hash::from_bytes(HASH_ALGO, sha.finalize().as_slice()).unwrap();
+
+            NodeHashes { hash, merkle_hash }
+        })
+    }
+
     /// The left height, or `None` if there is no left child.
-    fn left_height(&self) -> Option<u32> {
+    pub(crate) fn left_height(&self) -> Option<u32> {
         self.left.as_ref().map(|left| left.height)
     }
 
     /// The right height, or `None` if there is no right child.
-    fn right_height(&self) -> Option<u32> {
+    pub(crate) fn right_height(&self) -> Option<u32> {
         self.right.as_ref().map(|right| right.height)
     }
 
@@ -91,12 +132,12 @@ where
 
     /// The left merkle hash, if any
     pub fn left_hash(&self) -> Option<&[u8]> {
-        Some(self.left.as_ref()?.merkle_hash.as_bytes())
+        Some(self.left.as_ref()?.merkle_hash().as_bytes())
     }
 
     /// The right merkle hash, if any
     pub fn right_hash(&self) -> Option<&[u8]> {
-        Some(self.right.as_ref()?.merkle_hash.as_bytes())
+        Some(self.right.as_ref()?.merkle_hash().as_bytes())
     }
 
     /// Update the height of this node by looking at the height of its two children.
@@ -115,20 +156,11 @@ where
         }
     }
 
-    /// Update the node's merkle hash by looking at the hashes of its two children.
-    fn update_hashes(&mut self) {
-        let mut sha = Sha256::new();
-        sha.update(self.left_hash().unwrap_or(&EMPTY_CHILD));
-        sha.update(self.hash.as_bytes());
-        sha.update(self.right_hash().unwrap_or(&EMPTY_CHILD));
-        self.merkle_hash = Hash::from_bytes(HASH_ALGO, sha.finalize().as_slice()).unwrap();
-    }
-
-    /// Update node meta data, such as its height and merkle hash, by looking at its two
-    /// children.
+    /// Update node meta data after a mutation: its height is recomputed by looking at its two
+    /// children, and its hashes are marked dirty.
     pub fn update(&mut self) {
-        self.update_hashes();
         self.update_height();
+        self.hashes = OnceLock::new();
     }
 
     /// Returns the node's balance factor (left_height - right_height).
@@ -1,9 +1,16 @@
 //! # Test suite of tendermock AVL Tree.
 
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
 use ics23::commitment_proof::Proof;
-use ics23::{verify_membership, verify_non_membership, HostFunctionsManager};
+use ics23::{
+    is_compressed, verify_batch_membership, verify_batch_non_membership, verify_membership,
+    verify_non_membership, HostFunctionsManager,
+};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use sha2::{Digest, Sha256};
 
 use crate::avl::node::{as_node_ref, NodeRef};
@@ -86,6 +93,133 @@ fn test_shuffle_insert_get_remove() {
     assert!(tree.root.is_none());
 }
 
+#[test]
+fn structural_sharing() {
+    let mut tree = AvlTree::new();
+    for i in 0..=255u8 {
+        tree.insert([i], vec![i]);
+    }
+
+    let snapshot = tree.clone();
+    tree.insert([0], vec![42]);
+    assert_eq!(tree.remove([255]), Some(vec![255]));
+    assert_eq!(tree.remove([255]), None);
+
+    // the snapshot is unaffected by changes made to the tree
+    assert_eq!(snapshot.get(&[0]), Some(&vec![0]));
+    assert_eq!(snapshot.get(&[255]), Some(&vec![255]));
+    assert_eq!(tree.get(&[0]), Some(&vec![42]));
+    assert_eq!(tree.get(&[255]), None);
+    assert_ne!(tree.root_hash(), snapshot.root_hash());
+
+    // only the nodes on the modified paths were copied
+    let (root, snapshot_root) = (tree.root.as_ref().unwrap(), snapshot.root.as_ref().unwrap());
+    assert!(!Arc::ptr_eq(root, snapshot_root));
+    let (left, snapshot_left) = (
+        root.left.as_ref().unwrap(),
+        snapshot_root.left.as_ref().unwrap(),
+    );
+    assert!(Arc::ptr_eq(
+        left.right.as_ref().unwrap(),
+        snapshot_left.right.as_ref().unwrap()
+    ));
+    assert!(check_integrity(&tree.root));
+}
+
+#[test]
+fn range_iteration() {
+    let mut rng = thread_rng();
+    let mut tree = AvlTree::new();
+    let mut std_tree = BTreeMap::new();
+
+    let mut keys: Vec<u8> = (0..=255).step_by(2).collect();
+    keys.shuffle(&mut rng);
+    for &i in keys.iter() {
+        tree.insert([i], vec![i]);
+        std_tree.insert([i], vec![i]);
+    }
+
+    assert!(tree.iter().eq(std_tree.iter()));
+    assert!(tree.iter().rev().eq(std_tree.iter().rev()));
+
+    let random_bound = |rng: &mut rand::rngs::ThreadRng| match rng.gen_range(0..3) {
+        0 => Bound::Included([rng.gen()]),
+        1 => Bound::Excluded([rng.gen()]),
+        _ => Bound::Unbounded,
+    };
+    for _ in 0..500 {
+        let (start, end) = (random_bound(&mut rng), random_bound(&mut rng));
+        let std_range = match (start, end) {
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e))
+                if s > e || (s == e && start != Bound::Included(s)) =>
+            {
+                // BTreeMap panics on such ranges, they are empty
+                assert_eq!(tree.range((start, end)).next(), None);
+                continue;
+            }
+            _ => std_tree.range((start, end)),
+        };
+
+        assert!(tree.range((start, end)).eq(std_tree.range((start, end))));
+        assert!(tree.range((start, end)).rev().eq(std_range.clone().rev()));
+
+        // alternate between both ends
+        let mut range = tree.range((start, end));
+        let mut std_range = std_range;
+        loop {
+            let (next, std_next) = if rng.gen() {
+                (range.next(), std_range.next())
+            } else {
+                (range.next_back(), std_range.next_back())
+            };
+            assert_eq!(next, std_next);
+            if next.is_none() {
+                assert_eq!(range.next(), None);
+                assert_eq!(range.next_back(), None);
+                break;
+            }
+        }
+    }
+
+    for i in 0..=255u8 {
+        let next = std_tree
+            .range((Bound::Excluded([i]), Bound::Unbounded))
+            .next();
+        let prev = std_tree.range(..[i]).next_back();
+        assert_eq!(tree.next_key(&[i]), next.map(|(k, _)| k));
+        assert_eq!(tree.prev_key(&[i]), prev.map(|(k, _)| k));
+        assert!(tree.iter_from(&[i]).eq(std_tree.range([i]..)));
+    }
+
+    // an owning iterator is not affected by later changes to the tree
+    let snapshot = tree.clone().into_range::<[u8; 1], _>(..);
+    tree.insert([1], vec![1]);
+    assert!(snapshot.eq(std_tree.clone().into_iter()));
+}
+
+#[test]
+fn prefix_iteration() {
+    let keys = ["a", "a-b", "a/1", "a/2", "ab", "b", "b/a", "c"];
+    let mut tree = AvlTree::new();
+    for key in keys.iter().rev() {
+        tree.insert(*key, [0]);
+    }
+
+    let prefixed = |prefix: &str| {
+        keys.iter()
+            .copied()
+            .filter(|key| key.starts_with(prefix))
+            .collect::<Vec<_>>()
+    };
+    for prefix in ["", "a", "a/", "a-", "b", "b/", "bb", "c", "d", "0"] {
+        let found: Vec<_> = tree.iter_prefix(prefix).map(|(k, _)| *k).collect();
+        assert_eq!(found, prefixed(prefix), "prefix {prefix:?}");
+        let mut found: Vec<_> = tree.iter_prefix(prefix).rev().map(|(k, _)| *k).collect();
+        found.reverse();
+        assert_eq!(found, prefixed(prefix), "prefix {prefix:?}");
+    }
+}
+
 #[test]
 fn rotate_right() {
     let mut before = AvlTree {
@@ -161,7 +295,7 @@ fn proof() {
     sha.update(child_hash);
     sha.update(&inner_b.suffix);
     let inner_hash_b = sha.finalize();
-    assert_eq!(inner_hash_b.as_slice(), node_b.merkle_hash.as_bytes());
+    assert_eq!(inner_hash_b.as_slice(), node_b.merkle_hash().as_bytes());
     // Apply second inner node transformations
     let inner_a = &proof.path[1];
     let mut sha = Sha256::new();
@@ -169,7 +303,7 @@ fn proof() {
     sha.update(inner_hash_b);
     sha.update(&inner_a.suffix);
     let inner_hash_a = sha.finalize();
-    assert_eq!(inner_hash_a.as_slice(), node_a.merkle_hash.as_bytes());
+    assert_eq!(inner_hash_a.as_slice(), node_a.merkle_hash().as_bytes());
     // Check with ics32
     let spec = get_proof_spec();
     assert!(verify_membership::<HostFunctionsManager>(
@@ -224,6 +358,267 @@ fn integration() {
     }
 }
 
+#[test]
+fn batch_proof() {
+    let existing_keys = ["C", "E", "G", "I", "K", "M", "O", "Q", "S", "U"];
+    let non_existing_keys = ["A", "B", "D", "F", "H", "J", "L", "N", "P", "R", "T", "V"];
+
+    let mut tree = AvlTree::new();
+    for (i, &key) in existing_keys.iter().enumerate() {
+        tree.insert(key, [i as u8]);
+    }
+
+    let root = tree
+        .root_hash()
+        .expect("Unable to retrieve root hash")
+        .as_bytes()
+        .to_vec();
+    let spec = get_proof_spec();
+
+    let items: BTreeMap<&[u8], &[u8]> = existing_keys
+        .iter()
+        .map(|&key| (str::as_bytes(key), tree.get(key).unwrap().as_slice()))
+        .collect();
+    let absent: Vec<&[u8]> = non_existing_keys
+        .iter()
+        .map(|key| str::as_bytes(key))
+        .collect();
+    let keys = existing_keys
+        .iter()
+        .chain(non_existing_keys.iter())
+        .copied();
+
+    let proof = tree.get_batch_proof(keys.clone());
+    match &proof.proof {
+        Some(Proof::Batch(batch)) => {
+            assert_eq!(
+                batch.entries.len(),
+                existing_keys.len() + non_existing_keys.len()
+            )
+        }
+        _ => panic!("expected a batch proof"),
+    }
+    assert!(verify_batch_membership::<HostFunctionsManager>(
+        &proof,
+        &spec,
+        &root,
+        items.clone()
+    ));
+    assert!(verify_batch_non_membership::<HostFunctionsManager>(
+        &proof, &spec, &root, &absent
+    ));
+
+    let compressed = tree.get_compressed_batch_proof(keys);
+    assert!(is_compressed(&compressed));
+    assert!(verify_batch_membership::<HostFunctionsManager>(
+        &compressed,
+        &spec,
+        &root,
+        items.clone()
+    ));
+    assert!(verify_batch_non_membership::<HostFunctionsManager>(
+        &compressed,
+        &spec,
+        &root,
+        &absent
+    ));
+
+    // a batch proof doesn't prove other values for the same keys
+    let wrong_items = items.keys().map(|&key| (key, &[42u8][..])).collect();
+    assert!(!verify_batch_membership::<HostFunctionsManager>(
+        &proof,
+        &spec,
+        &root,
+        wrong_items
+    ));
+}
+
+#[test]
+fn encoding_round_trip() {
+    let mut rng = thread_rng();
+    let random_bytes = |rng: &mut rand::rngs::ThreadRng, max_len: usize| -> Vec<u8> {
+        let len = rng.gen_range(0..=max_len);
+        (0..len).map(|_| rng.gen()).collect()
+    };
+
+    for _ in 0..50 {
+        let mut tree: AvlTree<Vec<u8>, Vec<u8>> = AvlTree::new();
+        let mut keys = vec![];
+        for _ in 0..rng.gen_range(0..300) {
+            let key = random_bytes(&mut rng, 4);
+            tree.insert(key.clone(), random_bytes(&mut rng, 16));
+            keys.push(key);
+        }
+        for key in keys.choose_multiple(&mut rng, keys.len() / 3) {
+            tree.remove(key.clone());
+        }
+
+        let bytes = tree.encode();
+        let decoded = AvlTree::<Vec<u8>, Vec<u8>>::decode(&bytes).unwrap();
+        // trees compare their shapes, heights and hashes
+        assert_eq!(decoded, tree);
+        assert_eq!(decoded.root_hash(), tree.root_hash());
+        // the encoding is deterministic
+        assert_eq!(decoded.encode(), bytes);
+    }
+}
+
+#[test]
+fn malformed_encoding() {
+    let decode = |bytes: &[u8]| AvlTree::<Vec<u8>, Vec<u8>>::decode(bytes).unwrap_err();
+
+    let mut tree = AvlTree::new();
+    for i in 0..3u8 {
+        tree.insert(vec![i], vec![i]);
+    }
+    let bytes = tree.encode();
+
+    assert_eq!(decode(&[]), DecodeError::UnexpectedEnd);
+    assert_eq!(
+        decode(&bytes[..bytes.len() - 1]),
+        DecodeError::UnexpectedEnd
+    );
+    assert_eq!(decode(&[42, 0]), DecodeError::UnsupportedVersion(42));
+    assert_eq!(decode(&[ENCODING_VERSION, 2]), DecodeError::InvalidTag(2));
+    assert_eq!(
+        decode(&[&bytes[..], &[0]].concat()),
+        DecodeError::TrailingBytes(1)
+    );
+
+    // the root `[1]` with its children swapped
+    let leaf = |key: u8| [&[1, 0, 0, 0, 1, key, 0, 0, 0, 1, key][..], &[0, 0]].concat();
+    let swapped = [&bytes[..12], &leaf(2), &leaf(0)].concat();
+    assert_eq!(decode(&swapped), DecodeError::UnorderedKeys);
+}
+
+#[test]
+fn lazy_hashing() {
+    let mut rng = thread_rng();
+    let mut tree = AvlTree::new();
+    for round in 0..20 {
+        for _ in 0..50 {
+            let key = [rng.gen_range(0..=255u8)];
+            if rng.gen_bool(0.3) {
+                tree.remove(key);
+            } else {
+                tree.insert(key, vec![rng.gen(), round]);
+            }
+        }
+        // the hashes computed on demand are the ones of a full recomputation, whether they were
+        // partially cached by previous rounds or not
+        assert_eq!(
+            tree.root_hash().map(|hash| hash.as_bytes().to_vec()),
+            eager_merkle_hash(&tree.root)
+        );
+    }
+}
+
+#[test]
+fn model_based() {
+    let mut rng = thread_rng();
+    let spec = get_proof_spec();
+    let keys: Vec<[u8; 1]> = (0..16).map(|i| [i]).collect();
+
+    for _ in 0..8 {
+        let mut tree = AvlTree::new();
+        let mut model = BTreeMap::new();
+        for _ in 0..100 {
+            let key = *keys.choose(&mut rng).unwrap();
+            if rng.gen_bool(0.35) {
+                assert_eq!(tree.remove(key), model.remove(&key));
+            } else {
+                let value = vec![rng.gen()];
+                assert_eq!(tree.insert(key, value.clone()), model.insert(key, value));
+            }
+
+            assert!(check_integrity(&tree.root));
+            let entries: Vec<_> = tree.iter().map(|(k, v)| (*k, v.clone())).collect();
+            let model_entries: Vec<_> = model.iter().map(|(k, v)| (*k, v.clone())).collect();
+            assert_eq!(entries, model_entries);
+
+            let Some(root) = tree.root_hash() else {
+                continue;
+            };
+            let root = root.as_bytes().to_vec();
+            for key in &keys {
+                let proof = tree.get_proof(key);
+                match model.get(key) {
+                    Some(value) => assert!(verify_membership::<HostFunctionsManager>(
+                        &proof, &spec, &root, key, value
+                    )),
+                    None => assert!(verify_non_membership::<HostFunctionsManager>(
+                        &proof, &spec, &root, key
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// A removal can unbalance a node whose higher child has children of the same height, which a
+/// single rotation rebalances while a double rotation would leave the subtree unbalanced.
+#[test]
+fn remove_rebalancing() {
+    let mut tree = AvlTree::new();
+    for key in [0u8, 6, 15, 10, 13, 3, 7, 9, 12, 8, 1] {
+        tree.insert([key], vec![key]);
+    }
+    assert!(check_integrity(&tree.root));
+    assert_eq!(tree.remove([15]), Some(vec![15]));
+    assert!(check_integrity(&tree.root));
+}
+
+/// Root hashes of trees built by insertions only, recorded before rebalancing after removals
+/// was changed. Insertions never take the rotations that changed, so these hashes must not
+/// change either.
+#[test]
+fn insert_only_root_hashes() {
+    let cases: [(Vec<_>, &str); 4] = [
+        (
+            vec![(b"a".to_vec(), b"1".to_vec())],
+            "207c21f613a5553c9e4bdbe2650296203fba3c3307fe789c226126a04bdb8b5f",
+        ),
+        (
+            (0..16).map(|i| (vec![i], vec![i])).collect(),
+            "e2349a6617b1b8ba2368f00e1fca427474c82079f3b70bfc96d143d771d034f7",
+        ),
+        (
+            (0u32..64)
+                .map(|i| {
+                    let key = (i * 37 % 64) as u8;
+                    (vec![key], vec![key, key])
+                })
+                .collect(),
+            "9430742e259cfe631cb2f356ed5d33ecce429c9beff5b2ace540ca213e9fe53b",
+        ),
+        (
+            (0..100)
+                .rev()
+                .map(|i| {
+                    (
+                        format!("key/{i}").into_bytes(),
+                        format!("value-{i}").into_bytes(),
+                    )
+                })
+                .collect(),
+            "de9a4ffaae6e467300fe7dcb21341875cd29c7dd2e92d00af4470e30e2d172e3",
+        ),
+    ];
+
+    for (entries, expected) in cases {
+        let mut tree = AvlTree::new();
+        for (key, value) in entries {
+            tree.insert(key, value);
+        }
+        let expected: Vec<u8> = (0..expected.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&expected[i..i + 2], 16).unwrap())
+            .collect();
+        let root_hash = tree.root_hash().unwrap();
+        assert_eq!(root_hash.as_bytes(), expected);
+    }
+}
+
 /// Check that nodes are ordered, heights are correct and that balance factors are in {-1, 0, 1}.
 fn check_integrity<T: Ord, V>(node_ref: &NodeRef<T, V>) -> bool {
     if let Some(node) = node_ref {
@@ -273,9 +668,25 @@ fn build_node<T: Ord + AsBytes>(
     left: NodeRef<T, [u8; 1]>,
     right: NodeRef<T, [u8; 1]>,
 ) -> NodeRef<T, [u8; 1]> {
-    let mut node = as_node_ref(key, value).unwrap();
+    let mut node = AvlNode::new(key, value);
     node.left = left;
     node.right = right;
     node.update();
-    Some(node)
+    Some(Arc::new(node))
+}
+
+/// Recursively compute the merkle hash of a node without using the cached hashes.
+fn eager_merkle_hash<T: Ord + AsBytes>(node_ref: &NodeRef<T, Vec<u8>>) -> Option<Vec<u8>> {
+    let node = node_ref.as_ref()?;
+    let mut sha = Sha256::new();
+    sha.update(crate::avl::proof::LEAF_PREFIX);
+    sha.update(node.key.as_bytes());
+    sha.update(&node.value);
+    let hash = sha.finalize();
+
+    let mut sha = Sha256::new();
+    sha.update(eager_merkle_hash(&node.left).unwrap_or(vec![0; 32]));
+    sha.update(hash);
+    sha.update(eager_merkle_hash(&node.right).unwrap_or(vec![0; 32]));
+    Some(sha.finalize().to_vec())
 }
@@ -1,10 +1,16 @@
 use core::borrow::Borrow;
 use core::cmp::Ordering;
+use core::ops::{Bound, RangeBounds};
+use std::sync::Arc;
 
 use ics23::commitment_proof::Proof;
-use ics23::{CommitmentProof, ExistenceProof, HashOp, InnerOp, NonExistenceProof};
+use ics23::{
+    batch_entry, BatchEntry, BatchProof, CommitmentProof, ExistenceProof, HashOp, InnerOp,
+    NonExistenceProof,
+};
 use tendermint::hash::Hash;
 
+use super::iter::{is_after, is_before, prefix_bounds, IntoRange, Range, RawRange};
 use super::proof::{get_leaf_op, EMPTY_CHILD};
 use super::AvlNode;
 use crate::avl::node::{as_node_ref, NodeRef};
@@ -12,12 +18,15 @@ use crate::avl::AsBytes;
 
 /// An AVL Tree that supports `get` and `insert` operation and can be used to prove existence of a
 /// given key-value couple.
+///
+/// Nodes are reference-counted and updated with copy-on-write, so cloning a tree is O(1) and a
+/// clone only copies the O(log n) nodes on the paths that are modified afterwards.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct AvlTree<K: Ord + AsBytes, V> {
     pub root: NodeRef<K, V>,
 }
 
-impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
+impl<K: Ord + AsBytes + Clone, V: Borrow<[u8]> + Clone> AvlTree<K, V> {
     /// Return an empty AVL tree.
     pub fn new() -> Self {
         Self { root: None }
@@ -25,7 +34,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
 
     /// Return the hash of the merkle tree root, if it has at least one node.
     pub fn root_hash(&self) -> Option<&Hash> {
-        Some(&self.root.as_ref()?.merkle_hash)
+        Some(self.root.as_ref()?.merkle_hash())
     }
 
     /// Return the value corresponding to the key, if it exists.
@@ -45,6 +54,100 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
         None
     }
 
+    /// Return an iterator over all the entries of the tree, in ascending key order.
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range::<K, _>(..)
+    }
+
+    /// Return an iterator over the entries whose keys are within `range`, in ascending key
+    /// order. Creating the iterator and each iteration step run in O(log(n)).
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range(RawRange::new(
+            self.root.as_deref(),
+            |key: &K| is_before(range.start_bound(), key.borrow()),
+            |key: &K| is_after(range.end_bound(), key.borrow()),
+        ))
+    }
+
+    /// Return an iterator over the entries whose keys are greater than or equal to `key`, in
+    /// ascending key order.
+    pub fn iter_from<Q>(&self, key: &Q) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range((Bound::Included(key), Bound::Unbounded))
+    }
+
+    /// Return an iterator over the entries whose keys start with the bytes of `prefix`, in
+    /// ascending key order.
+    ///
+    /// The keys starting with `prefix` are expected to be contiguous in key order, which is the
+    /// case for byte strings and [`Path`](crate::types::Path)s.
+    pub fn iter_prefix<Q>(&self, prefix: &Q) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + AsBytes + ?Sized,
+    {
+        let (is_before_start, is_after_end) = prefix_bounds(prefix);
+        Range(RawRange::new(
+            self.root.as_deref(),
+            is_before_start,
+            is_after_end,
+        ))
+    }
+
+    /// Return the smallest key strictly greater than `key`, if any.
+    pub fn next_key<Q>(&self, key: &Q) -> Option<&K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range((Bound::Excluded(key), Bound::Unbounded))
+            .next()
+            .map(|(key, _)| key)
+    }
+
+    /// Return the greatest key strictly smaller than `key`, if any.
+    pub fn prev_key<Q>(&self, key: &Q) -> Option<&K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range((Bound::Unbounded, Bound::Excluded(key)))
+            .next_back()
+            .map(|(key, _)| key)
+    }
+
+    /// Same as [`Self::range`], but the returned iterator takes ownership of the tree.
+    pub fn into_range<Q, R>(self, range: R) -> IntoRange<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        IntoRange(RawRange::new(
+            self.root,
+            |key: &K| is_before(range.start_bound(), key.borrow()),
+            |key: &K| is_after(range.end_bound(), key.borrow()),
+        ))
+    }
+
+    /// Same as [`Self::iter_prefix`], but the returned iterator takes ownership of the tree.
+    pub fn into_iter_prefix<Q>(self, prefix: &Q) -> IntoRange<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + AsBytes + ?Sized,
+    {
+        let (is_before_start, is_after_end) = prefix_bounds(prefix);
+        IntoRange(RawRange::new(self.root, is_before_start, is_after_end))
+    }
+
     /// Insert a value into the AVL tree, this operation runs in amortized O(log(n)).
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         let node_ref = &mut self.root;
@@ -54,6 +157,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
     /// Insert a value in the tree.
     fn insert_rec(node_ref: &mut NodeRef<K, V>, key: K, value: V) -> Option<V> {
         if let Some(node) = node_ref {
+            let node = Arc::make_mut(node);
             let old_value = match node.key.cmp(&key) {
                 Ordering::Greater => Self::insert_rec(&mut node.left, key, value),
                 Ordering::Less => Self::insert_rec(&mut node.right, key, value),
@@ -72,15 +176,18 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
 
     /// Remove a value from the AVL tree, this operation runs in amortized O(log(n)).
     pub fn remove(&mut self, key: K) -> Option<V> {
+        // avoid copying the nodes on the search path if there is nothing to remove
+        self.get(&key)?;
         let node_ref = &mut self.root;
-        Self::remove_rec(node_ref, key).map(|node| node.value)
+        Self::remove_rec(node_ref, key)
+            .map(|node| Arc::try_unwrap(node).map_or_else(|node| node.value.clone(), |n| n.value))
     }
 
     /// Remove a value from the tree.
     ///
     /// Find the sub-tree whose root to be removed. Then, use [`Self::remove_root`].
     fn remove_rec(node_ref: &mut NodeRef<K, V>, key: K) -> NodeRef<K, V> {
-        let node = node_ref.as_deref_mut()?;
+        let node = Arc::make_mut(node_ref.as_mut()?);
 
         let removed_value = match node.key.cmp(&key) {
             Ordering::Greater => Self::remove_rec(&mut node.left, key),
@@ -93,7 +200,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
                 // need to update, as root node is updated
                 // Note: if removed_value is None, nothing is removed.
                 // So no need to update and balance.
-                node.update();
+                Arc::make_mut(node).update();
                 Self::balance_node(node_ref);
             }
         }
@@ -114,7 +221,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
     ///
     /// Never called on an empty tree.
     fn remove_root(node_ref: &mut NodeRef<K, V>) -> NodeRef<K, V> {
-        let node = node_ref.as_deref_mut()?;
+        let node = Arc::make_mut(node_ref.as_mut()?);
 
         let substitute_node_ref = if node.right.is_none() {
             // there is no right node, replace the root node with the left node.
@@ -133,7 +240,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
             let mut leftmost_node_ref = Self::remove_leftmost(&mut node.right);
             // leftmost_node_ref.right <- node_ref.right
             // leftmost_node_ref.left <- node_ref.left
-            if let Some(leftmost_node) = leftmost_node_ref.as_mut() {
+            if let Some(leftmost_node) = leftmost_node_ref.as_mut().map(Arc::make_mut) {
                 // removed leftmost node must be a leaf; it is an invariant.
                 // assert!(leftmost_node.right.is_none() && leftmost_node.left.is_none());
 
@@ -152,7 +259,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
             let mut rightmost_node_ref = Self::remove_rightmost(&mut node.left);
             // rightmost_node_ref.right <- node_ref.right
             // rightmost_node_ref.left <- node_ref.left
-            if let Some(rightmost_node) = rightmost_node_ref.as_mut() {
+            if let Some(rightmost_node) = rightmost_node_ref.as_mut().map(Arc::make_mut) {
                 // removed rightmost node must be a leaf; it is an invariant.
                 // assert!(rightmost_node.right.is_none() && rightmost_node.left.is_none());
 
@@ -168,7 +275,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
 
         if let Some(node) = node_ref {
             // need to update, as top node is replaced
-            node.update();
+            Arc::make_mut(node).update();
             Self::balance_node(node_ref);
         }
 
@@ -177,7 +284,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
 
     /// Removes the leftmost key in the tree, if it exists.
     fn remove_leftmost(node_ref: &mut NodeRef<K, V>) -> NodeRef<K, V> {
-        let node = node_ref.as_deref_mut()?;
+        let node = Arc::make_mut(node_ref.as_mut()?);
 
         if node.left.is_none() {
             let right_node = node.right.take();
@@ -198,7 +305,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
 
     /// Removes the rightmost key in the tree, if it exists.
     fn remove_rightmost(node_ref: &mut NodeRef<K, V>) -> NodeRef<K, V> {
-        let node = node_ref.as_deref_mut()?;
+        let node = Arc::make_mut(node_ref.as_mut()?);
 
         if node.right.is_none() {
             let left_node = node.left.take();
@@ -228,6 +335,42 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
         CommitmentProof { proof: Some(proof) }
     }
 
+    /// Return a batch proof made of an existence or non-existence proof for each of the given
+    /// keys, in the order of the keys.
+    pub fn get_batch_proof<'q, Q>(&self, keys: impl IntoIterator<Item = &'q Q>) -> CommitmentProof
+    where
+        K: Borrow<Q>,
+        Q: Ord + AsBytes + ?Sized + 'q,
+    {
+        let entries = keys
+            .into_iter()
+            .map(|key| {
+                let proof = match Self::get_proof_rec(key, &self.root) {
+                    Proof::Exist(proof) => batch_entry::Proof::Exist(proof),
+                    Proof::Nonexist(proof) => batch_entry::Proof::Nonexist(proof),
+                    _ => unreachable!(),
+                };
+                BatchEntry { proof: Some(proof) }
+            })
+            .collect();
+        CommitmentProof {
+            proof: Some(Proof::Batch(BatchProof { entries })),
+        }
+    }
+
+    /// Return a compressed batch proof for the given keys, in which the inner nodes shared by
+    /// the proofs of several keys are only included once.
+    pub fn get_compressed_batch_proof<'q, Q>(
+        &self,
+        keys: impl IntoIterator<Item = &'q Q>,
+    ) -> CommitmentProof
+    where
+        K: Borrow<Q>,
+        Q: Ord + AsBytes + ?Sized + 'q,
+    {
+        ics23::compress(&self.get_batch_proof(keys))
+    }
+
     fn get_local_existence_proof(node: &AvlNode<K, V>) -> ExistenceProof {
         ExistenceProof {
             key: node.key.as_bytes().as_ref().to_owned(),
@@ -252,7 +395,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
                 Ordering::Greater => {
                     let prefix = vec![];
                     let mut suffix = Vec::with_capacity(64);
-                    suffix.extend(node.hash.as_bytes());
+                    suffix.extend(node.hash().as_bytes());
                     suffix.extend(node.right_hash().unwrap_or(&EMPTY_CHILD));
                     let inner = InnerOp {
                         hash: HashOp::Sha256.into(),
@@ -286,7 +429,7 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
                     let suffix = vec![];
                     let mut prefix = Vec::with_capacity(64);
                     prefix.extend(node.left_hash().unwrap_or(&EMPTY_CHILD));
-                    prefix.extend(node.hash.as_bytes());
+                    prefix.extend(node.hash().as_bytes());
                     let inner = InnerOp {
                         hash: HashOp::Sha256.into(),
                         prefix,
@@ -333,20 +476,23 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
             .expect("[AVL]: Empty node in node balance");
         let balance_factor = node.balance_factor();
         if balance_factor >= 2 {
+            let node = Arc::make_mut(node);
             let left = node
                 .left
-                .as_mut()
+                .as_ref()
                 .expect("[AVL]: Unexpected empty left node");
-            if left.balance_factor() < 1 {
+            // a double rotation is needed if the inner grandchild is the highest one
+            if left.right_height() > left.left_height() {
                 Self::rotate_left(&mut node.left);
             }
             Self::rotate_right(node_ref);
         } else if balance_factor <= -2 {
+            let node = Arc::make_mut(node);
             let right = node
                 .right
-                .as_mut()
+                .as_ref()
                 .expect("[AVL]: Unexpected empty right node");
-            if right.balance_factor() > -1 {
+            if right.left_height() > right.right_height() {
                 Self::rotate_right(&mut node.right);
             }
             Self::rotate_left(node_ref);
@@ -356,25 +502,33 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
     /// Performs a right rotation.
     pub fn rotate_right(root: &mut NodeRef<K, V>) {
         let mut node = root.take().expect("[AVL]: Empty root in right rotation");
-        let mut left = node.left.take().expect("[AVL]: Unexpected right rotation");
-        let mut left_right = left.right.take();
-        core::mem::swap(&mut node.left, &mut left_right);
-        node.update();
-        core::mem::swap(&mut left.right, &mut Some(node));
-        left.update();
-        core::mem::swap(root, &mut Some(left));
+        let node_mut = Arc::make_mut(&mut node);
+        let mut left = node_mut
+            .left
+            .take()
+            .expect("[AVL]: Unexpected right rotation");
+        let left_mut = Arc::make_mut(&mut left);
+        node_mut.left = left_mut.right.take();
+        node_mut.update();
+        left_mut.right = Some(node);
+        left_mut.update();
+        *root = Some(left);
     }
 
     /// Perform a left rotation.
     pub fn rotate_left(root: &mut NodeRef<K, V>) {
         let mut node = root.take().expect("[AVL]: Empty root in left rotation");
-        let mut right = node.right.take().expect("[AVL]: Unexpected left rotation");
-        let mut right_left = right.left.take();
-        core::mem::swap(&mut node.right, &mut right_left);
-        node.update();
-        core::mem::swap(&mut right.left, &mut Some(node));
-        right.update();
-        core::mem::swap(root, &mut Some(right))
+        let node_mut = Arc::make_mut(&mut node);
+        let mut right = node_mut
+            .right
+            .take()
+            .expect("[AVL]: Unexpected left rotation");
+        let right_mut = Arc::make_mut(&mut right);
+        node_mut.right = right_mut.left.take();
+        node_mut.update();
+        right_mut.left = Some(node);
+        right_mut.update();
+        *root = Some(right);
     }
 
     /// Return a list of the keys present in the tree.
@@ -395,6 +549,6 @@ impl<K: Ord + AsBytes, V: Borrow<[u8]>> AvlTree<K, V> {
 
 impl<K: Ord + AsBytes, V: Borrow<[u8]>> Default for AvlTree<K, V> {
     fn default() -> Self {
-        Self::new()
+        Self { root: None }
     }
 }
@@ -1,10 +1,17 @@
 use core::fmt::Debug;
+use core::ops::RangeBounds;
 
 use ics23::CommitmentProof;
 
-use crate::types::{Height, Path, RawHeight};
+use crate::types::change::diff_entries;
+use crate::types::{ChangeSet, Height, Path, RawHeight};
 use crate::utils::Async;
 
+/// An owned iterator over the entries of a store, in ascending key order.
+///
+/// Use [`Iterator::rev`] to iterate in descending order.
+pub type StoreIter = Box<dyn DoubleEndedIterator<Item = (Path, Vec<u8>)> + Send>;
+
 /// Store trait - maybe provableStore or privateStore
 pub trait Store: Async + Clone {
     /// Error type - expected to envelope all possible errors in store
@@ -17,8 +24,7 @@ pub trait Store: Async + Clone {
     fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>>;
 
     /// Delete specified `path`
-    // TODO(rano): return Result to denote success or failure
-    fn delete(&mut self, path: &Path);
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error>;
 
     /// Commit `Pending` block to canonical chain and create new `Pending`
     fn commit(&mut self) -> Result<Vec<u8>, Self::Error>;
@@ -36,11 +42,60 @@ pub trait Store: Async + Clone {
         Ok(height)
     }
 
+    /// Prune the historic block at specified `height` only, keeping the blocks around it. The
+    /// latest block is never pruned.
+    fn prune_at(&mut self, _height: RawHeight) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     /// Return the current height of the chain
     fn current_height(&self) -> RawHeight;
 
-    /// Return all keys that start with specified prefix
-    fn get_keys(&self, key_prefix: &Path) -> Vec<Path>; // TODO(hu55a1n1): implement support for all heights
+    /// Return a lazy iterator over the entries whose keys are within `range` at specified
+    /// `height`
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter;
+
+    /// Return a lazy iterator over the entries whose keys start with specified prefix at
+    /// specified `height`
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter;
+
+    /// Return the changes that turn the state at height `from` into the state at height `to`,
+    /// or `None` if the store doesn't hold the state at either height
+    ///
+    /// The default implementation compares the entries yielded by [`Self::range_at`], so it
+    /// reads the states of heights that the store doesn't hold as empty.
+    fn diff(&self, from: Height, to: Height) -> Option<ChangeSet> {
+        Some(diff_entries(self.range_at(from, ..), self.range_at(to, ..)))
+    }
+
+    /// Return the changes committed at specified `height`, i.e. since the previous height
+    fn change_set(&self, height: RawHeight) -> Option<ChangeSet> {
+        let previous = height.checked_sub(1)?;
+        self.diff(Height::Stable(previous), Height::Stable(height))
+    }
+
+    /// Return all keys that start with specified prefix at specified `height`
+    fn get_keys_at(&self, height: Height, key_prefix: &Path) -> Vec<Path> {
+        self.iter_prefix_at(height, key_prefix)
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Return all keys that start with specified prefix in the `Pending` state
+    fn get_keys(&self, key_prefix: &Path) -> Vec<Path> {
+        self.get_keys_at(Height::Pending, key_prefix)
+    }
+
+    /// Return an immutable snapshot of the committed heights, or `None` if the store doesn't
+    /// support snapshots
+    ///
+    /// The snapshot answers reads at `Stable` and `Latest` heights like this store, but doesn't
+    /// hold its working state nor its change sets. Taking a snapshot must be cheap, as
+    /// [`SharedStore`](crate::impls::SharedStore) takes one after every commit to serve reads of
+    /// committed heights without locking the store.
+    fn snapshot(&self) -> Option<Self> {
+        None
+    }
 }
 
 /// ProvableStore trait
@@ -48,6 +103,22 @@ pub trait ProvableStore: Store {
     /// Return a vector commitment
     fn root_hash(&self) -> Vec<u8>;
 
+    /// Return the vector commitment of the pending state, which is the one that committing the
+    /// store would return
+    fn working_hash(&self) -> Vec<u8>;
+
+    /// Return the vector commitment at specified height, or `None` if the height isn't retained
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>>;
+
     /// Return proof of existence for key
     fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof>;
+
+    /// Return a batch proof of existence or non-existence for each of the keys
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof>;
+
+    /// Return a compressed batch proof of existence or non-existence for each of the keys
+    fn get_compressed_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        self.get_batch_proof(height, keys)
+            .map(|proof| ics23::compress(&proof))
+    }
 }
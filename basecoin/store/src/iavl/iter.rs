@@ -0,0 +1,167 @@
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::ops::Bound;
+use std::sync::Arc;
+
+use crate::avl::{is_after, is_before, AsBytes};
+use crate::iavl::node::{IavlNode, NodeRef};
+
+/// A double-ended in-order traversal of the leaves within a range of keys, compared as bytes.
+///
+/// Each stack holds the next leaf to be yielded from its end on top, below the subtrees that
+/// remain to be traversed from that end, so that walking the range costs O(log n) per step.
+#[derive(Clone, Debug)]
+pub(crate) struct RawRange<K, V> {
+    front: Vec<Arc<IavlNode<K, V>>>,
+    back: Vec<Arc<IavlNode<K, V>>>,
+    done: bool,
+}
+
+impl<K: AsBytes, V: Borrow<[u8]>> RawRange<K, V> {
+    pub(crate) fn new(root: NodeRef<K, V>, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Self {
+        let mut front = vec![];
+        let mut node_ref = root.clone();
+        while let Some(node) = node_ref {
+            if node.is_leaf() {
+                if !is_before(start, node.key.as_bytes().as_ref()) {
+                    front.push(node);
+                }
+                break;
+            }
+            // the keys of the left subtree are smaller than the node's key
+            let skip_left = match start {
+                Bound::Included(start) | Bound::Excluded(start) => {
+                    node.key.as_bytes().as_ref() <= start
+                }
+                Bound::Unbounded => false,
+            };
+            if skip_left {
+                node_ref = node.right.clone();
+            } else {
+                node_ref = node.left.clone();
+                front.extend(node.right.clone());
+            }
+        }
+
+        let mut back = vec![];
+        let mut node_ref = root;
+        while let Some(node) = node_ref {
+            if node.is_leaf() {
+                if !is_after(end, node.key.as_bytes().as_ref()) {
+                    back.push(node);
+                }
+                break;
+            }
+            // the keys of the right subtree are not smaller than the node's key
+            if is_after(end, node.key.as_bytes().as_ref()) {
+                node_ref = node.left.clone();
+            } else {
+                node_ref = node.right.clone();
+                back.extend(node.left.clone());
+            }
+        }
+
+        let mut range = Self {
+            front,
+            back,
+            done: false,
+        };
+        range.descend_front();
+        range.descend_back();
+        range
+    }
+
+    /// Replaces the subtree on top of the front stack by the path to its leftmost leaf.
+    fn descend_front(&mut self) {
+        while self.front.last().is_some_and(|node| !node.is_leaf()) {
+            let node = self.front.pop().unwrap();
+            self.front.extend(node.right.clone());
+            self.front.extend(node.left.clone());
+        }
+    }
+
+    /// Replaces the subtree on top of the back stack by the path to its rightmost leaf.
+    fn descend_back(&mut self) {
+        while self.back.last().is_some_and(|node| !node.is_leaf()) {
+            let node = self.back.pop().unwrap();
+            self.back.extend(node.left.clone());
+            self.back.extend(node.right.clone());
+        }
+    }
+
+    /// Checks whether the next leaves of both ends haven't crossed, and marks the traversal as
+    /// done if they are the same leaf.
+    fn check_remaining(&mut self) -> bool {
+        if self.done {
+            return false;
+        }
+        let (Some(front), Some(back)) = (self.front.last(), self.back.last()) else {
+            self.done = true;
+            return false;
+        };
+        match front
+            .key
+            .as_bytes()
+            .as_ref()
+            .cmp(back.key.as_bytes().as_ref())
+        {
+            Ordering::Less => true,
+            Ordering::Equal => {
+                self.done = true;
+                true
+            }
+            Ordering::Greater => {
+                self.done = true;
+                false
+            }
+        }
+    }
+
+    pub(crate) fn next(&mut self) -> Option<Arc<IavlNode<K, V>>> {
+        if !self.check_remaining() {
+            return None;
+        }
+        let leaf = self.front.pop()?;
+        self.descend_front();
+        Some(leaf)
+    }
+
+    pub(crate) fn next_back(&mut self) -> Option<Arc<IavlNode<K, V>>> {
+        if !self.check_remaining() {
+            return None;
+        }
+        let leaf = self.back.pop()?;
+        self.descend_back();
+        Some(leaf)
+    }
+}
+
+/// An owning iterator over a range of entries of an [`IavlTree`](super::IavlTree), in ascending
+/// order of the key bytes. Use [`Iterator::rev`] to iterate in descending order.
+///
+/// The iterator holds references to the nodes of the tree it was created from, so it is not
+/// affected by later changes to that tree.
+#[derive(Clone, Debug)]
+pub struct IntoRange<K, V>(pub(crate) RawRange<K, V>);
+
+impl<K: AsBytes + Clone, V: Borrow<[u8]> + Clone> Iterator for IntoRange<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|leaf| leaf_entry(&leaf))
+    }
+}
+
+impl<K: AsBytes + Clone, V: Borrow<[u8]> + Clone> DoubleEndedIterator for IntoRange<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|leaf| leaf_entry(&leaf))
+    }
+}
+
+fn leaf_entry<K: Clone, V: Clone>(leaf: &IavlNode<K, V>) -> (K, V) {
+    let value = leaf
+        .value
+        .clone()
+        .expect("[IAVL]: Iterated over an inner node");
+    (leaf.key.clone(), value)
+}
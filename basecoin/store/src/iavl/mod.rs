@@ -0,0 +1,28 @@
+//! # IAVL Tree
+//!
+//! This module hosts an implementation of the Merkle AVL+ tree used by the Cosmos SDK (IAVL).
+//! Unlike the tree of the `avl` module, values are only stored in leaves, and nodes are hashed
+//! along with their height, size and version, as IAVL does. Proofs of existence and
+//! non-existence therefore verify with the standard ICS23 `IavlSpec` (see [`get_proof_spec`]),
+//! which counterparty chains running ibc-go support out of the box.
+//!
+//! Keys needs to implement `AsBytes` and are ordered by their bytes, while values are required to
+//! implement `Borrow<[u8]>`.
+//!
+//! For more info, see the [IAVL specification](https://github.com/cosmos/iavl/blob/master/docs/node/node.md).
+
+mod iter;
+mod node;
+mod proof;
+mod tree;
+
+pub use iter::IntoRange;
+pub use node::{IavlNode, NodeRef};
+pub use proof::{get_leaf_op, get_proof_spec};
+pub use tree::IavlTree;
+
+#[cfg(test)]
+mod tests;
+
+use tendermint::hash::Algorithm;
+const HASH_ALGO: Algorithm = Algorithm::Sha256;
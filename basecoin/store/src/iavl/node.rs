@@ -0,0 +1,129 @@
+use core::borrow::Borrow;
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use tendermint::hash::Hash;
+
+use super::proof::{encode_bytes, node_header};
+use crate::avl::AsBytes;
+use crate::iavl::HASH_ALGO;
+
+/// A reference-counted pointer to a node, or `None` for an empty tree.
+///
+/// As in the AVL tree, nodes are updated with copy-on-write through [`Arc::make_mut`], so that
+/// the versions of a tree share all their unchanged subtrees.
+pub type NodeRef<K, V> = Option<Arc<IavlNode<K, V>>>;
+
+/// A node in the IAVL Tree.
+///
+/// Values are only stored in leaves. Inner nodes always have two children, and their key is the
+/// smallest key of their right subtree.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct IavlNode<K, V> {
+    pub key: K,
+    /// The value of a leaf, or `None` for an inner node.
+    pub value: Option<V>,
+    /// The version at which the node was last written.
+    pub version: i64,
+    /// The height of the subtree, `0` for leaves.
+    pub height: u32,
+    /// The number of leaves in the subtree.
+    pub size: i64,
+    pub hash: Hash,
+    pub left: NodeRef<K, V>,
+    pub right: NodeRef<K, V>,
+}
+
+impl<K: AsBytes, V: Borrow<[u8]>> IavlNode<K, V> {
+    /// Create a leaf written at specified version.
+    pub(crate) fn leaf(key: K, value: V, version: i64) -> Self {
+        let mut node = Self {
+            key,
+            value: Some(value),
+            version,
+            height: 0,
+            size: 1,
+            hash: Hash::None,
+            left: None,
+            right: None,
+        };
+        node.update_hash();
+        node
+    }
+
+    /// Create an inner node written at specified version, where `key` is the smallest key of
+    /// the `right` subtree.
+    pub(crate) fn inner(key: K, left: Arc<Self>, right: Arc<Self>, version: i64) -> Self {
+        let mut node = Self {
+            key,
+            value: None,
+            version,
+            height: 0,
+            size: 0,
+            hash: Hash::None,
+            left: Some(left),
+            right: Some(right),
+        };
+        node.update(version);
+        node
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.value.is_some()
+    }
+
+    /// Set the value of a leaf, and return the previous value.
+    pub(crate) fn set_value(&mut self, value: V, version: i64) -> Option<V> {
+        let previous = self.value.replace(value);
+        self.version = version;
+        self.update_hash();
+        previous
+    }
+
+    /// The merkle hash of the left child, if any.
+    pub fn left_hash(&self) -> Option<&[u8]> {
+        Some(self.left.as_ref()?.hash.as_bytes())
+    }
+
+    /// The merkle hash of the right child, if any.
+    pub fn right_hash(&self) -> Option<&[u8]> {
+        Some(self.right.as_ref()?.hash.as_bytes())
+    }
+
+    /// Update the node's version, height, size and hash by looking at its two children.
+    pub(crate) fn update(&mut self, version: i64) {
+        let (Some(left), Some(right)) = (&self.left, &self.right) else {
+            panic!("[IAVL]: Inner node without two children");
+        };
+        self.version = version;
+        self.height = core::cmp::max(left.height, right.height) + 1;
+        self.size = left.size + right.size;
+        self.update_hash();
+    }
+
+    /// Compute the node's hash as IAVL does, i.e. the SHA-256 hash of the node's height, size
+    /// and version, followed by the key and the hash of the value for a leaf, or by the hashes
+    /// of the children for an inner node, all length-prefixed.
+    fn update_hash(&mut self) {
+        let mut bytes = node_header(self.height, self.size, self.version);
+        match &self.value {
+            Some(value) => {
+                encode_bytes(self.key.as_bytes().as_ref(), &mut bytes);
+                encode_bytes(&Sha256::digest(value.borrow()), &mut bytes);
+            }
+            None => {
+                encode_bytes(self.left_hash().unwrap_or_default(), &mut bytes);
+                encode_bytes(self.right_hash().unwrap_or_default(), &mut bytes);
+            }
+        }
+        self.hash = Hash::from_bytes(HASH_ALGO, &Sha256::digest(bytes)).unwrap();
+    }
+
+    /// Returns the node's balance factor (left_height - right_height).
+    pub fn balance_factor(&self) -> i32 {
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => left.height as i32 - right.height as i32,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! # ICS23 Proof
+//!
+//! This module provides the ICS23 proof spec of IAVL trees, along with the encoding helpers used
+//! to build the leaf and inner operations of the proofs.
+use ics23::{HashOp, LeafOp, LengthOp, ProofSpec};
+
+/// The length prefix of a child hash in an inner node, i.e. its length encoded as an uvarint.
+pub(crate) const HASH_LENGTH_PREFIX: u8 = 32;
+
+/// Return the `LeafOp` of a leaf written at specified version.
+pub fn get_leaf_op(version: i64) -> LeafOp {
+    LeafOp {
+        hash: HashOp::Sha256.into(),
+        prehash_key: HashOp::NoHash.into(),
+        prehash_value: HashOp::Sha256.into(),
+        length: LengthOp::VarProto.into(),
+        prefix: node_header(0, 1, version),
+    }
+}
+
+/// Return the `ProofSpec` of IAVL trees, i.e. the standard ICS23 `IavlSpec`.
+pub fn get_proof_spec() -> ProofSpec {
+    ics23::iavl_spec()
+}
+
+/// Encode the header that prefixes the hashed bytes of every node.
+pub(crate) fn node_header(height: u32, size: i64, version: i64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(12);
+    encode_varint(height.into(), &mut header);
+    encode_varint(size, &mut header);
+    encode_varint(version, &mut header);
+    header
+}
+
+/// Encode a signed integer as a zigzag varint, like Go's `binary.PutVarint`.
+pub(crate) fn encode_varint(value: i64, buf: &mut Vec<u8>) {
+    encode_uvarint(((value << 1) ^ (value >> 63)) as u64, buf)
+}
+
+/// Encode an unsigned integer as a varint, like Go's `binary.PutUvarint`.
+pub(crate) fn encode_uvarint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Encode a byte slice prefixed by its length.
+pub(crate) fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    encode_uvarint(bytes.len() as u64, buf);
+    buf.extend_from_slice(bytes);
+}
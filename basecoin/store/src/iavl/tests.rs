@@ -0,0 +1,311 @@
+//! # Test suite of the IAVL Tree.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use ics23::commitment_proof::Proof;
+use ics23::{
+    verify_batch_membership, verify_batch_non_membership, verify_membership, verify_non_membership,
+    HostFunctionsManager,
+};
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use sha2::{Digest, Sha256};
+
+use crate::iavl::node::NodeRef;
+use crate::iavl::*;
+
+#[test]
+fn leaf_hash() {
+    let mut tree = IavlTree::new();
+    tree.insert(b"key".to_vec(), b"value".to_vec());
+
+    // height 0, size 1 and version 1 as zigzag varints, then the key and the hash of the value,
+    // both prefixed by their length
+    let mut bytes = vec![0, 2, 2, 3];
+    bytes.extend(b"key");
+    bytes.push(32);
+    bytes.extend(Sha256::digest(b"value"));
+    assert_eq!(
+        tree.root_hash().unwrap().as_bytes(),
+        Sha256::digest(bytes).as_slice()
+    );
+}
+
+/// Known-answer root hashes of IAVL trees, covering leaves, inner nodes, rotations, updates and
+/// removals across saved versions.
+///
+/// The expected hashes were computed with an independent transcription of Go iavl's
+/// `MutableTree` set, remove and balance algorithms and of its node hashing, rather than with Go
+/// iavl itself.
+#[test]
+fn known_root_hashes() {
+    fn root_hex(tree: &IavlTree<Vec<u8>, Vec<u8>>) -> String {
+        let hash = tree.root_hash().unwrap().as_bytes();
+        hash.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    let mut tree = IavlTree::new();
+    tree.insert(b"key".to_vec(), b"value".to_vec());
+    assert_eq!(
+        root_hex(&tree),
+        "85e286d2d33ee15ccc8a98f26ad8305dac3512dd5658432351c74b93a6471211"
+    );
+
+    let mut tree = IavlTree::new();
+    tree.insert(b"a".to_vec(), b"1".to_vec());
+    tree.insert(b"b".to_vec(), b"2".to_vec());
+    assert_eq!(
+        root_hex(&tree),
+        "94b037ab65e50f94eb827902a873ee796cb04e3c9ad38c9860d84cbad668a9e7"
+    );
+
+    // sequential inserts rotate at every other key, then updates and removals at later versions
+    let mut tree = IavlTree::new();
+    for key in b'a'..=b'h' {
+        tree.insert(vec![key], vec![key]);
+    }
+    assert_eq!(
+        root_hex(&tree),
+        "9661901322d43a4536941aa917a9e4dfabb5219a800c4edb5082a615ab4d4e66"
+    );
+    tree.save_version();
+    tree.insert(b"c".to_vec(), b"updated".to_vec());
+    tree.remove(&b"e"[..]);
+    tree.insert(b"i".to_vec(), b"i".to_vec());
+    assert_eq!(
+        root_hex(&tree),
+        "4d3a2eabb3930c7af1783f781746a0e405fd520c991187903ac6e8ac2cae698c"
+    );
+    tree.save_version();
+    for key in [b"a", b"b", b"h"] {
+        tree.remove(&key[..]);
+    }
+    assert_eq!(
+        root_hex(&tree),
+        "896e8032b746f56ae2e9a9c29732c668709d1219ecc213f278dffba0d1a0ad45"
+    );
+
+    let expected = [
+        "a360bf0d730fb51a3d26603f10204d3cecfcdd12d4233bdaaca0ea2ba0ae1d43",
+        "77086b43835acca1afb0f3ab85e838f9978ce6e6d75841b210a839294f9296fe",
+        "436f152df1d3bf493129f13ae7f93056b0a4ee3c1ce6097dcf1c62eb2d546899",
+    ];
+    let mut tree = IavlTree::new();
+    for (version, expected) in expected.into_iter().enumerate() {
+        for i in 0..100 {
+            let j = (i * 37 + version) % 100;
+            let key = format!("key/{j}").into_bytes();
+            if version > 0 && (j + version) % 3 == 0 {
+                tree.remove(&key);
+            } else {
+                tree.insert(key, format!("value/{j}/{version}").into_bytes());
+            }
+        }
+        assert_eq!(root_hex(&tree), expected);
+        tree.save_version();
+    }
+}
+
+#[test]
+fn shuffle_insert_get_remove() {
+    let mut tree = IavlTree::new();
+    let mut std_tree = BTreeMap::new();
+
+    let mut keys: Vec<u8> = (0..=255).collect();
+
+    keys.shuffle(&mut thread_rng());
+    for &i in keys.iter() {
+        assert_eq!(tree.insert(vec![i], vec![i]), None);
+        std_tree.insert(vec![i], vec![i]);
+        assert!(check_integrity(&tree.root));
+    }
+    for (key, value) in std_tree.iter() {
+        assert_eq!(tree.get(key), Some(value));
+    }
+    assert_eq!(tree.insert(vec![42], vec![0]), Some(vec![42]));
+    assert_eq!(tree.insert(vec![42], vec![42]), Some(vec![0]));
+
+    keys.shuffle(&mut thread_rng());
+    for &i in keys.iter() {
+        assert_eq!(tree.remove(&[i][..]), std_tree.remove(&vec![i]));
+        assert_eq!(tree.remove(&[i][..]), None);
+        assert!(check_integrity(&tree.root));
+        for (key, value) in std_tree.iter() {
+            assert_eq!(tree.get(key), Some(value));
+        }
+    }
+    assert_eq!(tree.root, None);
+}
+
+#[test]
+fn versions() {
+    let mut tree = IavlTree::new();
+    for i in 0..8u8 {
+        tree.insert(vec![i], vec![i]);
+    }
+    assert_eq!(tree.save_version(), 1);
+    let saved = tree.clone();
+
+    tree.insert(vec![3], vec![33]);
+    tree.remove(&[5][..]);
+    assert_eq!(tree.save_version(), 2);
+
+    // the saved version is unaffected by later writes
+    assert_eq!(saved.get(&[3][..]), Some(&vec![3]));
+    assert_eq!(saved.get(&[5][..]), Some(&vec![5]));
+    assert!(check_integrity(&saved.root));
+    assert!(check_integrity(&tree.root));
+
+    // only the nodes written since the first version are stamped with the second one
+    let root = tree.root.as_ref().unwrap();
+    assert_eq!(root.version, 2);
+    assert!(leaves(&tree.root)
+        .iter()
+        .all(|leaf| (leaf.version == 2) == (leaf.key == [3])));
+}
+
+#[test]
+fn range_iteration() {
+    let mut tree = IavlTree::new();
+    let keys: Vec<&[u8]> = vec![b"a", b"a-b", b"a/1", b"a/2", b"ab", b"a\xff", b"b"];
+    let mut shuffled = keys.clone();
+    shuffled.shuffle(&mut thread_rng());
+    for key in shuffled {
+        tree.insert(key.to_vec(), key.to_vec());
+    }
+
+    let all: Vec<_> = tree.clone().into_range(..).map(|(key, _)| key).collect();
+    assert_eq!(all, keys);
+    let rev: Vec<_> = tree
+        .clone()
+        .into_range(..)
+        .rev()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(rev, keys.iter().rev().copied().collect::<Vec<_>>());
+
+    let range: Vec<_> = tree
+        .clone()
+        .into_range((Bound::Excluded(&b"a-"[..]), Bound::Excluded(&b"a\xff"[..])))
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(range, &keys[1..5]);
+
+    for (prefix, expected) in [
+        (&b"a"[..], &keys[..6]),
+        (&b"a/"[..], &keys[2..4]),
+        (&b"a\xff"[..], &keys[5..6]),
+        (&b"b"[..], &keys[6..]),
+        (&b"c"[..], &keys[..0]),
+    ] {
+        let found: Vec<_> = tree
+            .clone()
+            .into_iter_prefix(prefix)
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn proofs() {
+    let mut existing_keys = ["C", "E", "G", "I", "K", "M", "O", "Q", "S", "U"];
+    // less than all, in the middle, greater than all
+    let non_existing_keys = ["A", "B", "D", "F", "H", "J", "L", "N", "P", "R", "T", "V"];
+
+    existing_keys.shuffle(&mut thread_rng());
+
+    let mut tree = IavlTree::new();
+    for (version, chunk) in existing_keys.chunks(3).enumerate() {
+        for &key in chunk {
+            tree.insert(key, vec![version as u8]);
+        }
+        tree.save_version();
+    }
+
+    let root = tree.root_hash().unwrap().as_bytes().to_vec();
+    let spec = get_proof_spec();
+
+    for &key in existing_keys.iter() {
+        let proof = tree.get_proof(key);
+        assert!(matches!(proof.proof, Some(Proof::Exist(_))));
+        assert!(
+            verify_membership::<HostFunctionsManager>(
+                &proof,
+                &spec,
+                &root,
+                key.as_bytes(),
+                tree.get(key).unwrap()
+            ),
+            "Failed to verify membership for key {}",
+            key
+        );
+    }
+
+    for &key in non_existing_keys.iter() {
+        let proof = tree.get_proof(key);
+        assert!(
+            verify_non_membership::<HostFunctionsManager>(&proof, &spec, &root, key.as_bytes()),
+            "Failed to verify non-membership for key {}",
+            key
+        );
+    }
+
+    let items: BTreeMap<&[u8], &[u8]> = existing_keys
+        .iter()
+        .map(|&key| (str::as_bytes(key), tree.get(key).unwrap().as_slice()))
+        .collect();
+    let absent: Vec<&[u8]> = non_existing_keys
+        .iter()
+        .map(|key| str::as_bytes(key))
+        .collect();
+    let keys = existing_keys
+        .iter()
+        .chain(non_existing_keys.iter())
+        .copied();
+    for proof in [
+        tree.get_batch_proof(keys.clone()),
+        tree.get_compressed_batch_proof(keys),
+    ] {
+        assert!(verify_batch_membership::<HostFunctionsManager>(
+            &proof,
+            &spec,
+            &root,
+            items.clone()
+        ));
+        assert!(verify_batch_non_membership::<HostFunctionsManager>(
+            &proof, &spec, &root, &absent
+        ));
+    }
+}
+
+fn leaves<K: Clone, V: Clone>(node_ref: &NodeRef<K, V>) -> Vec<IavlNode<K, V>> {
+    match node_ref {
+        None => vec![],
+        Some(node) if node.value.is_some() => vec![node.as_ref().clone()],
+        Some(node) => [leaves(&node.left), leaves(&node.right)].concat(),
+    }
+}
+
+/// Check that inner nodes have two children, that their keys are the smallest keys of their
+/// right subtrees, and that heights, sizes, hashes and balance factors are correct.
+fn check_integrity(node_ref: &NodeRef<Vec<u8>, Vec<u8>>) -> bool {
+    let Some(node) = node_ref else {
+        return true;
+    };
+    if node.value.is_some() {
+        return node.left.is_none() && node.right.is_none() && node.height == 0 && node.size == 1;
+    }
+    let (Some(left), Some(right)) = (&node.left, &node.right) else {
+        return false;
+    };
+    let mut updated = node.as_ref().clone();
+    updated.update(node.version);
+    leaves(&node.right).first().map(|leaf| &leaf.key) == Some(&node.key)
+        && leaves(&node.left).iter().all(|leaf| leaf.key < node.key)
+        && updated == **node
+        && node.balance_factor().abs() <= 1
+        && check_integrity(&Some(left.clone()))
+        && check_integrity(&Some(right.clone()))
+}
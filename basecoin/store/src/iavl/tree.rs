@@ -0,0 +1,393 @@
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use ics23::commitment_proof::Proof;
+use ics23::{
+    batch_entry, BatchEntry, BatchProof, CommitmentProof, ExistenceProof, HashOp, InnerOp,
+    NonExistenceProof,
+};
+use tendermint::hash::Hash;
+
+use super::iter::{IntoRange, RawRange};
+use super::node::{IavlNode, NodeRef};
+use super::proof::{get_leaf_op, node_header, HASH_LENGTH_PREFIX};
+use crate::avl::AsBytes;
+
+/// A Merkle AVL+ tree that hashes its nodes and generates proofs as the Cosmos SDK's IAVL
+/// tree does, so that its proofs verify with the standard ICS23 `IavlSpec`.
+///
+/// Keys are ordered by their byte representation. Nodes are versioned: every node written after
+/// the tree's latest saved version is stamped with the next version, which is part of its hash.
+/// As in [`AvlTree`](crate::avl::AvlTree), nodes are reference-counted and updated with
+/// copy-on-write, so cloning a tree is O(1).
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct IavlTree<K, V> {
+    pub root: NodeRef<K, V>,
+    /// The latest saved version.
+    version: i64,
+}
+
+impl<K: AsBytes + Clone, V: Borrow<[u8]> + Clone> IavlTree<K, V> {
+    /// Return an empty IAVL tree, whose nodes are written at version 1.
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            version: 0,
+        }
+    }
+
+    /// Return the latest saved version.
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// Save the current version of the tree, so that nodes written afterwards are stamped with
+    /// the next version. Returns the saved version.
+    pub fn save_version(&mut self) -> i64 {
+        self.version += 1;
+        self.version
+    }
+
+    /// The version at which nodes are written.
+    fn working_version(&self) -> i64 {
+        self.version + 1
+    }
+
+    /// Return the hash of the merkle tree root, if it has at least one node.
+    pub fn root_hash(&self) -> Option<&Hash> {
+        Some(&self.root.as_ref()?.hash)
+    }
+
+    /// Return the value corresponding to the key, if it exists.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: AsBytes + ?Sized,
+    {
+        let key = key.as_bytes();
+        let leaf = Self::find_leaf(&self.root, key.as_ref())?;
+        (leaf.key.as_bytes().as_ref() == key.as_ref())
+            .then_some(leaf.value.as_ref())
+            .flatten()
+    }
+
+    /// Return the leaf at which the search for `key` ends.
+    fn find_leaf<'a>(node_ref: &'a NodeRef<K, V>, key: &[u8]) -> Option<&'a IavlNode<K, V>> {
+        let mut node = node_ref.as_deref()?;
+        while !node.is_leaf() {
+            node = if key < node.key.as_bytes().as_ref() {
+                node.left.as_deref()?
+            } else {
+                node.right.as_deref()?
+            };
+        }
+        Some(node)
+    }
+
+    /// Return an owning iterator over the entries whose key bytes are within `range`, in
+    /// ascending order of the key bytes.
+    pub fn into_range<R>(self, range: R) -> IntoRange<K, V>
+    where
+        R: RangeBounds<[u8]>,
+    {
+        IntoRange(RawRange::new(
+            self.root,
+            range.start_bound(),
+            range.end_bound(),
+        ))
+    }
+
+    /// Return an owning iterator over the entries whose key bytes start with the bytes of
+    /// `prefix`, in ascending order of the key bytes.
+    pub fn into_iter_prefix<Q>(self, prefix: &Q) -> IntoRange<K, V>
+    where
+        Q: AsBytes + ?Sized,
+    {
+        let prefix = prefix.as_bytes();
+        let prefix = prefix.as_ref();
+        // the first key that doesn't start with `prefix`, if any
+        let end = prefix
+            .iter()
+            .rposition(|&byte| byte != u8::MAX)
+            .map(|i| [&prefix[..i], &[prefix[i] + 1]].concat());
+        let end = match &end {
+            Some(end) => Bound::Excluded(end.as_slice()),
+            None => Bound::Unbounded,
+        };
+        IntoRange(RawRange::new(self.root, Bound::Included(prefix), end))
+    }
+
+    /// Insert a value into the IAVL tree, this operation runs in amortized O(log(n)).
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let version = self.working_version();
+        Self::insert_rec(&mut self.root, key, value, version)
+    }
+
+    /// Insert a value in the tree.
+    fn insert_rec(node_ref: &mut NodeRef<K, V>, key: K, value: V, version: i64) -> Option<V> {
+        let Some(node) = node_ref else {
+            *node_ref = Some(Arc::new(IavlNode::leaf(key, value, version)));
+            return None;
+        };
+
+        if node.is_leaf() {
+            let ordering = key.as_bytes().as_ref().cmp(node.key.as_bytes().as_ref());
+            let (left, right) = match ordering {
+                Ordering::Equal => {
+                    return Arc::make_mut(node).set_value(value, version);
+                }
+                Ordering::Less => {
+                    let leaf = Arc::new(IavlNode::leaf(key, value, version));
+                    (leaf, node.clone())
+                }
+                Ordering::Greater => {
+                    let leaf = Arc::new(IavlNode::leaf(key, value, version));
+                    (node.clone(), leaf)
+                }
+            };
+            let inner_key = right.key.clone();
+            *node_ref = Some(Arc::new(IavlNode::inner(inner_key, left, right, version)));
+            return None;
+        }
+
+        let node = Arc::make_mut(node);
+        let previous = if key.as_bytes().as_ref() < node.key.as_bytes().as_ref() {
+            Self::insert_rec(&mut node.left, key, value, version)
+        } else {
+            Self::insert_rec(&mut node.right, key, value, version)
+        };
+        node.update(version);
+        if previous.is_none() {
+            // the shape of the tree only changes if a leaf was added
+            Self::balance_node(node_ref, version);
+        }
+        previous
+    }
+
+    /// Remove the value associated with a key from the tree, if it exists.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: AsBytes + ?Sized,
+    {
+        // don't copy the nodes along the path if the key is absent
+        self.get(key)?;
+        let version = self.working_version();
+        Self::remove_rec(&mut self.root, key.as_bytes().as_ref(), version).map(|(value, _)| value)
+    }
+
+    /// Remove the leaf of `key` from the subtree, which must contain it. Returns the removed
+    /// value, along with the new smallest key of the subtree if it changed and must be updated in
+    /// the ancestor of which the subtree is the right child.
+    fn remove_rec(
+        node_ref: &mut NodeRef<K, V>,
+        key: &[u8],
+        version: i64,
+    ) -> Option<(V, Option<K>)> {
+        if node_ref.as_ref()?.is_leaf() {
+            let leaf = node_ref.take()?;
+            let value = match Arc::try_unwrap(leaf) {
+                Ok(leaf) => leaf.value,
+                Err(leaf) => leaf.value.clone(),
+            };
+            return Some((value?, None));
+        }
+
+        let node = Arc::make_mut(node_ref.as_mut()?);
+        if key < node.key.as_bytes().as_ref() {
+            let (value, new_key) = Self::remove_rec(&mut node.left, key, version)?;
+            if node.left.is_none() {
+                // the left leaf was removed, so the right subtree replaces the node and its
+                // smallest key becomes the smallest key of the subtree
+                let new_key = node.key.clone();
+                *node_ref = node.right.take();
+                return Some((value, Some(new_key)));
+            }
+            node.update(version);
+            Self::balance_node(node_ref, version);
+            Some((value, new_key))
+        } else {
+            let (value, new_key) = Self::remove_rec(&mut node.right, key, version)?;
+            if node.right.is_none() {
+                *node_ref = node.left.take();
+                return Some((value, None));
+            }
+            if let Some(new_key) = new_key {
+                node.key = new_key;
+            }
+            node.update(version);
+            Self::balance_node(node_ref, version);
+            Some((value, None))
+        }
+    }
+
+    /// Rebalance the tree by performing rotations, if needed.
+    fn balance_node(node_ref: &mut NodeRef<K, V>, version: i64) {
+        let node = node_ref
+            .as_mut()
+            .expect("[IAVL]: Empty node in node balance");
+        let balance_factor = node.balance_factor();
+        if balance_factor >= 2 {
+            let node = Arc::make_mut(node);
+            if node.left.as_ref().map_or(0, |left| left.balance_factor()) < 0 {
+                Self::rotate_left(&mut node.left, version);
+            }
+            Self::rotate_right(node_ref, version);
+        } else if balance_factor <= -2 {
+            let node = Arc::make_mut(node);
+            if node
+                .right
+                .as_ref()
+                .map_or(0, |right| right.balance_factor())
+                > 0
+            {
+                Self::rotate_right(&mut node.right, version);
+            }
+            Self::rotate_left(node_ref, version);
+        }
+    }
+
+    /// Performs a right rotation. The keys of inner nodes are left unchanged, as they remain the
+    /// smallest keys of their right subtrees.
+    fn rotate_right(root: &mut NodeRef<K, V>, version: i64) {
+        let mut node = root.take().expect("[IAVL]: Empty root in right rotation");
+        let node_mut = Arc::make_mut(&mut node);
+        let mut left = node_mut
+            .left
+            .take()
+            .expect("[IAVL]: Inner node without left child");
+        let left_mut = Arc::make_mut(&mut left);
+        node_mut.left = left_mut.right.take();
+        node_mut.update(version);
+        left_mut.right = Some(node);
+        left_mut.update(version);
+        *root = Some(left);
+    }
+
+    /// Performs a left rotation. The keys of inner nodes are left unchanged, as they remain the
+    /// smallest keys of their right subtrees.
+    fn rotate_left(root: &mut NodeRef<K, V>, version: i64) {
+        let mut node = root.take().expect("[IAVL]: Empty root in left rotation");
+        let node_mut = Arc::make_mut(&mut node);
+        let mut right = node_mut
+            .right
+            .take()
+            .expect("[IAVL]: Inner node without right child");
+        let right_mut = Arc::make_mut(&mut right);
+        node_mut.right = right_mut.left.take();
+        node_mut.update(version);
+        right_mut.left = Some(node);
+        right_mut.update(version);
+        *root = Some(right);
+    }
+
+    /// Return an existence proof for the given element, if it exists.
+    /// Otherwise return a non-existence proof.
+    pub fn get_proof<Q>(&self, key: &Q) -> CommitmentProof
+    where
+        Q: AsBytes + ?Sized,
+    {
+        let proof = self.get_proof_inner(key.as_bytes().as_ref());
+        CommitmentProof { proof: Some(proof) }
+    }
+
+    /// Return a batch proof made of an existence or non-existence proof for each of the given
+    /// keys, in the order of the keys.
+    pub fn get_batch_proof<'q, Q>(&self, keys: impl IntoIterator<Item = &'q Q>) -> CommitmentProof
+    where
+        Q: AsBytes + ?Sized + 'q,
+    {
+        let entries = keys
+            .into_iter()
+            .map(|key| {
+                let proof = match self.get_proof_inner(key.as_bytes().as_ref()) {
+                    Proof::Exist(proof) => batch_entry::Proof::Exist(proof),
+                    Proof::Nonexist(proof) => batch_entry::Proof::Nonexist(proof),
+                    _ => unreachable!(),
+                };
+                BatchEntry { proof: Some(proof) }
+            })
+            .collect();
+        CommitmentProof {
+            proof: Some(Proof::Batch(BatchProof { entries })),
+        }
+    }
+
+    /// Return a compressed batch proof for the given keys, in which the inner nodes shared by
+    /// the proofs of several keys are only included once.
+    pub fn get_compressed_batch_proof<'q, Q>(
+        &self,
+        keys: impl IntoIterator<Item = &'q Q>,
+    ) -> CommitmentProof
+    where
+        Q: AsBytes + ?Sized + 'q,
+    {
+        ics23::compress(&self.get_batch_proof(keys))
+    }
+
+    fn get_proof_inner(&self, key: &[u8]) -> Proof {
+        if let Some(proof) = self.get_existence_proof(key) {
+            return Proof::Exist(proof);
+        }
+        // the neighbours of an absent key are the closest leaves on each side
+        let left = RawRange::new(self.root.clone(), Bound::Unbounded, Bound::Excluded(key))
+            .next_back()
+            .and_then(|leaf| self.get_existence_proof(leaf.key.as_bytes().as_ref()));
+        let right = RawRange::new(self.root.clone(), Bound::Excluded(key), Bound::Unbounded)
+            .next()
+            .and_then(|leaf| self.get_existence_proof(leaf.key.as_bytes().as_ref()));
+        Proof::Nonexist(NonExistenceProof {
+            key: key.to_vec(),
+            left,
+            right,
+        })
+    }
+
+    /// Build the existence proof of `key`, with the inner operations ordered from the leaf to
+    /// the root.
+    fn get_existence_proof(&self, key: &[u8]) -> Option<ExistenceProof> {
+        let mut path = vec![];
+        let mut node = self.root.as_deref()?;
+        while !node.is_leaf() {
+            let (left, right) = (node.left.as_deref()?, node.right.as_deref()?);
+            let mut prefix = node_header(node.height, node.size, node.version);
+            let mut suffix = vec![];
+            if key < node.key.as_bytes().as_ref() {
+                prefix.push(HASH_LENGTH_PREFIX);
+                suffix.push(HASH_LENGTH_PREFIX);
+                suffix.extend(right.hash.as_bytes());
+                node = left;
+            } else {
+                prefix.push(HASH_LENGTH_PREFIX);
+                prefix.extend(left.hash.as_bytes());
+                prefix.push(HASH_LENGTH_PREFIX);
+                node = right;
+            }
+            path.push(InnerOp {
+                hash: HashOp::Sha256.into(),
+                prefix,
+                suffix,
+            });
+        }
+        if node.key.as_bytes().as_ref() != key {
+            return None;
+        }
+        path.reverse();
+
+        Some(ExistenceProof {
+            key: key.to_vec(),
+            value: node.value.as_ref()?.borrow().to_vec(),
+            leaf: Some(get_leaf_op(node.version)),
+            path,
+        })
+    }
+}
+
+impl<K, V> Default for IavlTree<K, V> {
+    fn default() -> Self {
+        Self {
+            root: None,
+            version: 0,
+        }
+    }
+}
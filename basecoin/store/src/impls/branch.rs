@@ -0,0 +1,461 @@
+use core::cmp::Ordering;
+use core::ops::RangeBounds;
+use std::collections::BTreeMap;
+
+use ics23::CommitmentProof;
+use tracing::trace;
+
+use crate::avl::AsBytes;
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::types::{ChangeSet, Height, Path, RawHeight};
+
+/// A store that caches the writes made on top of a parent store, until they are either written
+/// to the parent or discarded.
+///
+/// Branches nest, as a branch is itself a store that can be branched off, e.g. a branch per
+/// message inside a branch per transaction inside a branch per block, or a branch to simulate a
+/// transaction on top of the committed state. The writes of a branch don't touch the parent
+/// store, and therefore its Merkle tree, until they are written with [`Store::apply`], which
+/// applies them to the parent in one pass. [`Store::reset`] discards them.
+///
+/// Only the `Pending` height sees the cached writes, while committed heights are read from the
+/// parent. Likewise, [`ProvableStore`] methods are answered by the parent, i.e. without the
+/// cached writes.
+///
+/// The parent is owned by the branch and can be recovered with [`BranchStore::into_parent`].
+/// To branch off a store that is also used elsewhere, use a [`SharedStore`](super::SharedStore)
+/// handle as the parent.
+#[derive(Clone, Debug)]
+pub struct BranchStore<S> {
+    /// The store the branch was created from.
+    parent: S,
+    /// The cached writes: `Some(value)` for a set and `None` for a delete.
+    cache: BTreeMap<Path, Option<Vec<u8>>>,
+}
+
+impl<S> BranchStore<S>
+where
+    S: Store,
+{
+    pub fn new(parent: S) -> Self {
+        Self {
+            parent,
+            cache: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a nested branch on top of this one.
+    pub fn branch(self) -> BranchStore<Self> {
+        BranchStore::new(self)
+    }
+
+    /// Writes the cached writes to the parent and returns it.
+    pub fn write(mut self) -> Result<S, S::Error> {
+        self.apply()?;
+        Ok(self.parent)
+    }
+
+    /// Discards the cached writes and returns the parent.
+    pub fn into_parent(self) -> S {
+        self.parent
+    }
+
+    /// Returns the parent store.
+    pub fn parent(&self) -> &S {
+        &self.parent
+    }
+
+    /// Returns whether the branch holds writes that weren't written to the parent.
+    pub fn is_dirty(&self) -> bool {
+        !self.cache.is_empty()
+    }
+
+    /// Overlays the cached writes selected by `filter` on top of the parent entries.
+    fn overlay(&self, parent: StoreIter, filter: impl Fn(&Path) -> bool) -> StoreIter {
+        if self.cache.is_empty() {
+            return parent;
+        }
+        let cache: Vec<_> = self
+            .cache
+            .iter()
+            .filter(|(path, _)| filter(path))
+            .map(|(path, value)| (path.clone(), value.clone()))
+            .collect();
+        Box::new(Overlay {
+            parent,
+            cache: cache.into_iter(),
+            parent_front: None,
+            parent_back: None,
+            cache_front: None,
+            cache_back: None,
+        })
+    }
+}
+
+impl<S> Default for BranchStore<S>
+where
+    S: Default + Store,
+{
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+impl<S> Store for BranchStore<S>
+where
+    S: Store,
+{
+    type Error = S::Error;
+
+    #[inline]
+    fn set(&mut self, path: Path, value: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error> {
+        let old_value = self.get(Height::Pending, &path);
+        self.cache.insert(path, Some(value));
+        Ok(old_value)
+    }
+
+    #[inline]
+    fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>> {
+        match (height, self.cache.get(path)) {
+            (Height::Pending, Some(value)) => value.clone(),
+            _ => self.parent.get(height, path),
+        }
+    }
+
+    #[inline]
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
+        self.cache.insert(path.clone(), None);
+        Ok(())
+    }
+
+    #[inline]
+    fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
+        // write the cached writes before committing the parent
+        self.apply()?;
+        self.parent.commit()
+    }
+
+    /// Writes the cached writes to the parent. Note that this doesn't call the parent's
+    /// `apply()`, so that a branch can be written to a branch of a transaction that may still
+    /// fail.
+    #[inline]
+    fn apply(&mut self) -> Result<(), Self::Error> {
+        trace!("writing {} cached writes to the parent", self.cache.len());
+        for (path, value) in core::mem::take(&mut self.cache) {
+            match value {
+                Some(value) => {
+                    self.parent.set(path, value)?;
+                }
+                None => self.parent.delete(&path)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Discards the cached writes.
+    #[inline]
+    fn reset(&mut self) {
+        self.cache.clear();
+    }
+
+    #[inline]
+    fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
+        self.parent.prune(height)
+    }
+
+    #[inline]
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        self.parent.prune_at(height)
+    }
+
+    #[inline]
+    fn current_height(&self) -> RawHeight {
+        self.parent.current_height()
+    }
+
+    #[inline]
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        let parent = self
+            .parent
+            .range_at(height, (range.start_bound(), range.end_bound()));
+        match height {
+            Height::Pending => self.overlay(parent, |path| range.contains(path)),
+            _ => parent,
+        }
+    }
+
+    #[inline]
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        let parent = self.parent.iter_prefix_at(height, key_prefix);
+        match height {
+            Height::Pending => {
+                let prefix = key_prefix.as_bytes();
+                self.overlay(parent, |path| {
+                    path.as_bytes().as_ref().starts_with(prefix.as_ref())
+                })
+            }
+            _ => parent,
+        }
+    }
+
+    #[inline]
+    fn change_set(&self, height: RawHeight) -> Option<ChangeSet> {
+        self.parent.change_set(height)
+    }
+
+    /// Committed heights are read from the parent, so the snapshot is a branch of the parent's
+    /// snapshot without cached writes.
+    fn snapshot(&self) -> Option<Self> {
+        Some(Self::new(self.parent.snapshot()?))
+    }
+}
+
+impl<S> ProvableStore for BranchStore<S>
+where
+    S: ProvableStore,
+{
+    #[inline]
+    fn root_hash(&self) -> Vec<u8> {
+        self.parent.root_hash()
+    }
+
+    #[inline]
+    fn working_hash(&self) -> Vec<u8> {
+        self.parent.working_hash()
+    }
+
+    #[inline]
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.parent.root_hash_at(height)
+    }
+
+    #[inline]
+    fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
+        self.parent.get_proof(height, key)
+    }
+
+    #[inline]
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        self.parent.get_batch_proof(height, keys)
+    }
+}
+
+type CachedEntry = (Path, Option<Vec<u8>>);
+
+/// A double-ended iterator over parent entries, in ascending path order, overlaid with the
+/// cached writes of a branch.
+///
+/// Each side keeps the entries it peeked from either end, so that both ends can be consumed
+/// until they meet.
+struct Overlay {
+    parent: StoreIter,
+    cache: std::vec::IntoIter<CachedEntry>,
+    parent_front: Option<(Path, Vec<u8>)>,
+    parent_back: Option<(Path, Vec<u8>)>,
+    cache_front: Option<CachedEntry>,
+    cache_back: Option<CachedEntry>,
+}
+
+/// Peeks the next entry of `iter` from the front, falling back on the entry peeked from the back.
+fn peek_front<'a, T>(
+    front: &'a mut Option<T>,
+    iter: &mut impl DoubleEndedIterator<Item = T>,
+    back: &mut Option<T>,
+) -> Option<&'a T> {
+    if front.is_none() {
+        *front = iter.next().or_else(|| back.take());
+    }
+    front.as_ref()
+}
+
+/// Peeks the next entry of `iter` from the back, falling back on the entry peeked from the front.
+fn peek_back<'a, T>(
+    back: &'a mut Option<T>,
+    iter: &mut impl DoubleEndedIterator<Item = T>,
+    front: &mut Option<T>,
+) -> Option<&'a T> {
+    if back.is_none() {
+        *back = iter.next_back().or_else(|| front.take());
+    }
+    back.as_ref()
+}
+
+impl Iterator for Overlay {
+    type Item = (Path, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let parent = peek_front(
+                &mut self.parent_front,
+                &mut self.parent,
+                &mut self.parent_back,
+            );
+            let cache = peek_front(&mut self.cache_front, &mut self.cache, &mut self.cache_back);
+            let ordering = match (parent, cache) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((parent_path, _)), Some((cache_path, _))) => parent_path.cmp(cache_path),
+            };
+            if ordering == Ordering::Less {
+                return self.parent_front.take();
+            }
+            if ordering == Ordering::Equal {
+                // the cached write overrides the parent entry
+                self.parent_front = None;
+            }
+            if let Some((path, Some(value))) = self.cache_front.take() {
+                return Some((path, value));
+            }
+        }
+    }
+}
+
+impl DoubleEndedIterator for Overlay {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let parent = peek_back(
+                &mut self.parent_back,
+                &mut self.parent,
+                &mut self.parent_front,
+            );
+            let cache = peek_back(&mut self.cache_back, &mut self.cache, &mut self.cache_front);
+            let ordering = match (parent, cache) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (Some((parent_path, _)), Some((cache_path, _))) => parent_path.cmp(cache_path),
+            };
+            if ordering == Ordering::Greater {
+                return self.parent_back.take();
+            }
+            if ordering == Ordering::Equal {
+                // the cached write overrides the parent entry
+                self.parent_back = None;
+            }
+            if let Some((path, Some(value))) = self.cache_back.take() {
+                return Some((path, value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{InMemoryStore, SharedStore};
+
+    fn path(path: &str) -> Path {
+        Path::from(path.to_owned())
+    }
+
+    #[test]
+    fn test_nested_branches() {
+        let mut store = InMemoryStore::default();
+        store.set(path("a"), vec![1]).unwrap();
+        store.set(path("b"), vec![2]).unwrap();
+        store.commit().unwrap();
+        let root_hash = store.root_hash();
+
+        // a block branch, with a transaction branch holding a message branch
+        let mut tx = BranchStore::new(store).branch();
+        tx.set(path("c"), vec![3]).unwrap();
+        let mut msg = tx.branch();
+        msg.delete(&path("a")).unwrap();
+        assert_eq!(msg.set(path("b"), vec![4]).unwrap(), Some(vec![2]));
+        assert_eq!(msg.get(Height::Pending, &path("a")), None);
+        assert_eq!(msg.get(Height::Latest, &path("a")), Some(vec![1]));
+
+        // the message fails, which only discards its own writes
+        let tx = msg.into_parent();
+        let entries: Vec<_> = tx.range_at(Height::Pending, ..).collect();
+        assert_eq!(
+            entries,
+            vec![
+                (path("a"), vec![1]),
+                (path("b"), vec![2]),
+                (path("c"), vec![3])
+            ]
+        );
+
+        let mut msg = tx.branch();
+        msg.delete(&path("a")).unwrap();
+        msg.set(path("b"), vec![4]).unwrap();
+        msg.set(path("d"), vec![5]).unwrap();
+        let block = msg.write().unwrap().write().unwrap();
+        assert!(block.is_dirty());
+        // nothing reached the Merkle tree yet
+        assert_eq!(block.parent().root_hash(), root_hash);
+        assert_eq!(
+            block.parent().get(Height::Pending, &path("b")),
+            Some(vec![2])
+        );
+
+        let entries: Vec<_> = block.range_at(Height::Pending, ..).rev().collect();
+        assert_eq!(
+            entries,
+            vec![
+                (path("d"), vec![5]),
+                (path("c"), vec![3]),
+                (path("b"), vec![4])
+            ]
+        );
+
+        let mut store = block.write().unwrap();
+        store.commit().unwrap();
+        assert_eq!(store.get(Height::Latest, &path("a")), None);
+        assert_eq!(store.get(Height::Latest, &path("d")), Some(vec![5]));
+    }
+
+    #[test]
+    fn test_overlay_iteration() {
+        let mut store = SharedStore::new(InMemoryStore::default());
+        for key in ["a/1", "a/2", "a/3", "b/1", "b/2"] {
+            store.set(path(key), key.as_bytes().to_vec()).unwrap();
+        }
+
+        let mut branch = BranchStore::new(store.clone());
+        branch.delete(&path("a/1")).unwrap();
+        branch.delete(&path("a/4")).unwrap();
+        branch.set(path("a/2"), vec![2]).unwrap();
+        branch.set(path("a/25"), vec![25]).unwrap();
+        branch.set(path("c"), vec![3]).unwrap();
+
+        let expected = vec![
+            (path("a/2"), vec![2]),
+            (path("a/25"), vec![25]),
+            (path("a/3"), b"a/3".to_vec()),
+        ];
+        let entries: Vec<_> = branch.iter_prefix_at(Height::Pending, &path("a")).collect();
+        assert_eq!(entries, expected);
+
+        // consuming both ends until they meet yields every entry once
+        let mut iter = branch.range_at(Height::Pending, path("a/2")..path("c"));
+        let mut front = vec![];
+        let mut back = vec![];
+        while let Some((path, _)) = iter.next() {
+            front.push(path);
+            match iter.next_back() {
+                Some((path, _)) => back.push(path),
+                None => break,
+            }
+        }
+        back.reverse();
+        assert_eq!(
+            [front, back].concat(),
+            vec![
+                path("a/2"),
+                path("a/25"),
+                path("a/3"),
+                path("b/1"),
+                path("b/2")
+            ]
+        );
+
+        // the shared parent isn't affected until the branch is written
+        assert_eq!(store.get(Height::Pending, &path("c")), None);
+        branch.apply().unwrap();
+        assert_eq!(store.get(Height::Pending, &path("c")), Some(vec![3]));
+        assert_eq!(store.get(Height::Pending, &path("a/1")), None);
+    }
+}
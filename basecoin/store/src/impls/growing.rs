@@ -1,6 +1,8 @@
+use core::ops::RangeBounds;
+
 use ics23::CommitmentProof;
 
-use crate::context::{ProvableStore, Store};
+use crate::context::{ProvableStore, Store, StoreIter};
 use crate::types::{Height, Path};
 
 /// GrowingStore does not prune any path.
@@ -54,9 +56,9 @@ where
     }
 
     #[inline]
-    fn delete(&mut self, path: &Path) {
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
         // set value to empty vec to denote the path is deleted.
-        self.store.set(path.clone(), vec![]).expect("delete failed");
+        self.store.set(path.clone(), vec![]).map(|_| ())
     }
 
     fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
@@ -78,23 +80,34 @@ where
         self.store.prune(height)
     }
 
+    #[inline]
+    fn prune_at(&mut self, height: u64) -> Result<(), Self::Error> {
+        self.store.prune_at(height)
+    }
+
     #[inline]
     fn current_height(&self) -> u64 {
         self.store.current_height()
     }
 
     #[inline]
-    fn get_keys(&self, key_prefix: &Path) -> Vec<Path> {
-        self.store
-            .get_keys(key_prefix)
-            .into_iter()
-            // ignore the deleted paths
-            .filter(|k| {
-                self.get(Height::Pending, k)
-                    .filter(|v| !v.is_empty())
-                    .is_some()
-            })
-            .collect()
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        // ignore the deleted paths
+        Box::new(
+            self.store
+                .range_at(height, range)
+                .filter(|(_, v)| !v.is_empty()),
+        )
+    }
+
+    #[inline]
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        // ignore the deleted paths
+        Box::new(
+            self.store
+                .iter_prefix_at(height, key_prefix)
+                .filter(|(_, v)| !v.is_empty()),
+        )
     }
 }
 
@@ -107,6 +120,16 @@ where
         self.store.root_hash()
     }
 
+    #[inline]
+    fn working_hash(&self) -> Vec<u8> {
+        self.store.working_hash()
+    }
+
+    #[inline]
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.store.root_hash_at(height)
+    }
+
     #[inline]
     fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
         self.get(height, key)
@@ -114,6 +137,15 @@ where
             .filter(|v| !v.is_empty())
             .and_then(|_| self.store.get_proof(height, key))
     }
+
+    #[inline]
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        // deleted paths can't be proven, as they are still present in the underlying store
+        keys.iter()
+            .all(|key| !self.store.get(height, key).is_some_and(|v| v.is_empty()))
+            .then(|| self.store.get_batch_proof(height, keys))
+            .flatten()
+    }
 }
 
 impl<S> GrowingStore<S>
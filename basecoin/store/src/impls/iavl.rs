@@ -0,0 +1,254 @@
+use core::convert::Infallible;
+use core::iter;
+use core::ops::{Bound, RangeBounds};
+
+use ics23::CommitmentProof;
+use sha2::{Digest, Sha256};
+use tracing::trace;
+
+use crate::avl::AsBytes;
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::iavl::IavlTree;
+use crate::impls::in_memory::PrunedVec;
+use crate::types::{Height, Path, RawHeight};
+
+type IavlState = IavlTree<Path, Vec<u8>>;
+
+/// An in-memory store backed by an [`IavlTree`], whose root hashes and proofs are those of an
+/// IAVL store of the Cosmos SDK holding the same writes at the same versions.
+///
+/// The store works like [`InMemoryStore`](super::InMemoryStore), with `pending` and `staged`
+/// copies of the working state, except that the tree version saved by every commit is the
+/// committed height. Its proofs verify with [`iavl::get_proof_spec`](crate::iavl::get_proof_spec).
+///
+/// As IAVL orders keys by their bytes, [`Store::range_at`] yields entries in the order of the
+/// bytes of their paths, which may differ from the order of [`Path`]s when path identifiers
+/// contain bytes lower than `/`.
+#[derive(Clone, Debug, Default)]
+pub struct IavlStore {
+    /// A collection of states corresponding to every committed block height.
+    store: PrunedVec<IavlState>,
+    /// The changes made as a result of successful transactions that are staged
+    /// and waiting to be committed.
+    staged: IavlState,
+    /// The dirty changes resulting from transactions that have not yet completed.
+    pending: IavlState,
+}
+
+impl IavlStore {
+    #[inline]
+    fn get_state(&self, height: Height) -> Option<&IavlState> {
+        match height {
+            Height::Pending => Some(&self.pending),
+            Height::Latest => self.store.last(),
+            Height::Stable(height) => {
+                if height == 0 {
+                    None
+                } else {
+                    let h = height as usize;
+                    self.store.get(h - 1)
+                }
+            }
+        }
+    }
+}
+
+/// Returns the root hash of `state`. IAVL hashes an empty tree as the hash of no bytes.
+fn state_hash(state: Option<&IavlState>) -> Vec<u8> {
+    state
+        .and_then(|s| s.root_hash())
+        .map(|hash| hash.as_bytes().to_vec())
+        .unwrap_or_else(|| Sha256::digest(b"").to_vec())
+}
+
+/// Maps a bound on paths to a bound on their bytes.
+fn bytes_bound(bound: Bound<&Path>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(path) => Bound::Included(path.as_bytes().as_ref().to_vec()),
+        Bound::Excluded(path) => Bound::Excluded(path.as_bytes().as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+fn as_slice_bound(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+    match bound {
+        Bound::Included(bytes) => Bound::Included(bytes),
+        Bound::Excluded(bytes) => Bound::Excluded(bytes),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl Store for IavlStore {
+    type Error = Infallible;
+
+    fn set(&mut self, path: Path, value: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error> {
+        trace!("set at path = {}", path.to_string());
+        Ok(self.pending.insert(path, value))
+    }
+
+    fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>> {
+        trace!(
+            "get at path = {} at height = {:?}",
+            path.to_string(),
+            height
+        );
+        self.get_state(height).and_then(|v| v.get(path).cloned())
+    }
+
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
+        self.pending.remove(path);
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
+        self.apply()?;
+        trace!("committing height: {}", self.current_height());
+        self.staged.save_version();
+        self.pending = self.staged.clone();
+        self.store.push(self.staged.clone());
+        Ok(self.root_hash())
+    }
+
+    fn apply(&mut self) -> Result<(), Self::Error> {
+        trace!("applying height: {}", self.current_height());
+        self.staged = self.pending.clone();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        trace!("resetting height: {}", self.current_height());
+        self.pending = self.staged.clone();
+    }
+
+    fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
+        let h = height as usize;
+        self.store.prune(h);
+        Ok(height)
+    }
+
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        if let Some(h) = (height as usize).checked_sub(1) {
+            self.store.remove(h);
+        }
+        Ok(())
+    }
+
+    fn current_height(&self) -> u64 {
+        self.store.original_length() as u64
+    }
+
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        let (start, end) = (
+            bytes_bound(range.start_bound()),
+            bytes_bound(range.end_bound()),
+        );
+        // cloning a state only clones a reference to its root node
+        match self.get_state(height) {
+            Some(state) => Box::new(
+                state
+                    .clone()
+                    .into_range((as_slice_bound(&start), as_slice_bound(&end))),
+            ),
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        match self.get_state(height) {
+            Some(state) => Box::new(state.clone().into_iter_prefix(key_prefix)),
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    /// The snapshot shares the committed states, which are immutable, and starts out its
+    /// working copies from the latest one.
+    fn snapshot(&self) -> Option<Self> {
+        let latest = self.store.last().cloned().unwrap_or_default();
+        Some(Self {
+            store: self.store.clone(),
+            staged: latest.clone(),
+            pending: latest,
+        })
+    }
+}
+
+impl ProvableStore for IavlStore {
+    fn root_hash(&self) -> Vec<u8> {
+        state_hash(self.get_state(Height::Latest))
+    }
+
+    fn working_hash(&self) -> Vec<u8> {
+        state_hash(self.get_state(Height::Pending))
+    }
+
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.get_state(height).map(|state| state_hash(Some(state)))
+    }
+
+    fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
+        trace!(
+            "get proof at path = {} at height = {:?}",
+            key.to_string(),
+            height
+        );
+        self.get_state(height).map(|v| v.get_proof(key))
+    }
+
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        trace!(
+            "get batch proof of {} paths at height = {:?}",
+            keys.len(),
+            height
+        );
+        self.get_state(height).map(|v| v.get_batch_proof(keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ics23::{verify_membership, verify_non_membership, HostFunctionsManager};
+
+    use super::*;
+    use crate::iavl::get_proof_spec;
+
+    #[test]
+    fn test_iavl_store() {
+        let mut store = IavlStore::default();
+        assert_eq!(store.root_hash(), Sha256::digest(b"").to_vec());
+
+        let (a, b) = (Path::from("a".to_owned()), Path::from("b/1".to_owned()));
+        store.set(a.clone(), vec![1]).unwrap();
+        store.set(b.clone(), vec![2]).unwrap();
+        let root_1 = store.commit().unwrap();
+
+        store.set(a.clone(), vec![3]).unwrap();
+        store.delete(&b).unwrap();
+        store.reset();
+        assert_eq!(store.get(Height::Pending, &a), Some(vec![1]));
+        store.delete(&b).unwrap();
+        let root_2 = store.commit().unwrap();
+        assert_ne!(root_1, root_2);
+        assert_eq!(store.current_height(), 2);
+
+        let spec = get_proof_spec();
+        let proof = store.get_proof(Height::Stable(1), &b).unwrap();
+        assert!(verify_membership::<HostFunctionsManager>(
+            &proof,
+            &spec,
+            &root_1,
+            b"b/1",
+            &[2]
+        ));
+        let proof = store.get_proof(Height::Latest, &b).unwrap();
+        assert!(verify_non_membership::<HostFunctionsManager>(
+            &proof, &spec, &root_2, b"b/1"
+        ));
+
+        let keys: Vec<_> = store
+            .iter_prefix_at(Height::Stable(1), &Path::from("b".to_owned()))
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b]);
+        assert_eq!(store.range_at(Height::Latest, ..).count(), 1);
+    }
+}
@@ -1,44 +1,140 @@
 use core::convert::Infallible;
+use core::iter;
+use core::ops::{Range, RangeBounds};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use ics23::CommitmentProof;
 use tendermint::hash::Algorithm;
 use tendermint::Hash;
 use tracing::trace;
 
-use crate::avl::{AsBytes, AvlTree};
-use crate::context::{ProvableStore, Store};
-use crate::types::{Height, Path, RawHeight, State};
+use crate::avl::{
+    decode_node, encode_node, read_version, take, AvlTree, DecodeError, ENCODING_VERSION,
+};
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::types::change::diff_entries;
+use crate::types::{Change, ChangeSet, Height, Path, RawHeight, State};
 
-/// A wrapper type around [`Vec`] that more easily facilitates the pruning of
+/// The number of children of the nodes of a [`PrunedVec`], as a power of two.
+const NODE_BITS: u32 = 5;
+const NODE_WIDTH: usize = 1 << NODE_BITS;
+
+/// A persistent vector-like type that more easily facilitates the pruning of
 /// its elements at a particular height / index. Keeps track of the latest
 /// height at which its elements were pruned.
 ///
+/// Besides pruning all elements up to an index, single elements can be removed
+/// while keeping the elements around them, which leaves a hole at their index.
+///
+/// The elements are held in a trie of reference-counted nodes, indexed by the
+/// index of the elements. The nodes are updated with copy-on-write, so cloning
+/// a `PrunedVec` is O(1) and the clones share all their unchanged nodes, while
+/// other operations are O(log n).
+///
 /// This type is used by [`InMemoryStore`] in order to prune old store entries.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct PrunedVec<T> {
-    vec: Vec<T>,
+    /// The root of the trie, or `None` if it holds no element.
+    root: Option<Arc<Node<T>>>,
+    /// The number of inner levels of the trie, above the level of leaves.
+    levels: u32,
+    /// The number of elements that were added, including the pruned ones.
+    len: usize,
     /// The latest index at which elements were pruned. In other words,
     /// elements that exist at and before this index are no longer accessible.
     pruned: usize,
 }
 
-impl<T> PrunedVec<T> {
+impl<T> Default for PrunedVec<T> {
+    fn default() -> Self {
+        Self {
+            root: None,
+            levels: 0,
+            len: 0,
+            pruned: 0,
+        }
+    }
+}
+
+/// A node of the trie of a [`PrunedVec`], whose slots are `None` for subtrees without elements
+/// and for removed elements.
+#[derive(Debug, Clone)]
+enum Node<T> {
+    Inner(Vec<Option<Arc<Node<T>>>>),
+    Leaf(Vec<Option<T>>),
+}
+
+impl<T> Node<T> {
+    fn new(level: u32) -> Self {
+        if level == 0 {
+            Self::Leaf(iter::repeat_with(|| None).take(NODE_WIDTH).collect())
+        } else {
+            Self::Inner(vec![None; NODE_WIDTH])
+        }
+    }
+}
+
+/// Returns the slot of the element at `index` in its node at `level`.
+fn slot(index: usize, level: u32) -> usize {
+    (index >> (NODE_BITS * level)) & (NODE_WIDTH - 1)
+}
+
+impl<T: Clone> PrunedVec<T> {
+    /// Returns a `PrunedVec` whose first `pruned` elements are pruned, followed
+    /// by `elements`, where `None` stands for a removed element.
+    pub fn with_pruned(elements: Vec<Option<T>>, pruned: usize) -> Self {
+        let mut vec = Self {
+            len: pruned,
+            pruned,
+            ..Self::default()
+        };
+        for element in elements {
+            if element.is_some() {
+                *vec.slot_mut(vec.len) = element;
+            }
+            vec.len += 1;
+        }
+        vec.prune_removed();
+        vec
+    }
+
     pub fn push(&mut self, value: T) {
-        self.vec.push(value);
+        *self.slot_mut(self.len) = Some(value);
+        self.len += 1;
     }
 
     pub fn get(&self, index: usize) -> Option<&T> {
-        self.vec.get(index.checked_sub(self.pruned)?)
+        if index < self.pruned || index >= self.len {
+            return None;
+        }
+        let mut node = self.root.as_deref()?;
+        let mut level = self.levels;
+        loop {
+            match node {
+                Node::Inner(children) => node = children[slot(index, level)].as_deref()?,
+                Node::Leaf(elements) => return elements[slot(index, 0)].as_ref(),
+            }
+            level -= 1;
+        }
     }
 
     pub fn last(&self) -> Option<&T> {
-        self.vec.last()
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    /// Returns an iterator over the retained elements, with `None` for removed
+    /// elements.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&T>> {
+        (self.pruned..self.len).map(|index| self.get(index))
     }
 
     /// Returns the number of elements currently in the `PrunedVec`,
     /// i.e., the total number of elements minus the pruned elements.
+    /// Removed elements that precede the first retained element count as
+    /// pruned, while the other removed elements still count as current ones.
     pub fn current_length(&self) -> usize {
-        self.vec.len()
+        self.len - self.pruned
     }
 
     /// Returns the number of elements that have been pruned over the
@@ -51,7 +147,7 @@ impl<T> PrunedVec<T> {
     /// the `PrunedVec` over the lifetime of the instance of this type.
     /// This includes the number of pruned elements in its count.
     pub fn original_length(&self) -> usize {
-        self.current_length() + self.pruned_length()
+        self.len
     }
 
     /// Removes all elements from the `PrunedVec` up to the specified
@@ -61,8 +157,107 @@ impl<T> PrunedVec<T> {
     pub fn prune(&mut self, index: usize) {
         trace!("pruning at index = {}", index);
         if index > self.pruned {
-            self.vec.drain(0..index - self.pruned);
-            self.pruned = index;
+            self.pruned = index.min(self.len);
+            self.prune_removed();
+        }
+    }
+
+    /// Removes the element at specified index, keeping the elements around it
+    /// accessible at their index. The last element can't be removed, so that
+    /// [`Self::last`] always returns the last pushed element.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        trace!("removing at index = {}", index);
+        if index + 1 >= self.original_length() {
+            return None;
+        }
+        // don't copy the nodes on the path to an element that doesn't exist
+        self.get(index)?;
+        let value = self.slot_mut(index).take();
+        self.prune_removed();
+        value
+    }
+
+    /// Removes all elements after the first `len` elements, including the pruned ones. Note that
+    /// `len` needs to be greater than or equal to `self.pruned`, otherwise this method is a no-op.
+    pub fn truncate(&mut self, len: usize) {
+        trace!("truncating at length = {}", len);
+        if len >= self.pruned && len < self.len {
+            Self::clear(&mut self.root, self.levels, 0, len..self.len);
+            self.len = len;
+        }
+    }
+
+    /// Prunes the removed elements that precede the first retained element, and drops the
+    /// nodes that only hold pruned elements.
+    fn prune_removed(&mut self) {
+        while self.pruned < self.len && self.get(self.pruned).is_none() {
+            self.pruned += 1;
+        }
+        Self::clear(&mut self.root, self.levels, 0, 0..self.pruned);
+    }
+
+    /// Returns the slot of the element at `index`, copying the nodes on the path to it and adding
+    /// the missing ones.
+    fn slot_mut(&mut self, index: usize) -> &mut Option<T> {
+        // add levels above the root until the trie has room for `index`
+        while index >> (NODE_BITS * (self.levels + 1)) != 0 {
+            if let Some(root) = self.root.take() {
+                let mut children = vec![None; NODE_WIDTH];
+                children[0] = Some(root);
+                self.root = Some(Arc::new(Node::Inner(children)));
+            }
+            self.levels += 1;
+        }
+
+        let mut level = self.levels;
+        let root = self.root.get_or_insert_with(|| Arc::new(Node::new(level)));
+        let mut node = Arc::make_mut(root);
+        loop {
+            match node {
+                Node::Inner(children) => {
+                    let child = &mut children[slot(index, level)];
+                    level -= 1;
+                    node = Arc::make_mut(child.get_or_insert_with(|| Arc::new(Node::new(level))));
+                }
+                Node::Leaf(elements) => return &mut elements[slot(index, 0)],
+            }
+        }
+    }
+
+    /// Drops the elements within `range` from the subtree at `level` whose first index is
+    /// `start`, along with the nodes left without elements.
+    fn clear(node_ref: &mut Option<Arc<Node<T>>>, level: u32, start: usize, range: Range<usize>) {
+        let Some(node) = node_ref else {
+            return;
+        };
+        let end = start + (NODE_WIDTH << (NODE_BITS * level));
+        if range.end <= start || end <= range.start {
+            return;
+        }
+        if range.start <= start && end <= range.end {
+            *node_ref = None;
+            return;
+        }
+
+        let empty = match Arc::make_mut(node) {
+            Node::Inner(children) => {
+                let width = 1 << (NODE_BITS * level);
+                for (i, child) in children.iter_mut().enumerate() {
+                    Self::clear(child, level - 1, start + i * width, range.clone());
+                }
+                children.iter().all(Option::is_none)
+            }
+            Node::Leaf(elements) => {
+                for (i, element) in elements.iter_mut().enumerate() {
+                    if range.contains(&(start + i)) {
+                        *element = None;
+                    }
+                }
+                elements.iter().all(Option::is_none)
+            }
+        };
+        if empty {
+            *node_ref = None;
         }
     }
 }
@@ -75,10 +270,12 @@ impl<T> PrunedVec<T> {
 /// - If it succeeded, the store _applies_ the transaction changes by copying `pending` to `staged`.
 /// - If it failed, the store _reverts_ the transaction changes by copying `staged` to `pending`.
 ///
-/// When a block is committed, the staged copy is copied into the committed store.
+/// When a block is committed, the staged copy is copied into the committed store, and the
+/// changes it makes to the previous height are recorded as the change set of the new height.
 ///
-/// Note that this store implementation is not production-friendly. After each transaction,
-/// the entire store is copied from `pending` to `staged`, or from `staged` to `pending`.
+/// Copying a state is cheap, as [`AvlTree`] nodes are reference-counted and shared between the
+/// copies. Only the nodes touched by a transaction are copied (on write), so applying, reverting
+/// and committing costs O(changes · log n) rather than O(state size).
 #[derive(Clone, Debug)]
 pub struct InMemoryStore {
     /// A collection of states corresponding to every committed block height.
@@ -88,11 +285,101 @@ pub struct InMemoryStore {
     staged: State,
     /// The dirty changes resulting from transactions that have not yet completed.
     pending: State,
+    /// The paths written by the `staged` changes since the latest commit.
+    staged_paths: AvlTree<Path, [u8; 0]>,
+    /// The paths written by the `pending` changes since the latest commit.
+    pending_paths: AvlTree<Path, [u8; 0]>,
+    /// The change sets recorded at every retained height committed by this instance.
+    changes: BTreeMap<RawHeight, ChangeSet>,
 }
 
 impl InMemoryStore {
+    /// Restores a store from previously committed states, where `states[0]` is the state at
+    /// height `pruned + 1` and `None` stands for a pruned height. The working copies start out
+    /// from the latest committed state.
+    pub(crate) fn restore(states: Vec<Option<State>>, pruned: usize) -> Self {
+        let latest = states.last().cloned().flatten().unwrap_or_default();
+
+        Self {
+            store: PrunedVec::with_pruned(states, pruned),
+            staged: latest.clone(),
+            pending: latest,
+            staged_paths: AvlTree::new(),
+            pending_paths: AvlTree::new(),
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Drops the committed states after `height` and the working changes, so that the store
+    /// resumes from the state at `height`, which must not be pruned.
+    pub(crate) fn rollback(&mut self, height: RawHeight) {
+        self.store.truncate(height as usize);
+        self.changes.retain(|changed, _| *changed <= height);
+        let latest = self.store.last().cloned().unwrap_or_default();
+        self.staged = latest.clone();
+        self.pending = latest;
+        self.staged_paths = AvlTree::new();
+        self.pending_paths = AvlTree::new();
+    }
+
+    /// Encodes all the retained committed states, so that they can be restored with
+    /// [`Self::decode_history`].
+    ///
+    /// The history is encoded as the [`ENCODING_VERSION`] byte, followed by the number of pruned
+    /// heights and the number of retained heights as big-endian `u64`s. Every retained height is
+    /// then encoded as a presence byte (`0` for a height that was pruned on its own, `1`
+    /// otherwise) followed by the nodes of its state, as in [`AvlTree::encode`]. Pending and staged
+    /// changes are not encoded.
+    pub fn encode_history(&self) -> Vec<u8> {
+        let mut buf = vec![ENCODING_VERSION];
+        buf.extend_from_slice(&(self.store.pruned_length() as u64).to_be_bytes());
+        buf.extend_from_slice(&(self.store.current_length() as u64).to_be_bytes());
+        for state in self.store.iter() {
+            match state {
+                None => buf.push(0),
+                Some(state) => {
+                    buf.push(1);
+                    encode_node(&state.root, &mut buf);
+                }
+            }
+        }
+        buf
+    }
+
+    /// Decodes a history encoded with [`Self::encode_history`], rebuilding the exact same states.
+    /// The working copies start out from the latest committed state.
+    pub fn decode_history(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        read_version(&mut bytes)?;
+        let pruned = read_u64(&mut bytes)?;
+        let len = read_u64(&mut bytes)?;
+
+        let mut states = Vec::new();
+        for _ in 0..len {
+            match take(&mut bytes, 1)?[0] {
+                0 => states.push(None),
+                1 => states.push(Some(AvlTree {
+                    root: decode_node(&mut bytes)?,
+                })),
+                tag => return Err(DecodeError::InvalidTag(tag)),
+            }
+        }
+        if !bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(bytes.len()));
+        }
+        if matches!(states.last(), Some(None)) {
+            return Err(DecodeError::PrunedLatest);
+        }
+
+        Ok(Self::restore(states, pruned as usize))
+    }
+
+    /// Returns the collection of committed states.
+    pub(crate) fn committed(&self) -> &PrunedVec<State> {
+        &self.store
+    }
+
     #[inline]
-    fn get_state(&self, height: Height) -> Option<&State> {
+    pub(crate) fn get_state(&self, height: Height) -> Option<&State> {
         match height {
             Height::Pending => Some(&self.pending),
             Height::Latest => self.store.last(),
@@ -106,6 +393,35 @@ impl InMemoryStore {
             }
         }
     }
+
+    /// Returns the changes made by the `staged` state to the latest committed state.
+    fn staged_changes(&self) -> ChangeSet {
+        let committed = self.store.last();
+        self.staged_paths
+            .iter()
+            .filter_map(|(path, _)| {
+                let old = committed.and_then(|state| state.get(path)).cloned();
+                let change = Change::between(old, self.staged.get(path).cloned())?;
+                Some((path.clone(), change))
+            })
+            .collect()
+    }
+}
+
+/// Returns the root hash of `state`, or the hash of an empty state.
+fn state_hash(state: Option<&State>) -> Vec<u8> {
+    state
+        .and_then(|s| s.root_hash())
+        .unwrap_or(&Hash::from_bytes(Algorithm::Sha256, &[0u8; 32]).unwrap())
+        .as_bytes()
+        .to_vec()
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Result<u64, DecodeError> {
+    let bytes = take(bytes, 8)?;
+    Ok(u64::from_be_bytes(
+        bytes.try_into().expect("slice has length 8"),
+    ))
 }
 
 impl Default for InMemoryStore {
@@ -121,6 +437,9 @@ impl Default for InMemoryStore {
             store,
             staged,
             pending,
+            staged_paths: AvlTree::new(),
+            pending_paths: AvlTree::new(),
+            changes: BTreeMap::new(),
         }
     }
 }
@@ -130,6 +449,7 @@ impl Store for InMemoryStore {
 
     fn set(&mut self, path: Path, value: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error> {
         trace!("set at path = {}", path.to_string());
+        self.pending_paths.insert(path.clone(), []);
         Ok(self.pending.insert(path, value))
     }
 
@@ -142,56 +462,134 @@ impl Store for InMemoryStore {
         self.get_state(height).and_then(|v| v.get(path).cloned())
     }
 
-    fn delete(&mut self, path: &Path) {
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
+        self.pending_paths.insert(path.clone(), []);
         self.pending.remove(path.clone());
+        Ok(())
     }
 
     fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
         self.apply()?;
         trace!("committing height: {}", self.current_height());
+        let changes = self.staged_changes();
         self.store.push(self.staged.clone());
+        self.changes.insert(self.current_height(), changes);
+        self.staged_paths = AvlTree::new();
+        self.pending_paths = AvlTree::new();
+        // computes the dirty hashes of the committed state, which are shared with the working
+        // copies
         Ok(self.root_hash())
     }
 
     fn apply(&mut self) -> Result<(), Self::Error> {
         trace!("applying height: {}", self.current_height());
         self.staged = self.pending.clone();
+        self.staged_paths = self.pending_paths.clone();
         Ok(())
     }
 
     fn reset(&mut self) {
         trace!("resetting height: {}", self.current_height());
         self.pending = self.staged.clone();
+        self.pending_paths = self.staged_paths.clone();
     }
 
     fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
         let h = height as usize;
         self.store.prune(h);
+        self.changes = self.changes.split_off(&(height + 1));
         Ok(height)
     }
 
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        if let Some(h) = (height as usize).checked_sub(1) {
+            if self.store.remove(h).is_some() {
+                self.changes.remove(&height);
+            }
+        }
+        Ok(())
+    }
+
+    fn diff(&self, from: Height, to: Height) -> Option<ChangeSet> {
+        // the state at height 0 is the empty genesis state
+        let genesis = State::new();
+        let state = |height| match height {
+            Height::Stable(0) => Some(&genesis),
+            _ => self.get_state(height),
+        };
+        let (from, to) = (state(from)?, state(to)?);
+        fn entry<'a>((path, value): (&'a Path, &'a Vec<u8>)) -> (&'a Path, &'a [u8]) {
+            (path, value)
+        }
+        Some(diff_entries(from.iter().map(entry), to.iter().map(entry)))
+    }
+
+    fn change_set(&self, height: RawHeight) -> Option<ChangeSet> {
+        match self.changes.get(&height) {
+            Some(changes) => Some(changes.clone()),
+            None => self.diff(
+                Height::Stable(height.checked_sub(1)?),
+                Height::Stable(height),
+            ),
+        }
+    }
+
     fn current_height(&self) -> u64 {
         self.store.original_length() as u64
     }
 
-    fn get_keys(&self, key_prefix: &Path) -> Vec<Path> {
-        let key_prefix = key_prefix.as_bytes();
-        self.pending
-            .get_keys()
-            .into_iter()
-            .filter(|&key| key.as_bytes().as_ref().starts_with(key_prefix.as_ref()))
-            .cloned()
-            .collect()
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        // cloning a state only clones a reference to its root node
+        match self.get_state(height) {
+            Some(state) => Box::new(state.clone().into_range(range)),
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        match self.get_state(height) {
+            Some(state) => Box::new(state.clone().into_iter_prefix(key_prefix)),
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    fn get_keys_at(&self, height: Height, key_prefix: &Path) -> Vec<Path> {
+        self.get_state(height)
+            .map(|state| {
+                state
+                    .iter_prefix(key_prefix)
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The snapshot shares the committed states, which are immutable, and starts out its
+    /// working copies from the latest one.
+    fn snapshot(&self) -> Option<Self> {
+        let latest = self.store.last().cloned().unwrap_or_default();
+        Some(Self {
+            store: self.store.clone(),
+            staged: latest.clone(),
+            pending: latest,
+            staged_paths: AvlTree::new(),
+            pending_paths: AvlTree::new(),
+            changes: BTreeMap::new(),
+        })
     }
 }
 
 impl ProvableStore for InMemoryStore {
     fn root_hash(&self) -> Vec<u8> {
-        self.get_state(Height::Latest)
-            .and_then(|s| s.root_hash())
-            .unwrap_or(&Hash::from_bytes(Algorithm::Sha256, &[0u8; 32]).unwrap())
-            .as_bytes()
-            .to_vec()
+        state_hash(self.get_state(Height::Latest))
+    }
+
+    fn working_hash(&self) -> Vec<u8> {
+        state_hash(self.get_state(Height::Pending))
+    }
+
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.get_state(height).map(|state| state_hash(Some(state)))
     }
 
     fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
@@ -202,13 +600,32 @@ impl ProvableStore for InMemoryStore {
         );
         self.get_state(height).map(|v| v.get_proof(key))
     }
-}
 
-// TODO(hu55a1n1): import tests
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        trace!(
+            "get batch proof of {} paths at height = {:?}",
+            keys.len(),
+            height
+        );
+        self.get_state(height).map(|v| v.get_batch_proof(keys))
+    }
+}
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
+    use ics23::{
+        verify_batch_membership, verify_batch_non_membership, verify_membership,
+        verify_non_membership, HostFunctionsManager, ProofSpec,
+    };
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+
     use super::*;
+    use crate::avl::{get_proof_spec, AsBytes};
+    use crate::impls::SharedStore;
+    use crate::types::{JsonStore, TypedStoreError};
 
     #[test]
     fn test_pruned_vec() {
@@ -230,6 +647,50 @@ mod tests {
         assert_eq!(pv.get(4), Some(&5));
         assert_eq!(pv.get(5), None);
         assert_eq!(pv.last(), Some(&5));
+
+        assert_eq!(pv.remove(3), Some(4));
+        assert_eq!(pv.remove(4), None);
+        assert_eq!(pv.get(3), None);
+        assert_eq!(pv.current_length(), 3);
+        assert_eq!(pv.remove(2), Some(3));
+        assert_eq!(pv.pruned_length(), 4);
+        assert_eq!(pv.current_length(), 1);
+        assert_eq!(pv.original_length(), 5);
+        assert_eq!(pv.last(), Some(&5));
+
+        pv.push(6);
+        pv.truncate(5);
+        assert_eq!(pv.original_length(), 5);
+        assert_eq!(pv.last(), Some(&5));
+        pv.truncate(3);
+        assert_eq!(pv.original_length(), 5);
+
+        // clones share their nodes, and aren't affected by the writes made to each other
+        let mut pv = PrunedVec::default();
+        for i in 0..1000 {
+            pv.push(i);
+        }
+        let snapshot = pv.clone();
+        assert!(Arc::ptr_eq(
+            pv.root.as_ref().unwrap(),
+            snapshot.root.as_ref().unwrap()
+        ));
+        pv.prune(990);
+        assert_eq!(pv.remove(995), Some(995));
+        pv.push(1000);
+        assert_eq!(pv.get(989), None);
+        assert_eq!(pv.get(995), None);
+        assert_eq!(pv.last(), Some(&1000));
+        assert_eq!(pv.iter().flatten().count(), 10);
+        assert_eq!(snapshot.get(0), Some(&0));
+        assert_eq!(snapshot.get(995), Some(&995));
+        assert_eq!(snapshot.last(), Some(&999));
+
+        // the leaves that only held pruned elements are dropped
+        let Some(Node::Inner(leaves)) = pv.root.as_deref() else {
+            panic!("root isn't an inner node");
+        };
+        assert_eq!(leaves.iter().flatten().count(), 2);
     }
 
     #[test]
@@ -262,8 +723,12 @@ mod tests {
         assert_eq!(store.get(Height::Latest, &path), Some(value1.clone()));
         assert_eq!(store.get(Height::Stable(1), &path), Some(value1.clone()));
 
+        // the working hash is the one that the commit returns
+        let working_hash = store.working_hash();
+        assert_ne!(working_hash, store.root_hash());
         store.apply().unwrap();
-        store.commit().unwrap();
+        assert_eq!(store.commit().unwrap(), working_hash);
+        assert_eq!(store.root_hash(), working_hash);
 
         assert_eq!(store.get(Height::Pending, &path), Some(value2.clone()));
         assert_eq!(store.get(Height::Latest, &path), Some(value2.clone()));
@@ -272,5 +737,509 @@ mod tests {
         assert_eq!(store.get(Height::Stable(3), &path), None);
         assert_eq!(store.current_height(), 2);
         assert!(!store.root_hash().is_empty());
+
+        // the root hashes of the retained heights, including the empty ones
+        assert_ne!(
+            store.root_hash_at(Height::Stable(1)),
+            Some(working_hash.clone())
+        );
+        assert_eq!(store.root_hash_at(Height::Stable(2)), Some(working_hash));
+        store.delete(&path).unwrap();
+        store.commit().unwrap();
+        assert_eq!(
+            store.root_hash_at(Height::Latest),
+            Some(InMemoryStore::default().root_hash())
+        );
+        assert_eq!(store.root_hash_at(Height::Stable(4)), None);
+    }
+
+    #[test]
+    fn test_get_keys_at() {
+        let mut store = InMemoryStore::default();
+        let prefix = Path::from("a".to_owned());
+        let (a1, a2, b1) = (
+            Path::from("a/1".to_owned()),
+            Path::from("a/2".to_owned()),
+            Path::from("b/1".to_owned()),
+        );
+
+        store.set(a1.clone(), vec![1]).unwrap();
+        store.set(b1, vec![1]).unwrap();
+        store.commit().unwrap();
+
+        store.set(a2.clone(), vec![2]).unwrap();
+        store.delete(&a1).unwrap();
+        store.commit().unwrap();
+
+        store.set(a1.clone(), vec![3]).unwrap();
+
+        assert_eq!(
+            store.get_keys_at(Height::Stable(1), &prefix),
+            vec![a1.clone()]
+        );
+        assert_eq!(
+            store.get_keys_at(Height::Stable(2), &prefix),
+            vec![a2.clone()]
+        );
+        assert_eq!(store.get_keys_at(Height::Latest, &prefix), vec![a2.clone()]);
+        assert_eq!(
+            store.get_keys_at(Height::Pending, &prefix),
+            vec![a1.clone(), a2.clone()]
+        );
+        assert_eq!(store.get_keys(&prefix), vec![a1, a2]);
+        assert!(store.get_keys_at(Height::Stable(3), &prefix).is_empty());
+    }
+
+    #[test]
+    fn test_iter_at() {
+        let mut store = InMemoryStore::default();
+        let keys = ["a", "a-b", "a/1", "a/1/x", "a/2", "ab", "b/a", "c"];
+        for (i, key) in keys.iter().enumerate() {
+            store
+                .set(Path::from(key.to_string()), vec![i as u8])
+                .unwrap();
+        }
+        store.commit().unwrap();
+        store.set(Path::from("a/3".to_owned()), vec![42]).unwrap();
+
+        for prefix in ["a", "a/", "a/1", "a-", "b", "c/", "d"] {
+            let expected: Vec<_> = keys
+                .iter()
+                .filter(|key| key.starts_with(prefix))
+                .map(|key| Path::from(key.to_string()))
+                .collect();
+            let prefix = Path::from(prefix.to_owned());
+            let found: Vec<_> = store
+                .iter_prefix_at(Height::Latest, &prefix)
+                .map(|(key, _)| key)
+                .collect();
+            let mut expected_sorted = expected.clone();
+            expected_sorted.sort();
+            assert_eq!(found, expected_sorted);
+            assert_eq!(store.get_keys_at(Height::Stable(1), &prefix), found);
+        }
+
+        let (start, end) = (Path::from("a/1".to_owned()), Path::from("ab".to_owned()));
+        let found: Vec<_> = store
+            .range_at(Height::Pending, start..end)
+            .rev()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        // paths are ordered by their bytes, so `a-b` sorts before `a/1`
+        assert_eq!(
+            found,
+            vec![
+                ("a/3".to_owned(), vec![42]),
+                ("a/2".to_owned(), vec![4]),
+                ("a/1/x".to_owned(), vec![3]),
+                ("a/1".to_owned(), vec![2]),
+            ]
+        );
+        assert_eq!(store.range_at(Height::Stable(2), ..).next(), None);
+    }
+
+    #[test]
+    fn test_batch_proof() {
+        let mut store = InMemoryStore::default();
+        let paths: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|key| Path::from(key.to_string()))
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            store.set(path.clone(), vec![i as u8]).unwrap();
+        }
+        let root = store.commit().unwrap();
+        store.set(Path::from("bb".to_owned()), vec![3]).unwrap();
+        store.commit().unwrap();
+
+        let spec = get_proof_spec();
+        let absent = Path::from("bb".to_owned());
+        let keys = [paths.clone(), vec![absent.clone()]].concat();
+        let items: BTreeMap<_, _> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| (path.to_string().into_bytes(), vec![i as u8]))
+            .collect();
+        let items = items
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_slice()))
+            .collect::<BTreeMap<_, _>>();
+        let absent = absent.to_string().into_bytes();
+
+        for proof in [
+            store.get_batch_proof(Height::Stable(1), &keys).unwrap(),
+            store
+                .get_compressed_batch_proof(Height::Stable(1), &keys)
+                .unwrap(),
+        ] {
+            assert!(verify_batch_membership::<HostFunctionsManager>(
+                &proof,
+                &spec,
+                &root,
+                items.clone()
+            ));
+            assert!(verify_batch_non_membership::<HostFunctionsManager>(
+                &proof,
+                &spec,
+                &root,
+                &[absent.as_slice()]
+            ));
+        }
+        assert!(store.get_batch_proof(Height::Stable(3), &keys).is_none());
+    }
+
+    #[test]
+    fn test_change_sets() {
+        let mut store = SharedStore::new(InMemoryStore::default());
+        let (a, b, c) = (
+            Path::from("a".to_owned()),
+            Path::from("b/1".to_owned()),
+            Path::from("b/2".to_owned()),
+        );
+        store.set(a.clone(), vec![1]).unwrap();
+        store.set(b.clone(), vec![2]).unwrap();
+        store.commit().unwrap();
+
+        // a reverted write and a write restoring the previous value aren't changes
+        store.set(c.clone(), vec![3]).unwrap();
+        store.reset();
+        store.set(a.clone(), vec![2]).unwrap();
+        store.set(a.clone(), vec![1]).unwrap();
+        store.set(b.clone(), vec![4]).unwrap();
+        store.set(c.clone(), vec![5]).unwrap();
+        store.apply().unwrap();
+        store.commit().unwrap();
+        store.delete(&b).unwrap();
+        store.commit().unwrap();
+
+        let change_set_1 = ChangeSet::from([
+            (a.clone(), Change::Inserted { new: vec![1] }),
+            (b.clone(), Change::Inserted { new: vec![2] }),
+        ]);
+        let change_set_2 = ChangeSet::from([
+            (
+                b.clone(),
+                Change::Updated {
+                    old: vec![2],
+                    new: vec![4],
+                },
+            ),
+            (c.clone(), Change::Inserted { new: vec![5] }),
+        ]);
+        assert_eq!(store.change_set(1), Some(change_set_1.clone()));
+        assert_eq!(store.change_set(2), Some(change_set_2));
+        assert_eq!(
+            store.change_set(3),
+            Some(ChangeSet::from([(
+                b.clone(),
+                Change::Deleted { old: vec![4] }
+            )]))
+        );
+        assert_eq!(store.change_set(4), None);
+
+        // the recorded change sets match the diffs between the committed states
+        for height in 1..=3 {
+            assert_eq!(
+                store.diff(Height::Stable(height - 1), Height::Stable(height)),
+                store.change_set(height)
+            );
+        }
+        assert_eq!(
+            store.diff(Height::Stable(3), Height::Stable(1)),
+            Some(ChangeSet::from([
+                (b.clone(), Change::Inserted { new: vec![2] }),
+                (c.clone(), Change::Deleted { old: vec![5] }),
+            ]))
+        );
+
+        // typed stores only report the changes under their prefix
+        let d = Path::from("b/3".to_owned());
+        let mut typed: JsonStore<_, Path, u8> = JsonStore::new(store.clone());
+        typed.set(d.clone(), 7).unwrap();
+        store.set(Path::from("c".to_owned()), vec![8]).unwrap();
+        store.commit().unwrap();
+        assert_eq!(
+            typed.diff(Height::Stable(0), Height::Latest, &d).unwrap(),
+            Some(ChangeSet::from([(d, Change::Inserted { new: 7 })]))
+        );
+
+        // undecodable and missing values are errors rather than `None`
+        let prefix = Path::from("b".to_owned());
+        assert!(matches!(
+            typed.diff(Height::Stable(0), Height::Latest, &prefix),
+            Err(TypedStoreError::DecodeFailed { path, .. }) if path == c
+        ));
+        assert!(matches!(
+            typed.get(Height::Latest, &c),
+            Err(TypedStoreError::DecodeFailed { .. })
+        ));
+        assert!(typed.get(Height::Latest, &b).unwrap().is_none());
+        assert!(matches!(
+            typed.get_existing(Height::Latest, &b),
+            Err(TypedStoreError::NotFound { .. })
+        ));
+
+        store.prune(1).unwrap();
+        assert_eq!(store.diff(Height::Stable(1), Height::Latest), None);
+        assert_eq!(store.change_set(1), None);
+    }
+
+    /// A typed key of the paths `seq/<n>`
+    #[derive(Debug, PartialEq)]
+    struct SeqKey(u64);
+
+    impl core::fmt::Display for SeqKey {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "seq/{}", self.0)
+        }
+    }
+
+    impl core::str::FromStr for SeqKey {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let n = s.strip_prefix("seq/").ok_or(())?;
+            n.parse().map(Self).map_err(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_typed_entries() {
+        let mut store = SharedStore::new(InMemoryStore::default());
+        let mut typed: JsonStore<_, SeqKey, u64> = JsonStore::new(store.clone());
+        for n in 1..=5 {
+            typed.set(SeqKey(n), n * 10).unwrap();
+        }
+        // neither parses as a key, so their values aren't decoded
+        store
+            .set(Path::from("seq/next".to_owned()), vec![0xff])
+            .unwrap();
+        store
+            .set(Path::from("seqs".to_owned()), vec![0xff])
+            .unwrap();
+        store.commit().unwrap();
+        typed.set(SeqKey(6), 60).unwrap();
+
+        let prefix = Path::from("seq".to_owned());
+        let entries = |height, start, limit| {
+            typed
+                .entries_at::<SeqKey>(height, &prefix, start, limit)
+                .map(|entry| entry.map(|(SeqKey(n), value)| (n, value)))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            entries(Height::Latest, None, None),
+            [(1, 10), (2, 20), (3, 30), (4, 40), (5, 50)]
+        );
+        assert_eq!(entries(Height::Pending, None, None).len(), 6);
+        assert_eq!(
+            entries(Height::Latest, Some(&SeqKey(3)), Some(2)),
+            [(3, 30), (4, 40)]
+        );
+        assert_eq!(
+            entries(Height::Latest, Some(&SeqKey(5)), Some(2)),
+            [(5, 50)]
+        );
+        assert_eq!(entries(Height::Latest, Some(&SeqKey(7)), None), []);
+        assert_eq!(entries(Height::Stable(0), None, None), []);
+
+        // values of parsed keys that fail to decode are errors
+        store
+            .set(Path::from("seq/7".to_owned()), vec![0xff])
+            .unwrap();
+        assert!(matches!(
+            typed
+                .entries_at::<SeqKey>(Height::Pending, &prefix, Some(&SeqKey(6)), None)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            [
+                Ok((SeqKey(6), 60)),
+                Err(TypedStoreError::DecodeFailed { .. })
+            ]
+        ));
+
+        // a value whose previous value fails to decode isn't written
+        assert!(matches!(
+            typed.set(SeqKey(7), 70),
+            Err(TypedStoreError::DecodeFailed { .. })
+        ));
+        assert_eq!(
+            store.get(Height::Pending, &Path::from("seq/7".to_owned())),
+            Some(vec![0xff])
+        );
+    }
+
+    #[test]
+    fn test_history_encoding() {
+        let mut store = InMemoryStore::default();
+        for height in 1..=6u8 {
+            for i in 0..height * 10 {
+                store
+                    .set(Path::from(format!("{height}/{i}")), vec![i])
+                    .unwrap();
+            }
+            if height % 2 == 0 {
+                store
+                    .delete(&Path::from(format!("{}/0", height - 1)))
+                    .unwrap();
+            }
+            store.commit().unwrap();
+        }
+        store.prune(2).unwrap();
+        store.prune_at(4).unwrap();
+
+        let bytes = store.encode_history();
+        let decoded = InMemoryStore::decode_history(&bytes).unwrap();
+        assert_eq!(decoded.current_height(), 6);
+        assert_eq!(decoded.committed().pruned_length(), 2);
+        for height in 1..=6 {
+            let (state, decoded_state) = (
+                store.get_state(Height::Stable(height)),
+                decoded.get_state(Height::Stable(height)),
+            );
+            assert_eq!(decoded_state, state);
+            assert_eq!(
+                decoded_state.and_then(|s| s.root_hash()),
+                state.and_then(|s| s.root_hash())
+            );
+        }
+        assert_eq!(decoded.root_hash(), store.root_hash());
+        assert_eq!(decoded.encode_history(), bytes);
+
+        assert_eq!(
+            InMemoryStore::decode_history(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::UnexpectedEnd
+        );
+    }
+
+    /// The entries of a state.
+    type Model = BTreeMap<Path, Vec<u8>>;
+
+    #[test]
+    fn test_model_based() {
+        let mut rng = rand::thread_rng();
+        let spec = get_proof_spec();
+        let paths: Vec<Path> = (0..16).map(|i| Path::from(format!("p/{i}"))).collect();
+
+        for _ in 0..8 {
+            let mut store = InMemoryStore::default();
+            let mut pending = Model::new();
+            let mut staged = Model::new();
+            // the entries and root hash of every committed height, or `None` if it was pruned
+            let mut committed: Vec<Option<(Model, Vec<u8>)>> = vec![];
+
+            for _ in 0..150 {
+                let path = paths.choose(&mut rng).unwrap().clone();
+                let mut committed_changed = true;
+                match rng.gen_range(0..100) {
+                    0..=44 => {
+                        let value = vec![rng.gen()];
+                        assert_eq!(
+                            store.set(path.clone(), value.clone()).unwrap(),
+                            pending.insert(path, value)
+                        );
+                        committed_changed = false;
+                    }
+                    45..=64 => {
+                        store.delete(&path).unwrap();
+                        pending.remove(&path);
+                        committed_changed = false;
+                    }
+                    65..=74 => {
+                        store.apply().unwrap();
+                        staged = pending.clone();
+                        committed_changed = false;
+                    }
+                    75..=82 => {
+                        store.reset();
+                        pending = staged.clone();
+                        committed_changed = false;
+                    }
+                    83..=94 => {
+                        let root_hash = store.commit().unwrap();
+                        staged = pending.clone();
+                        committed.push(Some((staged.clone(), root_hash)));
+                    }
+                    // the latest height is never pruned
+                    95..=97 if committed.len() > 1 => {
+                        let height = rng.gen_range(1..committed.len());
+                        store.prune(height as RawHeight).unwrap();
+                        committed[..height].fill(None);
+                    }
+                    _ if committed.len() > 1 => {
+                        let height = rng.gen_range(1..committed.len());
+                        store.prune_at(height as RawHeight).unwrap();
+                        committed[height - 1] = None;
+                    }
+                    _ => committed_changed = false,
+                }
+
+                for path in &paths {
+                    assert_eq!(store.get(Height::Pending, path).as_ref(), pending.get(path));
+                }
+                assert_eq!(store.current_height(), committed.len() as RawHeight);
+                if !committed_changed {
+                    continue;
+                }
+
+                for (height, state) in (1..).zip(&committed) {
+                    let height = Height::Stable(height);
+                    match state {
+                        Some((model, _)) => {
+                            let entries = store.range_at(height, ..);
+                            assert!(entries.eq(model.clone()));
+                        }
+                        None => assert!(store.get_state(height).is_none()),
+                    }
+                }
+                if let Some(Some((model, root_hash))) = committed.last() {
+                    assert_eq!(&store.root_hash(), root_hash);
+                    check_proofs(&store, Height::Latest, model, root_hash, &paths, &spec);
+                }
+            }
+
+            for (height, state) in (1..).zip(&committed) {
+                if let Some((model, root_hash)) = state {
+                    let height = Height::Stable(height);
+                    check_proofs(&store, height, model, root_hash, &paths, &spec);
+                }
+            }
+        }
+    }
+
+    /// Checks the existence or non-existence proof of every path of `paths` at `height` against
+    /// the `model` of the state committed with `root_hash`.
+    fn check_proofs(
+        store: &InMemoryStore,
+        height: Height,
+        model: &Model,
+        root_hash: &Vec<u8>,
+        paths: &[Path],
+        spec: &ProofSpec,
+    ) {
+        // an empty tree has no proofs
+        if model.is_empty() {
+            return;
+        }
+        for path in paths {
+            let proof = store.get_proof(height, path).unwrap();
+            let key = path.as_bytes();
+            match model.get(path) {
+                Some(value) => assert!(verify_membership::<HostFunctionsManager>(
+                    &proof,
+                    spec,
+                    root_hash,
+                    key.as_ref(),
+                    value
+                )),
+                None => assert!(verify_non_membership::<HostFunctionsManager>(
+                    &proof,
+                    spec,
+                    root_hash,
+                    key.as_ref()
+                )),
+            }
+        }
     }
 }
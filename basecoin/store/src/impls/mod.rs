@@ -1,9 +1,19 @@
+pub(crate) mod branch;
 pub(crate) mod growing;
+pub(crate) mod iavl;
 pub(crate) mod in_memory;
+pub(crate) mod persistent;
+pub(crate) mod prefix;
 pub(crate) mod revertible;
 pub(crate) mod shared;
+pub(crate) mod smt;
 
+pub use branch::BranchStore;
 pub use growing::GrowingStore;
+pub use iavl::IavlStore;
 pub use in_memory::InMemoryStore;
+pub use persistent::{Error as PersistentStoreError, PersistentStore, DEFAULT_CHECKPOINT_INTERVAL};
+pub use prefix::PrefixStore;
 pub use revertible::RevertibleStore;
 pub use shared::SharedStore;
+pub use smt::SmtStore;
@@ -0,0 +1,796 @@
+use core::convert::Infallible;
+use core::ops::RangeBounds;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path as FsPath, PathBuf};
+
+use displaydoc::Display as DisplayDoc;
+use ics23::CommitmentProof;
+use tracing::{debug, trace};
+
+use crate::avl::{self, encode_delta, index_nodes, AvlTree, NodeIndex};
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::impls::InMemoryStore;
+use crate::types::{ChangeSet, Height, Path, RawHeight, State};
+
+/// Magic bytes at the start of every checkpoint file.
+const STATE_FILE_MAGIC: &[u8; 4] = b"BCST";
+
+/// Magic bytes at the start of every delta file.
+const DELTA_FILE_MAGIC: &[u8; 4] = b"BCDT";
+
+/// Extension of checkpoint files, which hold the whole state of a height, named
+/// `<height>.state`.
+const STATE_FILE_EXT: &str = "state";
+
+/// Extension of delta files, which hold the nodes committed at a height, named `<height>.delta`.
+const DELTA_FILE_EXT: &str = "delta";
+
+/// Extension of files that are still being written.
+const TMP_FILE_EXT: &str = "tmp";
+
+/// Extension of the empty files marking heights that were pruned while their files are still
+/// needed to restore the following heights, named `<height>.pruned`.
+const PRUNED_FILE_EXT: &str = "pruned";
+
+/// Default number of heights between two checkpoints.
+pub const DEFAULT_CHECKPOINT_INTERVAL: RawHeight = 1000;
+
+#[derive(Debug, DisplayDoc)]
+pub enum Error {
+    /// I/O error on `{path}`: `{error}`
+    Io { path: String, error: io::Error },
+    /// malformed state file for height `{height}`: `{reason}`
+    MalformedState { height: RawHeight, reason: String },
+    /// state for height `{height}` is missing from `{path}`
+    MissingState { height: RawHeight, path: String },
+    /// height `{height}` isn't retained
+    UnavailableHeight { height: RawHeight },
+}
+
+impl Error {
+    fn io(path: &FsPath, error: io::Error) -> Self {
+        Self::Io {
+            path: path.display().to_string(),
+            error,
+        }
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+/// A provable store that persists every committed state to disk.
+///
+/// [`PersistentStore`] keeps the same in-memory layout as [`InMemoryStore`], so root hashes and
+/// proofs are identical for the same sequence of writes. On [`Store::commit`], only the nodes of
+/// the newly committed AVL tree that aren't shared with the previous height are written, to
+/// `<dir>/<height>.delta`, so that a commit writes O(changes · log n) bytes rather than the whole
+/// state. Every [checkpoint interval](Self::checkpoint_interval) heights, the whole tree is also
+/// written to `<dir>/<height>.state`. Files are written via a temporary file and an atomic rename.
+///
+/// [`PersistentStore::open`] decodes the oldest checkpoint it needs and the deltas of the
+/// following heights, whose unchanged nodes are shared between heights as they were before the
+/// restart, so the store resumes at the last committed height. A height that is pruned is marked
+/// with a `<dir>/<height>.pruned` file, as its delta is still needed to restore the following
+/// heights, and the files of the heights before the latest checkpoint that precedes all the
+/// retained heights are removed.
+///
+/// Note that each store is committed independently. An application that commits several stores
+/// per block (e.g. one per module plus the main store) may find them at different heights if the
+/// process is killed in the middle of a block commit, and should [roll back](Self::rollback) the
+/// stores that are ahead before resuming.
+///
+/// A store created with [`Default`] has no backing directory and behaves like an
+/// [`InMemoryStore`].
+#[derive(Clone, Debug)]
+pub struct PersistentStore {
+    /// The in-memory view of all retained heights.
+    inner: InMemoryStore,
+    /// The directory where committed states are written to, if any.
+    dir: Option<PathBuf>,
+    /// The heights of the checkpoints in the backing directory.
+    checkpoints: BTreeSet<RawHeight>,
+    /// The lowest height that may still have files in the backing directory.
+    first_file: RawHeight,
+    /// The number of heights between two checkpoints.
+    checkpoint_interval: RawHeight,
+}
+
+impl Default for PersistentStore {
+    fn default() -> Self {
+        Self {
+            inner: InMemoryStore::default(),
+            dir: None,
+            checkpoints: BTreeSet::new(),
+            first_file: 1,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+impl PersistentStore {
+    /// Opens the store persisted in `dir`, creating the directory if it doesn't exist.
+    pub fn open(dir: impl AsRef<FsPath>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+
+        let (mut deltas, mut checkpoints, mut pruned) =
+            (BTreeSet::new(), BTreeSet::new(), BTreeSet::new());
+        for entry in fs::read_dir(&dir).map_err(|e| Error::io(&dir, e))? {
+            let path = entry.map_err(|e| Error::io(&dir, e))?.path();
+            if let Some(height) = Self::parse_file_name(&path, DELTA_FILE_EXT) {
+                deltas.insert(height);
+            } else if let Some(height) = Self::parse_file_name(&path, STATE_FILE_EXT) {
+                checkpoints.insert(height);
+            } else if let Some(height) = Self::parse_file_name(&path, PRUNED_FILE_EXT) {
+                pruned.insert(height);
+            }
+        }
+        let first_file = [&deltas, &checkpoints, &pruned]
+            .iter()
+            .filter_map(|heights| heights.first().copied())
+            .min()
+            .unwrap_or(1);
+
+        // the oldest checkpoint, or the empty state at height 0, that is followed by the deltas
+        // of all the heights up to the latest one
+        let latest = deltas.last().copied().unwrap_or(0);
+        let mut first_delta = latest + 1;
+        while first_delta > 1 && deltas.contains(&(first_delta - 1)) {
+            first_delta -= 1;
+        }
+        let Some(base) = [0]
+            .into_iter()
+            .chain(checkpoints.range(..=latest).copied())
+            .find(|checkpoint| checkpoint + 1 >= first_delta)
+        else {
+            return Err(Error::MissingState {
+                height: first_delta - 1,
+                path: dir.display().to_string(),
+            });
+        };
+
+        let mut index = NodeIndex::new();
+        let mut states = Vec::new();
+        if base > 0 {
+            let file = Self::state_file(&dir, base);
+            let bytes = fs::read(&file).map_err(|e| Error::io(&file, e))?;
+            let state = decode_state(&bytes).map_err(|reason| Error::MalformedState {
+                height: base,
+                reason,
+            })?;
+            index_nodes(&state.root, &mut index);
+            states.push((!pruned.contains(&base)).then_some(state));
+        }
+        for height in base + 1..=latest {
+            let file = Self::delta_file(&dir, height);
+            let bytes = fs::read(&file).map_err(|e| Error::io(&file, e))?;
+            let state = decode_delta(&bytes, &mut index)
+                .map_err(|reason| Error::MalformedState { height, reason })?;
+            // the latest height is never pruned
+            let is_pruned = height < latest && pruned.contains(&height);
+            states.push((!is_pruned).then_some(state));
+        }
+
+        debug!(
+            "opened store at {} with heights {}..={} from height {}",
+            dir.display(),
+            base.max(1),
+            latest,
+            base
+        );
+
+        Ok(Self {
+            inner: InMemoryStore::restore(states, base.saturating_sub(1) as usize),
+            dir: Some(dir),
+            checkpoints,
+            first_file,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        })
+    }
+
+    /// Sets the number of heights between two checkpoints, which defaults to
+    /// [`DEFAULT_CHECKPOINT_INTERVAL`]. Checkpoints bound the number of deltas that are decoded
+    /// when opening the store, and that are kept on disk for the retained heights.
+    pub fn checkpoint_interval(mut self, interval: RawHeight) -> Self {
+        assert!(interval > 0, "checkpoint interval must be positive");
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    /// Returns the directory backing this store, if any.
+    pub fn dir(&self) -> Option<&FsPath> {
+        self.dir.as_deref()
+    }
+
+    /// Rolls the store back to `height`, removing the later heights and discarding the working
+    /// changes, e.g. to bring it back to the height of the other stores of an application after
+    /// the process was killed in the middle of a block commit. Fails if `height` is ahead of the
+    /// current height or isn't retained.
+    pub fn rollback(&mut self, height: RawHeight) -> Result<(), Error> {
+        let current = self.current_height();
+        let retained = self.inner.get_state(Height::Stable(height)).is_some()
+            || (height == 0 && self.inner.committed().pruned_length() == 0);
+        if height > current || !retained {
+            return Err(Error::UnavailableHeight { height });
+        }
+
+        if let Some(dir) = &self.dir {
+            // remove the latest files first, so that no retained height is followed by a gap
+            for height in (height + 1..=current).rev() {
+                Self::remove_files(dir, height)?;
+            }
+            self.checkpoints.retain(|checkpoint| *checkpoint <= height);
+        }
+        self.inner.rollback(height);
+        Ok(())
+    }
+
+    fn state_file(dir: &FsPath, height: RawHeight) -> PathBuf {
+        dir.join(format!("{height}.{STATE_FILE_EXT}"))
+    }
+
+    fn delta_file(dir: &FsPath, height: RawHeight) -> PathBuf {
+        dir.join(format!("{height}.{DELTA_FILE_EXT}"))
+    }
+
+    fn pruned_file(dir: &FsPath, height: RawHeight) -> PathBuf {
+        dir.join(format!("{height}.{PRUNED_FILE_EXT}"))
+    }
+
+    fn parse_file_name(path: &FsPath, ext: &str) -> Option<RawHeight> {
+        if path.extension()? != ext {
+            return None;
+        }
+        path.file_stem()?.to_str()?.parse().ok()
+    }
+
+    /// Removes the checkpoint, the delta and the pruned marker of specified height.
+    fn remove_files(dir: &FsPath, height: RawHeight) -> Result<(), Error> {
+        Self::remove_file(&Self::state_file(dir, height))?;
+        Self::remove_file(&Self::delta_file(dir, height))?;
+        Self::remove_file(&Self::pruned_file(dir, height))
+    }
+
+    fn remove_file(file: &FsPath) -> Result<(), Error> {
+        match fs::remove_file(file) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::io(file, e)),
+        }
+    }
+
+    /// Writes `bytes` to `file` via a temporary file and an atomic rename.
+    fn write_file(file: &FsPath, bytes: &[u8]) -> Result<(), Error> {
+        let tmp_file = file.with_extension(TMP_FILE_EXT);
+        let mut f = fs::File::create(&tmp_file).map_err(|e| Error::io(&tmp_file, e))?;
+        f.write_all(bytes)
+            .and_then(|_| f.sync_all())
+            .map_err(|e| Error::io(&tmp_file, e))?;
+        fs::rename(&tmp_file, file).map_err(|e| Error::io(file, e))
+    }
+
+    /// Writes the delta of the latest committed state to the `base` state it was derived from,
+    /// and a checkpoint of it if its height is a multiple of the checkpoint interval.
+    fn persist_latest(&mut self, base: &State) -> Result<(), Error> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let height = self.inner.current_height();
+        let state = self
+            .inner
+            .get_state(Height::Latest)
+            .expect("a state was just committed");
+        trace!("persisting height {} to {}", height, dir.display());
+
+        Self::write_file(
+            &Self::delta_file(dir, height),
+            &encode_delta_state(state, base),
+        )?;
+        if height % self.checkpoint_interval == 0 {
+            Self::write_file(&Self::state_file(dir, height), &encode_state(state))?;
+            self.checkpoints.insert(height);
+        }
+        Ok(())
+    }
+
+    /// Marks the `newly_pruned` heights as pruned, and removes the files of the heights before the
+    /// latest checkpoint that precedes all the retained heights, which aren't needed anymore.
+    fn persist_pruning(
+        &mut self,
+        newly_pruned: impl IntoIterator<Item = RawHeight>,
+    ) -> Result<(), Error> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let first_retained = self.inner.committed().pruned_length() as RawHeight + 1;
+        let base = self
+            .checkpoints
+            .range(..=first_retained)
+            .next_back()
+            .copied()
+            .unwrap_or(0);
+
+        for height in newly_pruned.into_iter().filter(|height| *height >= base) {
+            let marker = Self::pruned_file(dir, height);
+            fs::File::create(&marker).map_err(|e| Error::io(&marker, e))?;
+        }
+        // remove the oldest files first, so that the heights from the base checkpoint stay
+        // contiguous
+        for height in self.first_file..base {
+            Self::remove_files(dir, height)?;
+        }
+        self.checkpoints.retain(|checkpoint| *checkpoint >= base);
+        self.first_file = self.first_file.max(base);
+        Ok(())
+    }
+}
+
+impl Store for PersistentStore {
+    type Error = Error;
+
+    fn set(&mut self, path: Path, value: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.inner.set(path, value)?)
+    }
+
+    fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>> {
+        self.inner.get(height, path)
+    }
+
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(self.inner.delete(path)?)
+    }
+
+    fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
+        // the nodes of the new state that are shared with the previous one aren't written again
+        let base = self
+            .inner
+            .get_state(Height::Latest)
+            .cloned()
+            .unwrap_or_default();
+        let root_hash = self.inner.commit()?;
+        self.persist_latest(&base)?;
+        Ok(root_hash)
+    }
+
+    fn apply(&mut self) -> Result<(), Self::Error> {
+        Ok(self.inner.apply()?)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+
+    fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
+        let previously_pruned = self.inner.committed().pruned_length() as RawHeight;
+        let pruned = self.inner.prune(height)?;
+        let newly_pruned = self.inner.committed().pruned_length() as RawHeight;
+        self.persist_pruning(previously_pruned + 1..=newly_pruned)?;
+        Ok(pruned)
+    }
+
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        if self.inner.get_state(Height::Stable(height)).is_none() {
+            return Ok(());
+        }
+        self.inner.prune_at(height)?;
+        // the latest height is never pruned
+        if self.inner.get_state(Height::Stable(height)).is_none() {
+            self.persist_pruning([height])?;
+        }
+        Ok(())
+    }
+
+    fn diff(&self, from: Height, to: Height) -> Option<ChangeSet> {
+        self.inner.diff(from, to)
+    }
+
+    fn change_set(&self, height: RawHeight) -> Option<ChangeSet> {
+        self.inner.change_set(height)
+    }
+
+    fn current_height(&self) -> RawHeight {
+        self.inner.current_height()
+    }
+
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        self.inner.range_at(height, range)
+    }
+
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        self.inner.iter_prefix_at(height, key_prefix)
+    }
+
+    fn get_keys_at(&self, height: Height, key_prefix: &Path) -> Vec<Path> {
+        self.inner.get_keys_at(height, key_prefix)
+    }
+
+    /// The snapshot has no backing directory, so that it never writes to disk.
+    fn snapshot(&self) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.snapshot()?,
+            ..Self::default()
+        })
+    }
+}
+
+impl ProvableStore for PersistentStore {
+    fn root_hash(&self) -> Vec<u8> {
+        self.inner.root_hash()
+    }
+
+    fn working_hash(&self) -> Vec<u8> {
+        self.inner.working_hash()
+    }
+
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.inner.root_hash_at(height)
+    }
+
+    fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
+        self.inner.get_proof(height, key)
+    }
+
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        self.inner.get_batch_proof(height, keys)
+    }
+}
+
+/// Encodes a state as the magic bytes followed by the encoding of its tree (see
+/// [`AvlTree::encode`]), which starts with the encoding version.
+fn encode_state(state: &State) -> Vec<u8> {
+    let mut buf = STATE_FILE_MAGIC.to_vec();
+    buf.extend_from_slice(&state.encode());
+    buf
+}
+
+/// Decodes a state encoded with [`encode_state`], rebuilding the exact same tree.
+fn decode_state(bytes: &[u8]) -> Result<State, String> {
+    let tree = bytes
+        .strip_prefix(STATE_FILE_MAGIC)
+        .ok_or_else(|| "invalid magic bytes".to_owned())?;
+    AvlTree::decode(tree).map_err(|e| e.to_string())
+}
+
+/// Encodes a state as the magic bytes followed by the encoding of its tree as a delta to the
+/// `base` state, which starts with the encoding version.
+fn encode_delta_state(state: &State, base: &State) -> Vec<u8> {
+    let mut buf = DELTA_FILE_MAGIC.to_vec();
+    encode_delta(&state.root, &base.root, &mut buf);
+    buf
+}
+
+/// Decodes a state encoded with [`encode_delta_state`], whose base state's nodes must all be in
+/// `index`. Its new nodes are added to `index`, and it shares the other ones with the base state.
+fn decode_delta(bytes: &[u8], index: &mut NodeIndex<Path, Vec<u8>>) -> Result<State, String> {
+    let delta = bytes
+        .strip_prefix(DELTA_FILE_MAGIC)
+        .ok_or_else(|| "invalid magic bytes".to_owned())?;
+    let root = avl::decode_delta(delta, index).map_err(|e| e.to_string())?;
+    Ok(AvlTree { root })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::avl::NodeRef;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("basecoin-store-{}", rand::random::<u64>()));
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn commit_blocks(store: &mut PersistentStore, blocks: u64) -> Vec<Vec<u8>> {
+        (0..blocks)
+            .map(|block| {
+                for i in 0..20u64 {
+                    let path = Path::from(format!("block/{}/key/{}", block % 3, i));
+                    store.set(path, (block * i).to_be_bytes().to_vec()).unwrap();
+                }
+                store
+                    .delete(&Path::from(format!("block/{}/key/7", (block + 1) % 3)))
+                    .unwrap();
+                store.commit().unwrap()
+            })
+            .collect()
+    }
+
+    /// Returns the addresses of the nodes of the state at `height`.
+    fn node_addresses(store: &PersistentStore, height: RawHeight) -> HashSet<usize> {
+        fn collect(node: &NodeRef<Path, Vec<u8>>, addresses: &mut HashSet<usize>) {
+            if let Some(node) = node {
+                addresses.insert(Arc::as_ptr(node) as usize);
+                collect(&node.left, addresses);
+                collect(&node.right, addresses);
+            }
+        }
+        let mut addresses = HashSet::new();
+        let state = store.inner.get_state(Height::Stable(height)).unwrap();
+        collect(&state.root, &mut addresses);
+        addresses
+    }
+
+    #[test]
+    fn test_reopen_persistent_store() {
+        let tmp = TempDir::new();
+        let mut store = PersistentStore::open(&tmp.0).unwrap();
+        let mut in_memory = InMemoryStore::default();
+        assert_eq!(store.current_height(), 0);
+
+        let root_hashes = commit_blocks(&mut store, 5);
+        for block in 0..5u64 {
+            for i in 0..20u64 {
+                let path = Path::from(format!("block/{}/key/{}", block % 3, i));
+                in_memory
+                    .set(path, (block * i).to_be_bytes().to_vec())
+                    .unwrap();
+            }
+            in_memory
+                .delete(&Path::from(format!("block/{}/key/7", (block + 1) % 3)))
+                .unwrap();
+            in_memory.commit().unwrap();
+        }
+        assert_eq!(store.root_hash(), in_memory.root_hash());
+
+        // uncommitted changes are not persisted
+        store
+            .set(Path::from("uncommitted".to_owned()), vec![1])
+            .unwrap();
+
+        let reopened = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(reopened.current_height(), 5);
+        assert_eq!(reopened.root_hash(), root_hashes[4]);
+        assert_eq!(
+            reopened.get(Height::Pending, &Path::from("uncommitted".to_owned())),
+            None
+        );
+        for height in 1..=5 {
+            let height = Height::Stable(height);
+            assert_eq!(
+                reopened.inner.get_state(height).unwrap().root,
+                store.inner.get_state(height).unwrap().root
+            );
+            for path in [
+                "block/0/key/7",
+                "block/1/key/3",
+                "block/2/key/19",
+                "missing",
+            ] {
+                let path = Path::from(path.to_owned());
+                assert_eq!(reopened.get(height, &path), store.get(height, &path));
+                assert_eq!(
+                    reopened.get_proof(height, &path),
+                    store.get_proof(height, &path)
+                );
+            }
+        }
+
+        // the restored heights share their unchanged nodes as the committed ones do
+        for height in 2..=5 {
+            let shared = |store| {
+                node_addresses(store, height - 1)
+                    .intersection(&node_addresses(store, height))
+                    .count()
+            };
+            assert!(shared(&store) > 0);
+            assert_eq!(shared(&reopened), shared(&store));
+        }
+    }
+
+    #[test]
+    fn test_incremental_persistence() {
+        let tmp = TempDir::new();
+        let mut store = PersistentStore::open(&tmp.0)
+            .unwrap()
+            .checkpoint_interval(10);
+        for i in 0..200u64 {
+            store
+                .set(Path::from(format!("key/{i}")), i.to_be_bytes().to_vec())
+                .unwrap();
+        }
+        store.commit().unwrap();
+        for height in 2..=10u64 {
+            store
+                .set(
+                    Path::from("key/0".to_owned()),
+                    height.to_be_bytes().to_vec(),
+                )
+                .unwrap();
+            store.commit().unwrap();
+        }
+
+        // a commit only writes the nodes on the path to the changed key
+        let size = |file: PathBuf| fs::metadata(file).unwrap().len();
+        let full_size = size(PersistentStore::delta_file(&tmp.0, 1));
+        assert!(size(PersistentStore::delta_file(&tmp.0, 2)) * 10 < full_size);
+        assert!(!PersistentStore::state_file(&tmp.0, 9).exists());
+        assert!(PersistentStore::state_file(&tmp.0, 10).exists());
+
+        let reopened = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(reopened.current_height(), 10);
+        assert_eq!(reopened.root_hash(), store.root_hash());
+        assert_eq!(
+            reopened.get(Height::Stable(4), &Path::from("key/0".to_owned())),
+            Some(4u64.to_be_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn test_prune_persistent_store() {
+        let tmp = TempDir::new();
+        let mut store = PersistentStore::open(&tmp.0)
+            .unwrap()
+            .checkpoint_interval(2);
+        let root_hashes = commit_blocks(&mut store, 6);
+
+        // the heights are restored from the checkpoint at height 4
+        store.prune(3).unwrap();
+        assert!(!PersistentStore::delta_file(&tmp.0, 3).exists());
+        assert!(!PersistentStore::state_file(&tmp.0, 2).exists());
+        assert!(PersistentStore::state_file(&tmp.0, 4).exists());
+
+        let mut reopened = PersistentStore::open(&tmp.0)
+            .unwrap()
+            .checkpoint_interval(2);
+        assert_eq!(reopened.current_height(), 6);
+        assert_eq!(reopened.root_hash(), root_hashes[5]);
+        assert_eq!(
+            reopened.get_proof(Height::Stable(3), &Path::from("a".to_owned())),
+            None
+        );
+        assert!(reopened
+            .get_proof(Height::Stable(4), &Path::from("a".to_owned()))
+            .is_some());
+
+        // the reopened store keeps committing on top of the restored heights
+        let path = Path::from("block/0/key/0".to_owned());
+        reopened.set(path.clone(), vec![42]).unwrap();
+        reopened.commit().unwrap();
+        assert_eq!(reopened.current_height(), 7);
+        assert_eq!(reopened.get(Height::Stable(7), &path), Some(vec![42]));
+        assert_eq!(
+            PersistentStore::open(&tmp.0).unwrap().root_hash(),
+            reopened.root_hash()
+        );
+
+        // the pruned heights from the checkpoint are still needed to restore the later ones,
+        // until a later checkpoint precedes all the retained heights
+        reopened.prune(4).unwrap();
+        assert!(PersistentStore::delta_file(&tmp.0, 4).exists());
+        assert!(PersistentStore::pruned_file(&tmp.0, 4).exists());
+        let restored = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(restored.get(Height::Stable(4), &path), None);
+        assert_eq!(restored.get(Height::Stable(7), &path), Some(vec![42]));
+
+        reopened.prune(5).unwrap();
+        assert!(!PersistentStore::state_file(&tmp.0, 4).exists());
+        assert!(!PersistentStore::delta_file(&tmp.0, 5).exists());
+        let restored = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(restored.current_height(), 7);
+        assert!(restored.get(Height::Stable(6), &path).is_some());
+    }
+
+    #[test]
+    fn test_prune_single_heights() {
+        let tmp = TempDir::new();
+        let mut store = PersistentStore::open(&tmp.0)
+            .unwrap()
+            .checkpoint_interval(2);
+        let root_hashes = commit_blocks(&mut store, 5);
+
+        store.prune_at(2).unwrap();
+        store.prune_at(4).unwrap();
+        // the latest height is never pruned
+        store.prune_at(5).unwrap();
+        assert!(PersistentStore::delta_file(&tmp.0, 2).exists());
+        assert!(PersistentStore::pruned_file(&tmp.0, 2).exists());
+        assert!(!PersistentStore::pruned_file(&tmp.0, 5).exists());
+
+        let path = Path::from("block/0/key/1".to_owned());
+        let reopened = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(reopened.current_height(), 5);
+        assert_eq!(reopened.root_hash(), root_hashes[4]);
+        for (height, kept) in [(1, true), (2, false), (3, true), (4, false), (5, true)] {
+            assert_eq!(reopened.get(Height::Stable(height), &path).is_some(), kept);
+        }
+
+        // pruning the first height uncovers the checkpoint at height 2, before which no file is
+        // needed anymore
+        store.prune_at(1).unwrap();
+        assert!(!PersistentStore::delta_file(&tmp.0, 1).exists());
+        assert!(PersistentStore::pruned_file(&tmp.0, 2).exists());
+        store.prune(4).unwrap();
+        assert!(!PersistentStore::pruned_file(&tmp.0, 2).exists());
+        assert!(PersistentStore::pruned_file(&tmp.0, 4).exists());
+        let reopened = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(
+            reopened.get(Height::Stable(5), &path),
+            store.get(Height::Stable(5), &path)
+        );
+        assert_eq!(reopened.get(Height::Stable(3), &path), None);
+        assert_eq!(reopened.get(Height::Stable(4), &path), None);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let tmp = TempDir::new();
+        let mut store = PersistentStore::open(&tmp.0)
+            .unwrap()
+            .checkpoint_interval(2);
+        let root_hashes = commit_blocks(&mut store, 5);
+        store
+            .set(Path::from("pending".to_owned()), vec![1])
+            .unwrap();
+
+        store.rollback(3).unwrap();
+        assert_eq!(store.current_height(), 3);
+        assert_eq!(store.root_hash(), root_hashes[2]);
+        assert_eq!(
+            store.get(Height::Pending, &Path::from("pending".to_owned())),
+            None
+        );
+        assert!(!PersistentStore::delta_file(&tmp.0, 4).exists());
+        assert!(!PersistentStore::state_file(&tmp.0, 4).exists());
+        let reopened = PersistentStore::open(&tmp.0).unwrap();
+        assert_eq!(reopened.current_height(), 3);
+        assert_eq!(reopened.root_hash(), root_hashes[2]);
+
+        // the store commits the same states again from the rolled back height
+        let mut replayed = PersistentStore::open(&tmp.0).unwrap();
+        let root_hash = commit_blocks(&mut replayed, 1).remove(0);
+        assert_eq!(replayed.current_height(), 4);
+        assert_eq!(
+            PersistentStore::open(&tmp.0).unwrap().root_hash(),
+            root_hash
+        );
+
+        assert!(matches!(
+            store.rollback(4),
+            Err(Error::UnavailableHeight { height: 4 })
+        ));
+        store.prune(2).unwrap();
+        assert!(matches!(
+            store.rollback(1),
+            Err(Error::UnavailableHeight { height: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_malformed_state_file() {
+        let tmp = TempDir::new();
+        let mut store = PersistentStore::open(&tmp.0).unwrap();
+        commit_blocks(&mut store, 2);
+
+        fs::write(PersistentStore::delta_file(&tmp.0, 2), b"BCDT\x01\x00\x01").unwrap();
+        assert!(matches!(
+            PersistentStore::open(&tmp.0),
+            Err(Error::MalformedState { height: 2, .. })
+        ));
+
+        fs::remove_file(PersistentStore::delta_file(&tmp.0, 2)).unwrap();
+        fs::write(PersistentStore::delta_file(&tmp.0, 3), DELTA_FILE_MAGIC).unwrap();
+        assert!(matches!(
+            PersistentStore::open(&tmp.0),
+            Err(Error::MissingState { height: 2, .. })
+        ));
+    }
+}
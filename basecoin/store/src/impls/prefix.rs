@@ -0,0 +1,287 @@
+use core::ops::{Bound, RangeBounds};
+
+use ics23::CommitmentProof;
+
+use crate::avl::AsBytes;
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::types::{ChangeSet, Height, Path, RawHeight};
+
+/// A store that keeps its entries under a prefix of a backing store, so that several stores can
+/// share a single Merkle tree.
+///
+/// The entry at `path` is stored at `<prefix>/<path>` in the backing store, so the prefixed
+/// stores of a shared [`SharedStore`](super::SharedStore) are kept apart as long as no prefix is
+/// a leading segment of another one. Committing, applying, resetting and pruning act on the whole
+/// backing store, and proofs are proofs of the prefixed paths in the backing store's tree.
+///
+/// A store without prefix is a plain wrapper of its backing store, which lets code that expects a
+/// `PrefixStore` use a tree of its own.
+///
+/// A prefixed store of a shared store is itself shared with [`SharedStore::derive`], so that its
+/// reads of committed heights follow the snapshot of the backing store.
+///
+/// [`SharedStore::derive`]: super::SharedStore::derive
+#[derive(Clone, Debug)]
+pub struct PrefixStore<S> {
+    /// backing store
+    store: S,
+    /// prefix of the paths of the entries in the backing store, if any
+    prefix: Option<Path>,
+}
+
+impl<S> PrefixStore<S>
+where
+    S: Store,
+{
+    pub fn new(store: S, prefix: Path) -> Self {
+        Self {
+            store,
+            prefix: Some(prefix),
+        }
+    }
+
+    /// Wraps `store` without prefix, so that its entries are stored at their own paths.
+    pub fn unprefixed(store: S) -> Self {
+        Self {
+            store,
+            prefix: None,
+        }
+    }
+
+    #[inline]
+    pub fn prefix(&self) -> Option<&Path> {
+        self.prefix.as_ref()
+    }
+
+    /// Returns the path of the entry at `path` in the backing store.
+    pub fn prefixed(&self, path: &Path) -> Path {
+        match &self.prefix {
+            Some(prefix) => prefix.clone().join_path(path),
+            None => path.clone(),
+        }
+    }
+
+    /// Returns the bounds of the paths of all the entries in the backing store, and the length of
+    /// the prefix to strip from them.
+    fn bounds(&self) -> (Bound<Path>, Bound<Path>, usize) {
+        match &self.prefix {
+            Some(prefix) => {
+                let mut lower = prefix.as_bytes().as_ref().to_vec();
+                lower.push(b'/');
+                let mut upper = lower.clone();
+                // `/` is followed by `0`, so this is the least path that doesn't start with `lower`
+                *upper.last_mut().expect("not empty") += 1;
+                let prefix_len = lower.len();
+                (
+                    Bound::Included(lower.into()),
+                    Bound::Excluded(upper.into()),
+                    prefix_len,
+                )
+            }
+            None => (Bound::Unbounded, Bound::Unbounded, 0),
+        }
+    }
+
+    fn strip(prefix_len: usize) -> impl Fn(Path) -> Path {
+        move |path| Path::from(&path.as_bytes().as_ref()[prefix_len..])
+    }
+
+    fn strip_change_set(&self, changes: ChangeSet) -> ChangeSet {
+        let (lower, upper, prefix_len) = self.bounds();
+        let strip = Self::strip(prefix_len);
+        changes
+            .into_iter()
+            .filter(|(path, _)| (lower.as_ref(), upper.as_ref()).contains(path))
+            .map(|(path, change)| (strip(path), change))
+            .collect()
+    }
+}
+
+impl<S> Default for PrefixStore<S>
+where
+    S: Default + Store,
+{
+    fn default() -> Self {
+        Self::unprefixed(S::default())
+    }
+}
+
+impl<S> Store for PrefixStore<S>
+where
+    S: Store,
+{
+    type Error = S::Error;
+
+    #[inline]
+    fn set(&mut self, path: Path, value: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error> {
+        let path = self.prefixed(&path);
+        self.store.set(path, value)
+    }
+
+    #[inline]
+    fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>> {
+        self.store.get(height, &self.prefixed(path))
+    }
+
+    #[inline]
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
+        let path = self.prefixed(path);
+        self.store.delete(&path)
+    }
+
+    #[inline]
+    fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
+        self.store.commit()
+    }
+
+    #[inline]
+    fn apply(&mut self) -> Result<(), Self::Error> {
+        self.store.apply()
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.store.reset()
+    }
+
+    #[inline]
+    fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
+        self.store.prune(height)
+    }
+
+    #[inline]
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        self.store.prune_at(height)
+    }
+
+    #[inline]
+    fn current_height(&self) -> RawHeight {
+        self.store.current_height()
+    }
+
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        let (lower, upper, prefix_len) = self.bounds();
+        let prefixed = |bound: Bound<&Path>, unbounded: Bound<Path>| match bound {
+            Bound::Included(path) => Bound::Included(self.prefixed(path)),
+            Bound::Excluded(path) => Bound::Excluded(self.prefixed(path)),
+            Bound::Unbounded => unbounded,
+        };
+        let range = (
+            prefixed(range.start_bound(), lower),
+            prefixed(range.end_bound(), upper),
+        );
+        Box::new(
+            self.store
+                .range_at(height, range)
+                .map(move |(path, value)| (Self::strip(prefix_len)(path), value)),
+        )
+    }
+
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        let (_, _, prefix_len) = self.bounds();
+        Box::new(
+            self.store
+                .iter_prefix_at(height, &self.prefixed(key_prefix))
+                .map(move |(path, value)| (Self::strip(prefix_len)(path), value)),
+        )
+    }
+
+    fn diff(&self, from: Height, to: Height) -> Option<ChangeSet> {
+        let changes = self.store.diff(from, to)?;
+        Some(self.strip_change_set(changes))
+    }
+
+    fn change_set(&self, height: RawHeight) -> Option<ChangeSet> {
+        let changes = self.store.change_set(height)?;
+        Some(self.strip_change_set(changes))
+    }
+
+    fn snapshot(&self) -> Option<Self> {
+        Some(Self {
+            store: self.store.snapshot()?,
+            prefix: self.prefix.clone(),
+        })
+    }
+}
+
+impl<S> ProvableStore for PrefixStore<S>
+where
+    S: ProvableStore,
+{
+    #[inline]
+    fn root_hash(&self) -> Vec<u8> {
+        self.store.root_hash()
+    }
+
+    #[inline]
+    fn working_hash(&self) -> Vec<u8> {
+        self.store.working_hash()
+    }
+
+    #[inline]
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.store.root_hash_at(height)
+    }
+
+    #[inline]
+    fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
+        self.store.get_proof(height, &self.prefixed(key))
+    }
+
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        let keys: Vec<_> = keys.iter().map(|key| self.prefixed(key)).collect();
+        self.store.get_batch_proof(height, &keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{InMemoryStore, SharedStore};
+
+    fn path(path: &str) -> Path {
+        Path::from(path.to_owned())
+    }
+
+    #[test]
+    fn test_prefix_store() {
+        let shared = SharedStore::new(InMemoryStore::default());
+        let mut bank = PrefixStore::new(shared.clone(), path("bank"));
+        // `bank0` follows every path under `bank/`
+        let mut other = PrefixStore::new(shared.clone(), path("bank0"));
+
+        bank.set(path("a"), vec![1]).unwrap();
+        bank.set(path("b/1"), vec![2]).unwrap();
+        other.set(path("a"), vec![3]).unwrap();
+        assert_eq!(
+            shared.get(Height::Pending, &path("bank/b/1")),
+            Some(vec![2])
+        );
+        assert_eq!(other.get(Height::Pending, &path("a")), Some(vec![3]));
+
+        let entries: Vec<_> = bank.range_at(Height::Pending, ..).collect();
+        assert_eq!(entries, [(path("a"), vec![1]), (path("b/1"), vec![2])]);
+        let entries: Vec<_> = bank.range_at(Height::Pending, path("b")..).collect();
+        assert_eq!(entries, [(path("b/1"), vec![2])]);
+        assert_eq!(bank.get_keys(&path("b")), [path("b/1")]);
+
+        // committing any of the stores commits the shared tree
+        let root = bank.commit().unwrap();
+        assert_eq!(other.root_hash(), root);
+        assert_eq!(shared.current_height(), 1);
+        assert!(bank.get_proof(Height::Stable(1), &path("a")).is_some());
+
+        bank.delete(&path("a")).unwrap();
+        shared.share().commit().unwrap();
+        let changes = bank.change_set(2).unwrap();
+        assert_eq!(changes.into_keys().collect::<Vec<_>>(), [path("a")]);
+        assert!(other.change_set(2).unwrap().is_empty());
+
+        // a store without prefix is the backing store itself
+        let unprefixed = PrefixStore::unprefixed(shared);
+        assert_eq!(
+            unprefixed.get(Height::Latest, &path("bank0/a")),
+            Some(vec![3])
+        );
+    }
+}
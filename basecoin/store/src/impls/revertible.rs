@@ -1,8 +1,10 @@
+use core::ops::RangeBounds;
+
 use ics23::CommitmentProof;
 use tracing::trace;
 
-use crate::context::{ProvableStore, Store};
-use crate::types::{Height, Path};
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::types::{ChangeSet, Height, Path};
 
 /// A wrapper store that implements rudimentary `apply()`/`reset()` support for other stores.
 ///
@@ -18,7 +20,7 @@ use crate::types::{Height, Path};
 /// store should have no effect on a failed transaction.
 #[deprecated(
     since = "TBD",
-    note = "RevertibleStore has a bug where using the operation log to revert changes does not guarantee deterministic Merkle root hashes. Use InMemoryStore which implements a correct rollback procedure."
+    note = "RevertibleStore has a bug where using the operation log to revert changes does not guarantee deterministic Merkle root hashes. Use BranchStore, which caches writes until they are written to the backing store, or InMemoryStore which implements a correct rollback procedure."
 )]
 #[derive(Clone, Debug)]
 pub struct RevertibleStore<S> {
@@ -80,7 +82,7 @@ where
     }
 
     #[inline]
-    fn delete(&mut self, path: &Path) {
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
         self.store.delete(path)
     }
 
@@ -102,7 +104,7 @@ where
     /// Revert all operations in the operation log.
     ///
     /// This method doesn't guarantee that the Merkle tree will be reverted to the correct previous root hash.
-    /// It should be avoided. Use `InMemoryStore` directly which implements a correct rollback procedure.
+    /// It should be avoided. Use `BranchStore` or `InMemoryStore` directly which implement a correct rollback procedure.
     ///
     /// GH issue: informalsystems/basecoin-rs#129
     #[inline]
@@ -112,7 +114,9 @@ where
         trace!("Rollback operation log changes");
         while let Some(op) = self.op_log.pop() {
             match op {
-                RevertOp::Delete(path) => self.delete(&path),
+                RevertOp::Delete(path) => {
+                    self.delete(&path).unwrap(); // safety - reset failures are unrecoverable
+                }
                 RevertOp::Set(path, value) => {
                     // FIXME: potential non-termination
                     // self.set() may insert a new op into the op_log
@@ -122,14 +126,44 @@ where
         }
     }
 
+    #[inline]
+    fn prune(&mut self, height: u64) -> Result<u64, Self::Error> {
+        self.store.prune(height)
+    }
+
+    #[inline]
+    fn prune_at(&mut self, height: u64) -> Result<(), Self::Error> {
+        self.store.prune_at(height)
+    }
+
+    #[inline]
+    fn diff(&self, from: Height, to: Height) -> Option<ChangeSet> {
+        self.store.diff(from, to)
+    }
+
+    #[inline]
+    fn change_set(&self, height: u64) -> Option<ChangeSet> {
+        self.store.change_set(height)
+    }
+
     #[inline]
     fn current_height(&self) -> u64 {
         self.store.current_height()
     }
 
     #[inline]
-    fn get_keys(&self, key_prefix: &Path) -> Vec<Path> {
-        self.store.get_keys(key_prefix)
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        self.store.range_at(height, range)
+    }
+
+    #[inline]
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        self.store.iter_prefix_at(height, key_prefix)
+    }
+
+    #[inline]
+    fn get_keys_at(&self, height: Height, key_prefix: &Path) -> Vec<Path> {
+        self.store.get_keys_at(height, key_prefix)
     }
 }
 
@@ -142,8 +176,23 @@ where
         self.store.root_hash()
     }
 
+    #[inline]
+    fn working_hash(&self) -> Vec<u8> {
+        self.store.working_hash()
+    }
+
+    #[inline]
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.store.root_hash_at(height)
+    }
+
     #[inline]
     fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
         self.store.get_proof(height, key)
     }
+
+    #[inline]
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        self.store.get_batch_proof(height, keys)
+    }
 }
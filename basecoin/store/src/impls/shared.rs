@@ -1,23 +1,145 @@
-use core::ops::{Deref, DerefMut};
+use core::fmt::{Debug, Formatter};
+use core::ops::{Deref, DerefMut, RangeBounds};
 use std::sync::{Arc, RwLock};
 
 use ics23::CommitmentProof;
 
-use crate::context::{ProvableStore, Store};
-use crate::types::{Height, Path, RawHeight};
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::types::{ChangeSet, Height, Path, RawHeight};
 use crate::utils::{SharedRw, SharedRwExt};
 
 /// Wraps a store to make it shareable by cloning
+///
+/// If the store supports [snapshots](Store::snapshot), a snapshot of its committed heights is
+/// taken after every commit and pruning, and reads at `Stable` and `Latest` heights, as well as
+/// the current height and the changes between committed heights, are served from it, so that they
+/// never wait for the lock held by writers of the pending state. Only the reads of the pending
+/// state, including its [working hash](ProvableStore::working_hash), take the lock. The snapshot
+/// is refreshed by the commits and prunings made through the [`Store`] methods of the
+/// `SharedStore`, not by those made through a guard of the lock, after which
+/// [`SharedStore::refresh_snapshot`] must be called.
+///
+/// A store that wraps a share of another shared store, such as a [`PrefixStore`] of it, is
+/// committed through that other store. Such a store must be created with [`SharedStore::derive`],
+/// so that its snapshot is derived from the latest snapshot of the other store.
+///
+/// [`PrefixStore`]: super::PrefixStore
 #[derive(Clone, Debug)]
-pub struct SharedStore<S>(SharedRw<S>);
+pub struct SharedStore<S> {
+    /// shared store
+    store: SharedRw<S>,
+    /// snapshot of the committed heights of the store, if it supports snapshots
+    snapshot: Snapshot<S>,
+}
 
-impl<S> SharedStore<S> {
+/// The source of the snapshot of a [`SharedStore`].
+enum Snapshot<S> {
+    /// A snapshot of the store itself, taken after every commit and pruning.
+    Own(SharedRw<Option<Arc<S>>>),
+    /// A snapshot wrapping the latest snapshot of the shared store that the store wraps.
+    Derived(Arc<dyn Fn() -> Option<Arc<S>> + Send + Sync>),
+}
+
+impl<S> Snapshot<S> {
+    fn latest(&self) -> Option<Arc<S>> {
+        match self {
+            Self::Own(snapshot) => snapshot.read_access().clone(),
+            Self::Derived(derive) => derive(),
+        }
+    }
+}
+
+impl<S> Clone for Snapshot<S> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Own(snapshot) => Self::Own(snapshot.clone()),
+            Self::Derived(derive) => Self::Derived(derive.clone()),
+        }
+    }
+}
+
+impl<S: Debug> Debug for Snapshot<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Own(snapshot) => f.debug_tuple("Own").field(snapshot).finish(),
+            Self::Derived(_) => f.write_str("Derived"),
+        }
+    }
+}
+
+impl<S> SharedStore<S>
+where
+    S: Store,
+{
     pub fn new(store: S) -> Self {
-        Self(Arc::new(RwLock::new(store)))
+        let snapshot = store.snapshot().map(Arc::new);
+        Self {
+            store: Arc::new(RwLock::new(store)),
+            snapshot: Snapshot::Own(Arc::new(RwLock::new(snapshot))),
+        }
+    }
+
+    /// Creates the store that `wrap` makes out of a share of `parent`, e.g. a
+    /// [`PrefixStore`](super::PrefixStore) of it. Its snapshot is what `wrap` makes out of the
+    /// latest snapshot of `parent`, so it follows the commits and prunings of `parent`.
+    pub fn derive<P>(
+        parent: &SharedStore<P>,
+        wrap: impl Fn(SharedStore<P>) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        P: Store,
+    {
+        let store = wrap(parent.share());
+        let parent = parent.share();
+        Self {
+            store: Arc::new(RwLock::new(store)),
+            snapshot: Snapshot::Derived(Arc::new(move || Some(Arc::new(wrap(parent.snapshot()?))))),
+        }
+    }
+
+    /// Returns the latest snapshot of the committed heights, if the store supports snapshots.
+    /// The snapshot can be held for as long as needed without blocking writers.
+    pub fn latest_snapshot(&self) -> Option<Arc<S>> {
+        self.snapshot.latest()
+    }
+
+    /// Returns the snapshot to read `height` from, or `None` if it must be read from the store.
+    #[inline]
+    fn snapshot_at(&self, height: Height) -> Option<Arc<S>> {
+        match height {
+            Height::Pending => None,
+            Height::Latest | Height::Stable(_) => self.latest_snapshot(),
+        }
+    }
+
+    /// Returns the snapshot to read both `from` and `to` from, or `None` if they must be read from
+    /// the store.
+    #[inline]
+    fn snapshot_between(&self, from: Height, to: Height) -> Option<Arc<S>> {
+        self.snapshot_at(from).and(self.snapshot_at(to))
     }
 
+    /// Takes a new snapshot of the committed heights of the store, e.g. after it was committed or
+    /// pruned through a guard of the lock.
+    pub fn refresh_snapshot(&self) {
+        let store = self.read_access();
+        self.update_snapshot(&store);
+    }
+
+    /// Takes a new snapshot of `store`, unless the snapshot is derived from another store.
+    fn update_snapshot(&self, store: &S) {
+        if let Snapshot::Own(snapshot) = &self.snapshot {
+            *snapshot.write_access() = store.snapshot().map(Arc::new);
+        }
+    }
+}
+
+impl<S> SharedStore<S> {
     pub fn share(&self) -> Self {
-        Self(self.0.clone())
+        Self {
+            store: self.store.clone(),
+            snapshot: self.snapshot.clone(),
+        }
     }
 }
 
@@ -43,17 +165,22 @@ where
 
     #[inline]
     fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>> {
-        self.read_access().get(height, path)
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.get(height, path),
+            None => self.read_access().get(height, path),
+        }
     }
 
     #[inline]
-    fn delete(&mut self, path: &Path) {
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
         self.write_access().delete(path)
     }
 
-    #[inline]
     fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
-        self.write_access().commit()
+        let mut store = self.write_access();
+        let root_hash = store.commit()?;
+        self.update_snapshot(&store);
+        Ok(root_hash)
     }
 
     #[inline]
@@ -66,14 +193,75 @@ where
         self.write_access().reset()
     }
 
+    fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
+        let mut store = self.write_access();
+        let pruned = store.prune(height)?;
+        self.update_snapshot(&store);
+        Ok(pruned)
+    }
+
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        let mut store = self.write_access();
+        store.prune_at(height)?;
+        self.update_snapshot(&store);
+        Ok(())
+    }
+
     #[inline]
     fn current_height(&self) -> RawHeight {
-        self.read_access().current_height()
+        match self.latest_snapshot() {
+            Some(snapshot) => snapshot.current_height(),
+            None => self.read_access().current_height(),
+        }
     }
 
     #[inline]
-    fn get_keys(&self, key_prefix: &Path) -> Vec<Path> {
-        self.read_access().get_keys(key_prefix)
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.range_at(height, range),
+            None => self.read_access().range_at(height, range),
+        }
+    }
+
+    #[inline]
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.iter_prefix_at(height, key_prefix),
+            None => self.read_access().iter_prefix_at(height, key_prefix),
+        }
+    }
+
+    #[inline]
+    fn diff(&self, from: Height, to: Height) -> Option<ChangeSet> {
+        match self.snapshot_between(from, to) {
+            Some(snapshot) => snapshot.diff(from, to),
+            None => self.read_access().diff(from, to),
+        }
+    }
+
+    #[inline]
+    fn change_set(&self, height: RawHeight) -> Option<ChangeSet> {
+        match self.latest_snapshot() {
+            Some(snapshot) => snapshot.change_set(height),
+            None => self.read_access().change_set(height),
+        }
+    }
+
+    #[inline]
+    fn get_keys_at(&self, height: Height, key_prefix: &Path) -> Vec<Path> {
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.get_keys_at(height, key_prefix),
+            None => self.read_access().get_keys_at(height, key_prefix),
+        }
+    }
+
+    /// The snapshot of a shared store is a shared store of its latest snapshot.
+    fn snapshot(&self) -> Option<Self> {
+        let snapshot = self.latest_snapshot()?;
+        Some(Self {
+            store: Arc::new(RwLock::new(S::clone(&snapshot))),
+            snapshot: Snapshot::Own(Arc::new(RwLock::new(Some(snapshot)))),
+        })
     }
 }
 
@@ -83,12 +271,40 @@ where
 {
     #[inline]
     fn root_hash(&self) -> Vec<u8> {
-        self.read_access().root_hash()
+        match self.snapshot_at(Height::Latest) {
+            Some(snapshot) => snapshot.root_hash(),
+            None => self.read_access().root_hash(),
+        }
+    }
+
+    /// The working hash is the one of the pending state, which only the store holds.
+    #[inline]
+    fn working_hash(&self) -> Vec<u8> {
+        self.read_access().working_hash()
+    }
+
+    #[inline]
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.root_hash_at(height),
+            None => self.read_access().root_hash_at(height),
+        }
     }
 
     #[inline]
     fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
-        self.read_access().get_proof(height, key)
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.get_proof(height, key),
+            None => self.read_access().get_proof(height, key),
+        }
+    }
+
+    #[inline]
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        match self.snapshot_at(height) {
+            Some(snapshot) => snapshot.get_batch_proof(height, keys),
+            None => self.read_access().get_batch_proof(height, keys),
+        }
     }
 }
 
@@ -96,12 +312,106 @@ impl<S> Deref for SharedStore<S> {
     type Target = Arc<RwLock<S>>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.store
     }
 }
 
 impl<S> DerefMut for SharedStore<S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{InMemoryStore, PrefixStore};
+
+    #[test]
+    fn test_snapshot_reads() {
+        let path = Path::from("a/1".to_owned());
+        let mut store = SharedStore::new(InMemoryStore::default());
+        store.set(path.clone(), vec![1]).unwrap();
+        store.commit().unwrap();
+        store.set(path.clone(), vec![2]).unwrap();
+
+        {
+            // reads of committed heights don't wait for the writer, which would deadlock here
+            let _writer = store.write_access();
+            assert_eq!(store.get(Height::Latest, &path), Some(vec![1]));
+            assert_eq!(store.get(Height::Stable(1), &path), Some(vec![1]));
+            assert_eq!(store.get(Height::Stable(2), &path), None);
+            assert!(store.get_proof(Height::Latest, &path).is_some());
+            assert_eq!(
+                store.get_keys_at(Height::Latest, &Path::from("a".to_owned())),
+                core::slice::from_ref(&path)
+            );
+            assert_eq!(store.range_at(Height::Stable(1), ..).count(), 1);
+            assert_eq!(store.current_height(), 1);
+            assert_eq!(store.change_set(1).unwrap().len(), 1);
+            assert!(store.diff(Height::Stable(0), Height::Latest).is_some());
+        }
+        assert_eq!(store.get(Height::Pending, &path), Some(vec![2]));
+
+        // a held snapshot isn't affected by later commits
+        let snapshot = store.latest_snapshot().unwrap();
+        let root_hash = store.commit().unwrap();
+        assert_eq!(store.root_hash(), root_hash);
+        assert_eq!(store.get(Height::Latest, &path), Some(vec![2]));
+        assert_eq!(snapshot.get(Height::Latest, &path), Some(vec![1]));
+
+        store.prune(1).unwrap();
+        assert_eq!(store.get(Height::Stable(1), &path), None);
+        assert_eq!(store.get(Height::Stable(2), &path), Some(vec![2]));
+    }
+
+    #[test]
+    fn test_refresh_snapshot() {
+        let path = Path::from("a".to_owned());
+        let mut store = SharedStore::new(InMemoryStore::default());
+        store.set(path.clone(), vec![1]).unwrap();
+
+        // committing through a guard doesn't refresh the snapshot
+        store.write_access().commit().unwrap();
+        assert_eq!(store.get(Height::Latest, &path), None);
+        store.refresh_snapshot();
+        assert_eq!(store.get(Height::Latest, &path), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_derived_snapshot() {
+        let path = Path::from("a".to_owned());
+        let main = SharedStore::new(InMemoryStore::default());
+        let prefix = Path::from("module".to_owned());
+        let mut module =
+            SharedStore::derive(&main, move |main| PrefixStore::new(main, prefix.clone()));
+        module.set(path.clone(), vec![1]).unwrap();
+
+        // the snapshot of the module store follows the commits and prunings of the backing store
+        main.share().commit().unwrap();
+        assert_eq!(module.get(Height::Latest, &path), Some(vec![1]));
+        assert_eq!(module.root_hash(), main.root_hash());
+        module.set(path.clone(), vec![2]).unwrap();
+        main.share().commit().unwrap();
+        assert_eq!(module.get(Height::Stable(1), &path), Some(vec![1]));
+        main.share().prune(1).unwrap();
+        assert_eq!(module.get(Height::Stable(1), &path), None);
+        assert_eq!(module.get(Height::Latest, &path), Some(vec![2]));
+
+        {
+            // neither reads of committed heights nor snapshots wait for the writers
+            let _writer = main.write_access();
+            let _module_writer = module.write_access();
+            assert_eq!(module.get(Height::Stable(2), &path), Some(vec![2]));
+            let snapshot = module.snapshot().unwrap();
+            assert_eq!(snapshot.get(Height::Pending, &path), Some(vec![2]));
+        }
+
+        // a snapshot of the module store isn't affected by later commits
+        let snapshot = module.snapshot().unwrap();
+        module.set(path.clone(), vec![3]).unwrap();
+        main.share().commit().unwrap();
+        assert_eq!(module.get(Height::Latest, &path), Some(vec![3]));
+        assert_eq!(snapshot.get(Height::Latest, &path), Some(vec![2]));
     }
 }
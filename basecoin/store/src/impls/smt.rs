@@ -0,0 +1,260 @@
+use core::convert::Infallible;
+use core::iter;
+use core::ops::RangeBounds;
+
+use ics23::CommitmentProof;
+use tracing::trace;
+
+use crate::avl::AvlTree;
+use crate::context::{ProvableStore, Store, StoreIter};
+use crate::impls::in_memory::PrunedVec;
+use crate::smt::{SparseMerkleTree, EMPTY_CHILD};
+use crate::types::{Height, Path, RawHeight};
+
+/// The state of a [`SmtStore`] at some height.
+#[derive(Clone, Debug, Default)]
+struct SmtState {
+    /// The tree holding the entries, which commits to them and proves them.
+    tree: SparseMerkleTree<Path, Vec<u8>>,
+    /// The paths of the entries, in path order, as the tree orders them by their hash.
+    paths: AvlTree<Path, [u8; 0]>,
+}
+
+impl SmtState {
+    fn into_entries(
+        self,
+        paths: impl DoubleEndedIterator<Item = (Path, [u8; 0])> + Send + 'static,
+    ) -> StoreIter {
+        let tree = self.tree;
+        Box::new(paths.filter_map(move |(path, _)| {
+            let value = tree.get(&path)?.clone();
+            Some((path, value))
+        }))
+    }
+}
+
+/// An in-memory store backed by a [`SparseMerkleTree`], whose root hash only depends on the
+/// entries it holds, regardless of the order in which they were written or deleted.
+///
+/// The store works like [`InMemoryStore`](super::InMemoryStore), with `pending` and `staged`
+/// copies of the working state. Its proofs verify with
+/// [`smt::get_proof_spec`](crate::smt::get_proof_spec), and the root hash of the empty store is
+/// 32 bytes of zeroes.
+#[derive(Clone, Debug, Default)]
+pub struct SmtStore {
+    /// A collection of states corresponding to every committed block height.
+    store: PrunedVec<SmtState>,
+    /// The changes made as a result of successful transactions that are staged
+    /// and waiting to be committed.
+    staged: SmtState,
+    /// The dirty changes resulting from transactions that have not yet completed.
+    pending: SmtState,
+}
+
+impl SmtStore {
+    #[inline]
+    fn get_state(&self, height: Height) -> Option<&SmtState> {
+        match height {
+            Height::Pending => Some(&self.pending),
+            Height::Latest => self.store.last(),
+            Height::Stable(height) => {
+                if height == 0 {
+                    None
+                } else {
+                    let h = height as usize;
+                    self.store.get(h - 1)
+                }
+            }
+        }
+    }
+}
+
+impl Store for SmtStore {
+    type Error = Infallible;
+
+    fn set(&mut self, path: Path, value: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error> {
+        trace!("set at path = {}", path.to_string());
+        self.pending.paths.insert(path.clone(), []);
+        Ok(self.pending.tree.insert(path, value))
+    }
+
+    fn get(&self, height: Height, path: &Path) -> Option<Vec<u8>> {
+        trace!(
+            "get at path = {} at height = {:?}",
+            path.to_string(),
+            height
+        );
+        self.get_state(height)
+            .and_then(|v| v.tree.get(path).cloned())
+    }
+
+    fn delete(&mut self, path: &Path) -> Result<(), Self::Error> {
+        if self.pending.tree.remove(path).is_some() {
+            self.pending.paths.remove(path.clone());
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<Vec<u8>, Self::Error> {
+        self.apply()?;
+        trace!("committing height: {}", self.current_height());
+        self.store.push(self.staged.clone());
+        Ok(self.root_hash())
+    }
+
+    fn apply(&mut self) -> Result<(), Self::Error> {
+        trace!("applying height: {}", self.current_height());
+        self.staged = self.pending.clone();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        trace!("resetting height: {}", self.current_height());
+        self.pending = self.staged.clone();
+    }
+
+    fn prune(&mut self, height: RawHeight) -> Result<RawHeight, Self::Error> {
+        let h = height as usize;
+        self.store.prune(h);
+        Ok(height)
+    }
+
+    fn prune_at(&mut self, height: RawHeight) -> Result<(), Self::Error> {
+        if let Some(h) = (height as usize).checked_sub(1) {
+            self.store.remove(h);
+        }
+        Ok(())
+    }
+
+    fn current_height(&self) -> u64 {
+        self.store.original_length() as u64
+    }
+
+    fn range_at(&self, height: Height, range: impl RangeBounds<Path>) -> StoreIter {
+        // cloning a state only clones references to the root nodes of its trees
+        match self.get_state(height) {
+            Some(state) => {
+                let paths = state.paths.clone().into_range(range);
+                state.clone().into_entries(paths)
+            }
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    fn iter_prefix_at(&self, height: Height, key_prefix: &Path) -> StoreIter {
+        match self.get_state(height) {
+            Some(state) => {
+                let paths = state.paths.clone().into_iter_prefix(key_prefix);
+                state.clone().into_entries(paths)
+            }
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    /// The snapshot shares the committed states, which are immutable, and starts out its
+    /// working copies from the latest one.
+    fn snapshot(&self) -> Option<Self> {
+        let latest = self.store.last().cloned().unwrap_or_default();
+        Some(Self {
+            store: self.store.clone(),
+            staged: latest.clone(),
+            pending: latest,
+        })
+    }
+}
+
+impl ProvableStore for SmtStore {
+    fn root_hash(&self) -> Vec<u8> {
+        state_hash(self.get_state(Height::Latest))
+    }
+
+    fn working_hash(&self) -> Vec<u8> {
+        state_hash(self.get_state(Height::Pending))
+    }
+
+    fn root_hash_at(&self, height: Height) -> Option<Vec<u8>> {
+        self.get_state(height).map(|state| state_hash(Some(state)))
+    }
+
+    fn get_proof(&self, height: Height, key: &Path) -> Option<CommitmentProof> {
+        trace!(
+            "get proof at path = {} at height = {:?}",
+            key.to_string(),
+            height
+        );
+        self.get_state(height).map(|v| v.tree.get_proof(key))
+    }
+
+    fn get_batch_proof(&self, height: Height, keys: &[Path]) -> Option<CommitmentProof> {
+        trace!(
+            "get batch proof of {} paths at height = {:?}",
+            keys.len(),
+            height
+        );
+        self.get_state(height).map(|v| v.tree.get_batch_proof(keys))
+    }
+}
+
+/// Returns the root hash of `state`, or the hash of an empty tree.
+fn state_hash(state: Option<&SmtState>) -> Vec<u8> {
+    state
+        .and_then(|s| s.tree.root_hash())
+        .map(|hash| hash.as_bytes().to_vec())
+        .unwrap_or_else(|| EMPTY_CHILD.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use ics23::{verify_membership, verify_non_membership, HostFunctionsManager};
+
+    use super::*;
+    use crate::smt::get_proof_spec;
+
+    #[test]
+    fn test_smt_store() {
+        let mut store = SmtStore::default();
+        assert_eq!(store.root_hash(), EMPTY_CHILD.to_vec());
+
+        let (a, b) = (Path::from("a".to_owned()), Path::from("b/1".to_owned()));
+        store.set(b.clone(), vec![2]).unwrap();
+        store.set(a.clone(), vec![1]).unwrap();
+        let root_1 = store.commit().unwrap();
+
+        store.set(a.clone(), vec![3]).unwrap();
+        store.delete(&b).unwrap();
+        store.reset();
+        assert_eq!(store.get(Height::Pending, &a), Some(vec![1]));
+        store.delete(&b).unwrap();
+        let root_2 = store.commit().unwrap();
+        assert_ne!(root_1, root_2);
+        assert_eq!(store.current_height(), 2);
+
+        // the root only depends on the entries held by the store
+        let mut other = SmtStore::default();
+        other.set(a.clone(), vec![1]).unwrap();
+        assert_eq!(other.commit().unwrap(), root_2);
+
+        let spec = get_proof_spec();
+        let proof = store.get_proof(Height::Stable(1), &b).unwrap();
+        assert!(verify_membership::<HostFunctionsManager>(
+            &proof,
+            &spec,
+            &root_1,
+            b"b/1",
+            &[2]
+        ));
+        let proof = store.get_proof(Height::Latest, &b).unwrap();
+        assert!(verify_non_membership::<HostFunctionsManager>(
+            &proof, &spec, &root_2, b"b/1"
+        ));
+
+        let entries: Vec<_> = store.range_at(Height::Stable(1), ..).collect();
+        assert_eq!(entries, vec![(a.clone(), vec![1]), (b.clone(), vec![2])]);
+        let keys: Vec<_> = store
+            .iter_prefix_at(Height::Stable(1), &Path::from("b".to_owned()))
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b]);
+        assert_eq!(store.range_at(Height::Latest, ..).rev().count(), 1);
+    }
+}
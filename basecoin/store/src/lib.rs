@@ -1,5 +1,7 @@
 pub mod avl;
 pub mod context;
+pub mod iavl;
 pub mod impls;
+pub mod smt;
 pub mod types;
 pub mod utils;
@@ -0,0 +1,25 @@
+//! # Sparse Merkle Tree
+//!
+//! This module hosts an implementation of a sparse Merkle tree, in which entries are positioned
+//! by the SHA-256 hash of their key. Unlike AVL trees, the shape of the tree only depends on the
+//! set of entries it holds, so its root hash is the same however and in whichever order these
+//! entries were written. Proofs of existence and non-existence verify with the standard ICS23
+//! `SmtSpec` (see [`get_proof_spec`]).
+//!
+//! Keys needs to implement `AsBytes`, while values are required to implement `Borrow<[u8]>`.
+//! As entries are ordered by the hash of their key, the tree doesn't support range iteration.
+
+mod node;
+mod proof;
+mod tree;
+
+pub use node::{KeyPath, NodeRef, SmtNode};
+pub(crate) use proof::EMPTY_CHILD;
+pub use proof::{get_leaf_op, get_proof_spec};
+pub use tree::SparseMerkleTree;
+
+#[cfg(test)]
+mod tests;
+
+use tendermint::hash::Algorithm;
+const HASH_ALGO: Algorithm = Algorithm::Sha256;
@@ -0,0 +1,115 @@
+use core::borrow::Borrow;
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use tendermint::hash::Hash;
+
+use super::proof::{EMPTY_CHILD, INNER_PREFIX, LEAF_PREFIX};
+use crate::smt::HASH_ALGO;
+
+/// The position of a leaf in the tree, i.e. the SHA-256 hash of its key.
+pub type KeyPath = [u8; 32];
+
+/// A reference-counted pointer to a node, or `None` for an empty subtree.
+///
+/// Nodes are updated with copy-on-write through [`Arc::make_mut`], so that cloned trees share all
+/// their unchanged subtrees.
+pub type NodeRef<K, V> = Option<Arc<SmtNode<K, V>>>;
+
+/// A node in the sparse Merkle tree.
+///
+/// A subtree holding a single leaf is replaced by that leaf, so that leaves sit at the shallowest
+/// depth at which their path differs from the paths of all other leaves.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum SmtNode<K, V> {
+    Leaf {
+        path: KeyPath,
+        key: K,
+        value: V,
+        hash: Hash,
+    },
+    Inner {
+        left: NodeRef<K, V>,
+        right: NodeRef<K, V>,
+        hash: Hash,
+    },
+}
+
+/// Returns the bit of `path` at specified depth, most significant bit first.
+pub(crate) fn bit(path: &KeyPath, depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// Returns the path of a key.
+pub(crate) fn key_path(key: &[u8]) -> KeyPath {
+    Sha256::digest(key).into()
+}
+
+impl<K, V: Borrow<[u8]>> SmtNode<K, V> {
+    pub(crate) fn leaf(path: KeyPath, key: K, value: V) -> Self {
+        let hash = Self::leaf_hash(&path, &value);
+        Self::Leaf {
+            path,
+            key,
+            value,
+            hash,
+        }
+    }
+
+    pub(crate) fn inner(left: NodeRef<K, V>, right: NodeRef<K, V>) -> Self {
+        let mut node = Self::Inner {
+            left,
+            right,
+            hash: Hash::None,
+        };
+        node.update();
+        node
+    }
+
+    pub fn hash(&self) -> &Hash {
+        match self {
+            Self::Leaf { hash, .. } | Self::Inner { hash, .. } => hash,
+        }
+    }
+
+    /// Set the value of a leaf, and return the previous value.
+    pub(crate) fn set_value(&mut self, new_value: V) -> Option<V> {
+        let Self::Leaf {
+            path, value, hash, ..
+        } = self
+        else {
+            return None;
+        };
+        *hash = Self::leaf_hash(path, &new_value);
+        Some(core::mem::replace(value, new_value))
+    }
+
+    /// Update the hash of an inner node by looking at its two children.
+    pub(crate) fn update(&mut self) {
+        if let Self::Inner { left, right, hash } = self {
+            let mut sha = Sha256::new();
+            sha.update(INNER_PREFIX);
+            sha.update(child_hash(left));
+            sha.update(child_hash(right));
+            *hash = Hash::from_bytes(HASH_ALGO, &sha.finalize()).unwrap();
+        }
+    }
+
+    fn leaf_hash(path: &KeyPath, value: &V) -> Hash {
+        let mut sha = Sha256::new();
+        sha.update(LEAF_PREFIX);
+        sha.update(path);
+        sha.update(Sha256::digest(value.borrow()));
+        Hash::from_bytes(HASH_ALGO, &sha.finalize()).unwrap()
+    }
+}
+
+/// The hash of a child, or the placeholder of empty subtrees.
+pub(crate) fn child_hash<K, V>(node_ref: &NodeRef<K, V>) -> &[u8] {
+    match node_ref {
+        Some(node) => match node.as_ref() {
+            SmtNode::Leaf { hash, .. } | SmtNode::Inner { hash, .. } => hash.as_bytes(),
+        },
+        None => &EMPTY_CHILD,
+    }
+}
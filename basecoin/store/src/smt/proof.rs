@@ -0,0 +1,25 @@
+//! # ICS23 Proof
+//!
+//! This module provides the ICS23 proof spec of the sparse Merkle tree, which is the standard
+//! ICS23 `SmtSpec`.
+use ics23::{HashOp, LeafOp, LengthOp, ProofSpec};
+
+pub const LEAF_PREFIX: [u8; 1] = [0];
+pub const INNER_PREFIX: [u8; 1] = [1];
+pub const EMPTY_CHILD: [u8; 32] = [0; 32]; // 32 bytes of zeroes.
+
+/// Return the `LeafOp` of the sparse Merkle tree.
+pub fn get_leaf_op() -> LeafOp {
+    LeafOp {
+        hash: HashOp::Sha256.into(),
+        prehash_key: HashOp::Sha256.into(),
+        prehash_value: HashOp::Sha256.into(),
+        length: LengthOp::NoPrefix.into(),
+        prefix: LEAF_PREFIX.to_vec(),
+    }
+}
+
+/// Return the `ProofSpec` of the sparse Merkle tree.
+pub fn get_proof_spec() -> ProofSpec {
+    ics23::smt_spec()
+}
@@ -0,0 +1,221 @@
+//! # Test suite of the sparse Merkle tree.
+
+use std::collections::BTreeMap;
+
+use ics23::commitment_proof::Proof;
+use ics23::{
+    verify_batch_membership, verify_batch_non_membership, verify_membership, verify_non_membership,
+    HostFunctionsManager,
+};
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::smt::node::{bit, child_hash, key_path};
+use crate::smt::*;
+
+#[test]
+fn leaf_hash() {
+    let mut tree = SparseMerkleTree::new();
+    tree.insert(b"key".to_vec(), b"value".to_vec());
+
+    // the leaf prefix, then the hashes of the key and of the value
+    let mut bytes = vec![0];
+    bytes.extend(Sha256::digest(b"key"));
+    bytes.extend(Sha256::digest(b"value"));
+    assert_eq!(
+        tree.root_hash().unwrap().as_bytes(),
+        Sha256::digest(bytes).as_slice()
+    );
+}
+
+#[test]
+fn root_is_independent_of_history() {
+    let mut keys: Vec<u8> = (0..=255).collect();
+
+    let mut reference = SparseMerkleTree::new();
+    for &i in keys.iter().filter(|&&i| i % 3 != 0) {
+        reference.insert(vec![i], vec![i]);
+    }
+
+    for _ in 0..4 {
+        // write every key in a random order, then remove the multiples of 3 in a random order
+        let mut tree = SparseMerkleTree::new();
+        keys.shuffle(&mut thread_rng());
+        for &i in keys.iter() {
+            tree.insert(vec![i], vec![!i]);
+        }
+        keys.shuffle(&mut thread_rng());
+        for &i in keys.iter() {
+            if i % 3 == 0 {
+                assert_eq!(tree.remove(&[i][..]), Some(vec![!i]));
+            } else {
+                assert_eq!(tree.insert(vec![i], vec![i]), Some(vec![!i]));
+            }
+        }
+        assert!(check_integrity(&tree.root, 0));
+        assert_eq!(tree, reference);
+        assert_eq!(tree.root_hash(), reference.root_hash());
+    }
+}
+
+#[test]
+fn random_insert_get_remove() {
+    let mut rng = thread_rng();
+    let mut tree = SparseMerkleTree::new();
+    let mut std_tree = BTreeMap::new();
+
+    for _ in 0..2000 {
+        let key = vec![rng.gen_range(0..64u8)];
+        if rng.gen_bool(0.6) {
+            let value = vec![rng.gen()];
+            assert_eq!(
+                tree.insert(key.clone(), value.clone()),
+                std_tree.insert(key, value)
+            );
+        } else {
+            assert_eq!(tree.remove(&key), std_tree.remove(&key));
+        }
+        assert!(check_integrity(&tree.root, 0));
+    }
+    for key in 0..64u8 {
+        assert_eq!(tree.get(&[key][..]), std_tree.get(&vec![key]));
+    }
+
+    for key in std_tree.keys() {
+        tree.remove(key);
+    }
+    assert_eq!(tree.root, None);
+}
+
+#[test]
+fn proofs() {
+    let mut existing_keys = ["C", "E", "G", "I", "K", "M", "O", "Q", "S", "U"];
+    let non_existing_keys = ["A", "B", "D", "F", "H", "J", "L", "N", "P", "R", "T", "V"];
+
+    existing_keys.shuffle(&mut thread_rng());
+
+    let mut tree = SparseMerkleTree::new();
+    for &key in existing_keys.iter() {
+        tree.insert(key, key.to_lowercase().into_bytes());
+    }
+
+    let root = tree.root_hash().unwrap().as_bytes().to_vec();
+    let spec = get_proof_spec();
+
+    for &key in existing_keys.iter() {
+        let proof = tree.get_proof(key);
+        assert!(matches!(proof.proof, Some(Proof::Exist(_))));
+        assert!(
+            verify_membership::<HostFunctionsManager>(
+                &proof,
+                &spec,
+                &root,
+                key.as_bytes(),
+                tree.get(key).unwrap()
+            ),
+            "Failed to verify membership for key {}",
+            key
+        );
+    }
+
+    for &key in non_existing_keys.iter() {
+        let proof = tree.get_proof(key);
+        assert!(
+            verify_non_membership::<HostFunctionsManager>(&proof, &spec, &root, key.as_bytes()),
+            "Failed to verify non-membership for key {}",
+            key
+        );
+    }
+
+    let items: BTreeMap<&[u8], &[u8]> = existing_keys
+        .iter()
+        .map(|&key| (str::as_bytes(key), tree.get(key).unwrap().as_slice()))
+        .collect();
+    let absent: Vec<&[u8]> = non_existing_keys
+        .iter()
+        .map(|key| str::as_bytes(key))
+        .collect();
+    let keys = existing_keys
+        .iter()
+        .chain(non_existing_keys.iter())
+        .copied();
+    for proof in [
+        tree.get_batch_proof(keys.clone()),
+        tree.get_compressed_batch_proof(keys),
+    ] {
+        assert!(verify_batch_membership::<HostFunctionsManager>(
+            &proof,
+            &spec,
+            &root,
+            items.clone()
+        ));
+        assert!(verify_batch_non_membership::<HostFunctionsManager>(
+            &proof, &spec, &root, &absent
+        ));
+    }
+}
+
+#[test]
+fn single_leaf_proofs() {
+    let mut tree = SparseMerkleTree::new();
+    tree.insert("a", vec![1]);
+
+    let root = tree.root_hash().unwrap().as_bytes().to_vec();
+    let spec = get_proof_spec();
+    assert!(verify_membership::<HostFunctionsManager>(
+        &tree.get_proof("a"),
+        &spec,
+        &root,
+        b"a",
+        &[1]
+    ));
+    for key in ["b", "c", "d"] {
+        assert!(verify_non_membership::<HostFunctionsManager>(
+            &tree.get_proof(key),
+            &spec,
+            &root,
+            key.as_bytes()
+        ));
+    }
+}
+
+/// Check that leaves sit on their path, that inner nodes hold more than a single leaf, and that
+/// hashes are correct.
+fn check_integrity(node_ref: &NodeRef<Vec<u8>, Vec<u8>>, depth: usize) -> bool {
+    let Some(node) = node_ref else {
+        return true;
+    };
+    match node.as_ref() {
+        SmtNode::Leaf { path, key, .. } => *path == key_path(key),
+        SmtNode::Inner { left, right, .. } => {
+            let single_leaf =
+                |child: &NodeRef<_, _>| matches!(child.as_deref(), Some(SmtNode::Leaf { .. }));
+            let collapsible = match (left, right) {
+                (None, None) => true,
+                (Some(_), None) => single_leaf(left),
+                (None, Some(_)) => single_leaf(right),
+                _ => false,
+            };
+            let mut updated = node.as_ref().clone();
+            updated.update();
+            !collapsible
+                && updated == **node
+                && under(left, depth, false)
+                && under(right, depth, true)
+                && check_integrity(left, depth + 1)
+                && check_integrity(right, depth + 1)
+        }
+    }
+}
+
+/// Check that the leaves of a child subtree have the bit of its side at the parent's depth.
+fn under(node_ref: &NodeRef<Vec<u8>, Vec<u8>>, depth: usize, side: bool) -> bool {
+    match node_ref.as_deref() {
+        None => child_hash(node_ref) == [0; 32],
+        Some(SmtNode::Leaf { path, .. }) => bit(path, depth) == side,
+        Some(SmtNode::Inner { left, right, .. }) => {
+            under(left, depth, side) && under(right, depth, side)
+        }
+    }
+}
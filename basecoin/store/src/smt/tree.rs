@@ -0,0 +1,357 @@
+use core::borrow::Borrow;
+use std::sync::Arc;
+
+use ics23::commitment_proof::Proof;
+use ics23::{
+    batch_entry, BatchEntry, BatchProof, CommitmentProof, ExistenceProof, HashOp, InnerOp,
+    NonExistenceProof,
+};
+use tendermint::hash::Hash;
+
+use super::node::{bit, child_hash, key_path, KeyPath, NodeRef, SmtNode};
+use super::proof::{get_leaf_op, INNER_PREFIX};
+use crate::avl::AsBytes;
+
+/// A sparse Merkle tree over the SHA-256 hashes of its keys.
+///
+/// Leaves are positioned by the bits of the hash of their key, so the shape of the tree, and
+/// therefore its root hash, only depends on the set of entries and not on the order in which they
+/// were written. Proofs verify with the standard ICS23 `SmtSpec`.
+///
+/// Nodes are reference-counted and updated with copy-on-write, so cloning a tree is O(1).
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SparseMerkleTree<K, V> {
+    pub root: NodeRef<K, V>,
+}
+
+impl<K: AsBytes + Clone, V: Borrow<[u8]> + Clone> SparseMerkleTree<K, V> {
+    /// Return an empty sparse Merkle tree.
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Return the hash of the merkle tree root, if it has at least one node. The root of an empty
+    /// tree is the placeholder of empty subtrees, i.e. 32 bytes of zeroes.
+    pub fn root_hash(&self) -> Option<&Hash> {
+        Some(self.root.as_ref()?.hash())
+    }
+
+    /// Return the value corresponding to the key, if it exists.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: AsBytes + ?Sized,
+    {
+        let path = key_path(key.as_bytes().as_ref());
+        match Self::find_leaf(&self.root, &path)? {
+            SmtNode::Leaf {
+                path: leaf_path,
+                value,
+                ..
+            } if *leaf_path == path => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Return the leaf at which the search for `path` ends.
+    fn find_leaf<'a>(node_ref: &'a NodeRef<K, V>, path: &KeyPath) -> Option<&'a SmtNode<K, V>> {
+        let mut node = node_ref.as_deref()?;
+        let mut depth = 0;
+        while let SmtNode::Inner { left, right, .. } = node {
+            node = if bit(path, depth) { right } else { left }.as_deref()?;
+            depth += 1;
+        }
+        Some(node)
+    }
+
+    /// Insert a value into the tree, this operation runs in O(log(n)) on average.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let path = key_path(key.as_bytes().as_ref());
+        Self::insert_rec(&mut self.root, 0, path, key, value)
+    }
+
+    fn insert_rec(
+        node_ref: &mut NodeRef<K, V>,
+        depth: usize,
+        path: KeyPath,
+        key: K,
+        value: V,
+    ) -> Option<V> {
+        let Some(node) = node_ref else {
+            *node_ref = Some(Arc::new(SmtNode::leaf(path, key, value)));
+            return None;
+        };
+
+        if let SmtNode::Leaf {
+            path: leaf_path, ..
+        } = node.as_ref()
+        {
+            if *leaf_path == path {
+                return Arc::make_mut(node).set_value(value);
+            }
+            // the leaf and the new one can't share this subtree anymore
+            let leaf_path = *leaf_path;
+            let sibling = node.clone();
+            let leaf = Arc::new(SmtNode::leaf(path, key, value));
+            *node_ref = Some(Arc::new(Self::split(
+                depth, leaf, &path, sibling, &leaf_path,
+            )));
+            return None;
+        }
+
+        let node = Arc::make_mut(node);
+        let SmtNode::Inner { left, right, .. } = node else {
+            unreachable!()
+        };
+        let previous = if bit(&path, depth) {
+            Self::insert_rec(right, depth + 1, path, key, value)
+        } else {
+            Self::insert_rec(left, depth + 1, path, key, value)
+        };
+        node.update();
+        previous
+    }
+
+    /// Build the subtree at `depth` holding the two leaves `a` and `b`, whose paths are distinct.
+    fn split(
+        depth: usize,
+        a: Arc<SmtNode<K, V>>,
+        a_path: &KeyPath,
+        b: Arc<SmtNode<K, V>>,
+        b_path: &KeyPath,
+    ) -> SmtNode<K, V> {
+        match (bit(a_path, depth), bit(b_path, depth)) {
+            (false, true) => SmtNode::inner(Some(a), Some(b)),
+            (true, false) => SmtNode::inner(Some(b), Some(a)),
+            (a_bit, _) => {
+                let child = Some(Arc::new(Self::split(depth + 1, a, a_path, b, b_path)));
+                if a_bit {
+                    SmtNode::inner(None, child)
+                } else {
+                    SmtNode::inner(child, None)
+                }
+            }
+        }
+    }
+
+    /// Remove the value associated with a key from the tree, if it exists.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: AsBytes + ?Sized,
+    {
+        // don't copy the nodes along the path if the key is absent
+        self.get(key)?;
+        let path = key_path(key.as_bytes().as_ref());
+        Self::remove_rec(&mut self.root, 0, &path)
+    }
+
+    /// Remove the leaf at `path` from the subtree, which must contain it.
+    fn remove_rec(node_ref: &mut NodeRef<K, V>, depth: usize, path: &KeyPath) -> Option<V> {
+        if let SmtNode::Leaf { .. } = node_ref.as_deref()? {
+            let leaf = node_ref.take()?;
+            return match Arc::try_unwrap(leaf) {
+                Ok(SmtNode::Leaf { value, .. }) => Some(value),
+                Err(leaf) => match leaf.as_ref() {
+                    SmtNode::Leaf { value, .. } => Some(value.clone()),
+                    SmtNode::Inner { .. } => None,
+                },
+                Ok(SmtNode::Inner { .. }) => None,
+            };
+        }
+
+        let node = Arc::make_mut(node_ref.as_mut()?);
+        let SmtNode::Inner { left, right, .. } = node else {
+            unreachable!()
+        };
+        let value = if bit(path, depth) {
+            Self::remove_rec(right, depth + 1, path)
+        } else {
+            Self::remove_rec(left, depth + 1, path)
+        }?;
+
+        // a subtree holding a single leaf is replaced by that leaf
+        let single_leaf = match (&*left, &*right) {
+            (Some(child), None) | (None, Some(child)) => match child.as_ref() {
+                SmtNode::Leaf { .. } => Some(child.clone()),
+                SmtNode::Inner { .. } => None,
+            },
+            _ => None,
+        };
+        match single_leaf {
+            Some(leaf) => *node_ref = Some(leaf),
+            None => node.update(),
+        }
+        Some(value)
+    }
+
+    /// Return an existence proof for the given element, if it exists.
+    /// Otherwise return a non-existence proof.
+    pub fn get_proof<Q>(&self, key: &Q) -> CommitmentProof
+    where
+        Q: AsBytes + ?Sized,
+    {
+        let proof = self.get_proof_inner(key.as_bytes().as_ref());
+        CommitmentProof { proof: Some(proof) }
+    }
+
+    /// Return a batch proof made of an existence or non-existence proof for each of the given
+    /// keys, in the order of the keys.
+    pub fn get_batch_proof<'q, Q>(&self, keys: impl IntoIterator<Item = &'q Q>) -> CommitmentProof
+    where
+        Q: AsBytes + ?Sized + 'q,
+    {
+        let entries = keys
+            .into_iter()
+            .map(|key| {
+                let proof = match self.get_proof_inner(key.as_bytes().as_ref()) {
+                    Proof::Exist(proof) => batch_entry::Proof::Exist(proof),
+                    Proof::Nonexist(proof) => batch_entry::Proof::Nonexist(proof),
+                    _ => unreachable!(),
+                };
+                BatchEntry { proof: Some(proof) }
+            })
+            .collect();
+        CommitmentProof {
+            proof: Some(Proof::Batch(BatchProof { entries })),
+        }
+    }
+
+    /// Return a compressed batch proof for the given keys, in which the inner nodes shared by
+    /// the proofs of several keys are only included once.
+    pub fn get_compressed_batch_proof<'q, Q>(
+        &self,
+        keys: impl IntoIterator<Item = &'q Q>,
+    ) -> CommitmentProof
+    where
+        Q: AsBytes + ?Sized + 'q,
+    {
+        ics23::compress(&self.get_batch_proof(keys))
+    }
+
+    fn get_proof_inner(&self, key: &[u8]) -> Proof {
+        let path = key_path(key);
+        if let Some(proof) = self.get_existence_proof(&path) {
+            return Proof::Exist(proof);
+        }
+        // the neighbours of an absent key are the leaves with the closest paths on each side
+        let left = Self::prev_leaf(&self.root, &path, 0)
+            .and_then(|leaf| self.get_existence_proof(leaf_path(leaf)));
+        let right = Self::next_leaf(&self.root, &path, 0)
+            .and_then(|leaf| self.get_existence_proof(leaf_path(leaf)));
+        Proof::Nonexist(NonExistenceProof {
+            key: key.to_vec(),
+            left,
+            right,
+        })
+    }
+
+    /// Build the existence proof of the leaf at `path`, with the inner operations ordered from
+    /// the leaf to the root.
+    fn get_existence_proof(&self, path: &KeyPath) -> Option<ExistenceProof> {
+        let mut inner_ops = vec![];
+        let mut node = self.root.as_deref()?;
+        let mut depth = 0;
+        while let SmtNode::Inner { left, right, .. } = node {
+            let (prefix, suffix, child) = if bit(path, depth) {
+                ([&INNER_PREFIX, child_hash(left)].concat(), vec![], right)
+            } else {
+                (INNER_PREFIX.to_vec(), child_hash(right).to_vec(), left)
+            };
+            inner_ops.push(InnerOp {
+                hash: HashOp::Sha256.into(),
+                prefix,
+                suffix,
+            });
+            node = child.as_deref()?;
+            depth += 1;
+        }
+        let SmtNode::Leaf {
+            path: leaf_path,
+            key,
+            value,
+            ..
+        } = node
+        else {
+            return None;
+        };
+        if leaf_path != path {
+            return None;
+        }
+        inner_ops.reverse();
+
+        Some(ExistenceProof {
+            key: key.as_bytes().as_ref().to_vec(),
+            value: value.borrow().to_vec(),
+            leaf: Some(get_leaf_op()),
+            path: inner_ops,
+        })
+    }
+
+    /// Return the leaf with the greatest path smaller than `path` in the subtree at `depth`, whose
+    /// leaves share the first `depth` bits of `path`.
+    fn prev_leaf<'a>(
+        node_ref: &'a NodeRef<K, V>,
+        path: &KeyPath,
+        depth: usize,
+    ) -> Option<&'a SmtNode<K, V>> {
+        match node_ref.as_deref()? {
+            leaf @ SmtNode::Leaf { .. } => (leaf_path(leaf) < path).then_some(leaf),
+            SmtNode::Inner { left, right, .. } => {
+                if bit(path, depth) {
+                    Self::prev_leaf(right, path, depth + 1).or_else(|| Self::last_leaf(left))
+                } else {
+                    Self::prev_leaf(left, path, depth + 1)
+                }
+            }
+        }
+    }
+
+    /// Return the leaf with the smallest path greater than `path` in the subtree at `depth`,
+    /// whose leaves share the first `depth` bits of `path`.
+    fn next_leaf<'a>(
+        node_ref: &'a NodeRef<K, V>,
+        path: &KeyPath,
+        depth: usize,
+    ) -> Option<&'a SmtNode<K, V>> {
+        match node_ref.as_deref()? {
+            leaf @ SmtNode::Leaf { .. } => (leaf_path(leaf) > path).then_some(leaf),
+            SmtNode::Inner { left, right, .. } => {
+                if bit(path, depth) {
+                    Self::next_leaf(right, path, depth + 1)
+                } else {
+                    Self::next_leaf(left, path, depth + 1).or_else(|| Self::first_leaf(right))
+                }
+            }
+        }
+    }
+
+    fn first_leaf(node_ref: &NodeRef<K, V>) -> Option<&SmtNode<K, V>> {
+        match node_ref.as_deref()? {
+            leaf @ SmtNode::Leaf { .. } => Some(leaf),
+            SmtNode::Inner { left, right, .. } => {
+                Self::first_leaf(left).or_else(|| Self::first_leaf(right))
+            }
+        }
+    }
+
+    fn last_leaf(node_ref: &NodeRef<K, V>) -> Option<&SmtNode<K, V>> {
+        match node_ref.as_deref()? {
+            leaf @ SmtNode::Leaf { .. } => Some(leaf),
+            SmtNode::Inner { left, right, .. } => {
+                Self::last_leaf(right).or_else(|| Self::last_leaf(left))
+            }
+        }
+    }
+}
+
+fn leaf_path<K, V>(leaf: &SmtNode<K, V>) -> &KeyPath {
+    match leaf {
+        SmtNode::Leaf { path, .. } => path,
+        SmtNode::Inner { .. } => unreachable!("[SMT]: Inner node instead of leaf"),
+    }
+}
+
+impl<K, V> Default for SparseMerkleTree<K, V> {
+    fn default() -> Self {
+        Self { root: None }
+    }
+}
@@ -0,0 +1,103 @@
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::types::Path;
+
+/// A change to the value at some path between two states.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Change<V = Vec<u8>> {
+    /// The path was set, while it had no value.
+    Inserted { new: V },
+    /// The value at the path was replaced.
+    Updated { old: V, new: V },
+    /// The path was deleted.
+    Deleted { old: V },
+}
+
+/// The changes between two states, in ascending path order.
+pub type ChangeSet<V = Vec<u8>> = BTreeMap<Path, Change<V>>;
+
+impl<V: PartialEq> Change<V> {
+    /// Returns the change from the `old` value to the `new` one, if they differ.
+    pub fn between(old: Option<V>, new: Option<V>) -> Option<Self> {
+        match (old, new) {
+            (None, None) => None,
+            (None, Some(new)) => Some(Self::Inserted { new }),
+            (Some(old), None) => Some(Self::Deleted { old }),
+            (Some(old), Some(new)) => (old != new).then_some(Self::Updated { old, new }),
+        }
+    }
+}
+
+impl<V> Change<V> {
+    /// Returns the value before the change, if any.
+    pub fn old_value(&self) -> Option<&V> {
+        match self {
+            Self::Inserted { .. } => None,
+            Self::Updated { old, .. } | Self::Deleted { old } => Some(old),
+        }
+    }
+
+    /// Returns the value after the change, if any.
+    pub fn new_value(&self) -> Option<&V> {
+        match self {
+            Self::Inserted { new } | Self::Updated { new, .. } => Some(new),
+            Self::Deleted { .. } => None,
+        }
+    }
+
+    /// Maps the values of the change with `f`, returning the first error `f` fails with.
+    pub fn try_map<W, E>(self, f: impl Fn(V) -> Result<W, E>) -> Result<Change<W>, E> {
+        Ok(match self {
+            Self::Inserted { new } => Change::Inserted { new: f(new)? },
+            Self::Updated { old, new } => Change::Updated {
+                old: f(old)?,
+                new: f(new)?,
+            },
+            Self::Deleted { old } => Change::Deleted { old: f(old)? },
+        })
+    }
+}
+
+/// Returns the changes that turn the `old` entries into the `new` ones, both of which must be in
+/// ascending path order. Only the values that changed are copied.
+pub fn diff_entries<P, V>(
+    old: impl IntoIterator<Item = (P, V)>,
+    new: impl IntoIterator<Item = (P, V)>,
+) -> ChangeSet
+where
+    P: Borrow<Path>,
+    V: Borrow<[u8]>,
+{
+    let (mut old, mut new) = (old.into_iter().peekable(), new.into_iter().peekable());
+    let mut changes = ChangeSet::new();
+    loop {
+        let ordering = match (old.peek(), new.peek()) {
+            (None, None) => return changes,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((old_path, _)), Some((new_path, _))) => old_path.borrow().cmp(new_path.borrow()),
+        };
+        match ordering {
+            Ordering::Less => {
+                let (path, old) = old.next().expect("peeked entry");
+                let old = old.borrow().to_vec();
+                changes.insert(path.borrow().clone(), Change::Deleted { old });
+            }
+            Ordering::Greater => {
+                let (path, new) = new.next().expect("peeked entry");
+                let new = new.borrow().to_vec();
+                changes.insert(path.borrow().clone(), Change::Inserted { new });
+            }
+            Ordering::Equal => {
+                let (path, old) = old.next().expect("peeked entry");
+                let (_, new) = new.next().expect("peeked entry");
+                if old.borrow() != new.borrow() {
+                    let (old, new) = (old.borrow().to_vec(), new.borrow().to_vec());
+                    changes.insert(path.borrow().clone(), Change::Updated { old, new });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use core::fmt::{Debug, Formatter};
+
+use crate::context::Store;
+use crate::types::map::{join_key, read_key};
+use crate::types::{Height, Map, MapKey, Path, TypedSet, TypedStoreError};
+use crate::utils::Codec;
+
+/// A secondary index of an [`IndexedMap`], deriving a single-segment index key from each value.
+#[derive(Clone)]
+pub struct Index<V> {
+    name: &'static str,
+    key: fn(&V) -> String,
+}
+
+impl<V> Debug for Index<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Index").field("name", &self.name).finish()
+    }
+}
+
+/// A [`Map`] with secondary indexes that are updated together with the values.
+///
+/// The values are stored at `<namespace>/pk/<key>` and the index entries, which hold no value, at
+/// `<namespace>/idx/<index name>/<index key>/<key>`.
+#[derive(Clone, Debug)]
+pub struct IndexedMap<S, K, C>
+where
+    C: Codec,
+{
+    map: Map<S, K, C>,
+    index_entries: TypedSet<S, Path>,
+    namespace: Path,
+    indexes: Vec<Index<C::Value>>,
+}
+
+impl<S, K, C, V> IndexedMap<S, K, C>
+where
+    S: Store,
+    K: MapKey,
+    C: Codec<Value = V>,
+{
+    pub fn new(store: S, namespace: Path) -> Self {
+        Self {
+            map: Map::new(store.clone(), namespace.clone().join("pk")),
+            index_entries: TypedSet::new(store),
+            namespace,
+            indexes: vec![],
+        }
+    }
+
+    /// Adds an index named `name` that maps every value to the index key returned by `key`.
+    /// Indexes must be added before any value is set.
+    pub fn with_index(mut self, name: &'static str, key: fn(&V) -> String) -> Self {
+        self.indexes.push(Index { name, key });
+        self
+    }
+
+    #[inline]
+    pub fn get(&self, height: Height, key: &K) -> Result<Option<V>, TypedStoreError> {
+        self.map.get(height, key)
+    }
+
+    #[inline]
+    pub fn get_existing(&self, height: Height, key: &K) -> Result<V, TypedStoreError> {
+        self.map.get_existing(height, key)
+    }
+
+    /// Sets `value` for `key` and moves its index entries from the previous value, which is
+    /// returned, to `value`.
+    pub fn set(&mut self, key: &K, value: V) -> Result<Option<V>, TypedStoreError> {
+        let old_value = self.map.get(Height::Pending, key)?;
+        let old_paths = old_value
+            .as_ref()
+            .map(|old_value| self.index_paths(key, old_value))
+            .transpose()?
+            .unwrap_or_default();
+        let new_paths = self.index_paths(key, &value)?;
+
+        self.map.set(key, value)?;
+        for path in old_paths.iter().filter(|path| !new_paths.contains(path)) {
+            self.index_entries.delete_at_path(path)?;
+        }
+        for path in new_paths {
+            self.index_entries.set_at_path(path, ())?;
+        }
+        Ok(old_value)
+    }
+
+    /// Deletes the value for `key` together with its index entries.
+    pub fn delete(&mut self, key: &K) -> Result<(), TypedStoreError> {
+        if let Some(old_value) = self.map.get(Height::Pending, key)? {
+            for path in self.index_paths(key, &old_value)? {
+                self.index_entries.delete_at_path(&path)?;
+            }
+        }
+        self.map.delete(key)
+    }
+
+    /// Returns a lazy iterator over all the entries at specified `height`, in ascending path
+    /// order.
+    #[inline]
+    pub fn iter_at(&self, height: Height) -> impl Iterator<Item = Result<(K, V), TypedStoreError>> {
+        self.map.iter_at(height)
+    }
+
+    /// Returns a lazy iterator over the keys of the values that the index `name` maps to
+    /// `index_key` at specified `height`. An unknown index yields no keys.
+    pub fn keys_by_index_at(
+        &self,
+        height: Height,
+        name: &str,
+        index_key: &str,
+    ) -> Result<impl Iterator<Item = Result<K, TypedStoreError>>, TypedStoreError> {
+        let prefix = self.index_prefix(name, index_key)?;
+        let keys_prefix = prefix.clone();
+        Ok(self
+            .index_entries
+            .scan_at(height, &prefix, None, None, move |path| {
+                read_key(&keys_prefix, path)
+            })
+            .map(|entry| entry.map(|(key, ())| key)))
+    }
+
+    /// Returns a lazy iterator over the entries whose values the index `name` maps to
+    /// `index_key` at specified `height`.
+    pub fn values_by_index_at<'a>(
+        &'a self,
+        height: Height,
+        name: &str,
+        index_key: &str,
+    ) -> Result<impl Iterator<Item = Result<(K, V), TypedStoreError>> + 'a, TypedStoreError>
+    where
+        K: 'a,
+        C: 'a,
+        V: 'a,
+    {
+        Ok(self
+            .keys_by_index_at(height, name, index_key)?
+            .map(move |key| {
+                let key = key?;
+                let value = self.map.get_existing(height, &key)?;
+                Ok((key, value))
+            }))
+    }
+
+    fn index_prefix(&self, name: &str, index_key: &str) -> Result<Path, TypedStoreError> {
+        let index_key = (name.to_owned(), index_key.to_owned());
+        join_key(&self.namespace.clone().join("idx"), &index_key)
+    }
+
+    fn index_paths(&self, key: &K, value: &V) -> Result<Vec<Path>, TypedStoreError> {
+        self.indexes
+            .iter()
+            .map(|index| {
+                let prefix = self.index_prefix(index.name, &(index.key)(value))?;
+                join_key(&prefix, key)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{InMemoryStore, SharedStore};
+    use crate::utils::codec::JsonCodec;
+
+    /// A proposal as `(proposer, status)`.
+    type Proposal = (String, String);
+
+    fn proposal(proposer: &str, status: &str) -> Proposal {
+        (proposer.to_owned(), status.to_owned())
+    }
+
+    fn ids_by_status(
+        proposals: &IndexedMap<SharedStore<InMemoryStore>, u64, JsonCodec<Proposal>>,
+        status: &str,
+    ) -> Vec<u64> {
+        proposals
+            .keys_by_index_at(Height::Pending, "status", status)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_indexed_map() {
+        let store = SharedStore::new(InMemoryStore::default());
+        let mut proposals =
+            IndexedMap::<_, u64, JsonCodec<Proposal>>::new(store, "proposals".to_owned().into())
+                .with_index("status", |p| p.1.clone())
+                .with_index("proposer", |p| p.0.clone());
+
+        proposals.set(&1, proposal("alice", "voting")).unwrap();
+        proposals.set(&2, proposal("bob", "voting")).unwrap();
+        proposals.set(&10, proposal("alice", "passed")).unwrap();
+        assert_eq!(ids_by_status(&proposals, "voting"), [1, 2]);
+
+        // updating a value moves its index entries
+        let old = proposals.set(&1, proposal("alice", "passed")).unwrap();
+        assert_eq!(old, Some(proposal("alice", "voting")));
+        assert_eq!(ids_by_status(&proposals, "voting"), [2]);
+        assert_eq!(ids_by_status(&proposals, "passed"), [1, 10]);
+
+        let by_alice: Vec<_> = proposals
+            .values_by_index_at(Height::Pending, "proposer", "alice")
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(by_alice, [1, 10]);
+
+        proposals.delete(&10).unwrap();
+        assert_eq!(ids_by_status(&proposals, "passed"), [1]);
+        assert_eq!(proposals.get(Height::Pending, &10).unwrap(), None);
+        assert_eq!(ids_by_status(&proposals, "unknown"), Vec::<u64>::new());
+        assert_eq!(proposals.iter_at(Height::Pending).count(), 2);
+    }
+}
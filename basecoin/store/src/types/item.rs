@@ -0,0 +1,132 @@
+use crate::context::Store;
+use crate::types::{Height, Path, TypedStore, TypedStoreError};
+use crate::utils::codec::JsonCodec;
+use crate::utils::Codec;
+
+/// A single typed value stored at a fixed path.
+#[derive(Clone, Debug)]
+pub struct Item<S, C> {
+    store: TypedStore<S, Path, C>,
+    path: Path,
+}
+
+impl<S, C, V> Item<S, C>
+where
+    S: Store,
+    C: Codec<Value = V>,
+{
+    pub fn new(store: S, path: Path) -> Self {
+        Self {
+            store: TypedStore::new(store),
+            path,
+        }
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the value at specified `height`, or `None` if it was never set.
+    #[inline]
+    pub fn get(&self, height: Height) -> Result<Option<V>, TypedStoreError> {
+        self.store.get_at_path(height, &self.path)
+    }
+
+    /// Returns the value at specified `height`, failing with [`TypedStoreError::NotFound`] if it
+    /// was never set.
+    #[inline]
+    pub fn get_existing(&self, height: Height) -> Result<V, TypedStoreError> {
+        self.store.get_existing_at_path(height, &self.path)
+    }
+
+    /// Sets the value, returning the previous one.
+    #[inline]
+    pub fn set(&mut self, value: V) -> Result<Option<V>, TypedStoreError> {
+        self.store.set_at_path(self.path.clone(), value)
+    }
+
+    #[inline]
+    pub fn delete(&mut self) -> Result<(), TypedStoreError> {
+        self.store.delete_at_path(&self.path)
+    }
+}
+
+/// A monotonic counter stored at a fixed path, starting at zero.
+///
+/// The counter is stored as a JSON number, so it can take over existing `JsonStore<_, _, u64>`
+/// counters without migrating their state.
+#[derive(Clone, Debug)]
+pub struct Sequence<S> {
+    item: Item<S, JsonCodec<u64>>,
+}
+
+impl<S> Sequence<S>
+where
+    S: Store,
+{
+    pub fn new(store: S, path: Path) -> Self {
+        Self {
+            item: Item::new(store, path),
+        }
+    }
+
+    /// Returns the value that the next call to [`Self::next_value`] would return at specified
+    /// `height`.
+    #[inline]
+    pub fn current(&self, height: Height) -> Result<u64, TypedStoreError> {
+        Ok(self.item.get(height)?.unwrap_or_default())
+    }
+
+    /// Returns the current value and advances the counter.
+    pub fn next_value(&mut self) -> Result<u64, TypedStoreError> {
+        let current = self.current(Height::Pending)?;
+        let next = current
+            .checked_add(1)
+            .ok_or_else(|| TypedStoreError::EncodeFailed {
+                path: self.item.path().clone(),
+                reason: "sequence overflow".to_owned(),
+            })?;
+        self.item.set(next)?;
+        Ok(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{InMemoryStore, SharedStore};
+    use crate::types::JsonStore;
+
+    #[test]
+    fn test_item_and_sequence() {
+        let store = SharedStore::new(InMemoryStore::default());
+        let mut item = Item::<_, JsonCodec<String>>::new(store.clone(), "config".to_owned().into());
+        let mut sequence = Sequence::new(store.clone(), "counter".to_owned().into());
+
+        assert_eq!(item.get(Height::Pending).unwrap(), None);
+        assert!(matches!(
+            item.get_existing(Height::Pending),
+            Err(TypedStoreError::NotFound { .. })
+        ));
+        assert_eq!(item.set("a".to_owned()).unwrap(), None);
+        assert_eq!(item.set("b".to_owned()).unwrap(), Some("a".to_owned()));
+        assert_eq!(item.get_existing(Height::Pending).unwrap(), "b");
+
+        assert_eq!(sequence.current(Height::Pending).unwrap(), 0);
+        assert_eq!(sequence.next_value().unwrap(), 0);
+        assert_eq!(sequence.next_value().unwrap(), 1);
+        assert_eq!(sequence.current(Height::Pending).unwrap(), 2);
+
+        // the counter is a plain JSON number, as the hand-rolled counters stored it
+        let raw = JsonStore::<_, Path, u64>::new(store);
+        assert_eq!(
+            raw.get(Height::Pending, &"counter".to_owned().into())
+                .unwrap(),
+            Some(2)
+        );
+
+        item.delete().unwrap();
+        assert_eq!(item.get(Height::Pending).unwrap(), None);
+    }
+}
@@ -0,0 +1,236 @@
+use core::marker::PhantomData;
+
+use crate::context::Store;
+use crate::types::{Height, Path, Segments, TypedStore, TypedStoreError};
+use crate::utils::Codec;
+
+/// A key of a [`Map`], stored as one or more segments of a [`Path`].
+pub trait MapKey: Sized {
+    /// Appends the segments of the key to `path`, or returns `None` if they couldn't be read
+    /// back, e.g. for strings that contain `/`.
+    fn join_to(&self, path: Path) -> Option<Path>;
+
+    /// Reads the key from the leading segments.
+    fn read(segments: &mut Segments<'_>) -> Option<Self>;
+}
+
+impl MapKey for String {
+    fn join_to(&self, path: Path) -> Option<Path> {
+        (!self.contains('/')).then(|| path.join(self))
+    }
+
+    fn read(segments: &mut Segments<'_>) -> Option<Self> {
+        segments.next_str().map(ToOwned::to_owned)
+    }
+}
+
+/// Stored length-prefixed, e.g. for addresses.
+impl MapKey for Vec<u8> {
+    fn join_to(&self, path: Path) -> Option<Path> {
+        Some(path.join_bytes(self))
+    }
+
+    fn read(segments: &mut Segments<'_>) -> Option<Self> {
+        segments.next_bytes().map(ToOwned::to_owned)
+    }
+}
+
+macro_rules! impl_map_key_for_uint {
+    ($($ty:ty),*) => {
+        $(
+            /// Stored big-endian, so that keys are iterated in numeric order.
+            impl MapKey for $ty {
+                fn join_to(&self, path: Path) -> Option<Path> {
+                    Some(path.join_u64(u64::from(*self)))
+                }
+
+                fn read(segments: &mut Segments<'_>) -> Option<Self> {
+                    segments.next_u64()?.try_into().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_map_key_for_uint!(u8, u16, u32, u64);
+
+impl<A: MapKey, B: MapKey> MapKey for (A, B) {
+    fn join_to(&self, path: Path) -> Option<Path> {
+        self.1.join_to(self.0.join_to(path)?)
+    }
+
+    fn read(segments: &mut Segments<'_>) -> Option<Self> {
+        Some((A::read(segments)?, B::read(segments)?))
+    }
+}
+
+impl<A: MapKey, B: MapKey, C: MapKey> MapKey for (A, B, C) {
+    fn join_to(&self, path: Path) -> Option<Path> {
+        self.2.join_to(self.1.join_to(self.0.join_to(path)?)?)
+    }
+
+    fn read(segments: &mut Segments<'_>) -> Option<Self> {
+        Some((A::read(segments)?, B::read(segments)?, C::read(segments)?))
+    }
+}
+
+/// Appends the segments of `key` to `prefix`.
+pub(crate) fn join_key<K: MapKey>(prefix: &Path, key: &K) -> Result<Path, TypedStoreError> {
+    key.join_to(prefix.clone())
+        .ok_or_else(|| TypedStoreError::EncodeFailed {
+            path: prefix.clone(),
+            reason: "key can't be stored as path segments".to_owned(),
+        })
+}
+
+/// Reads a key from the segments of `path` that follow `prefix`, if there are no other segments.
+pub(crate) fn read_key<K: MapKey>(prefix: &Path, path: &Path) -> Option<K> {
+    let mut segments = path.strip_prefix(prefix)?;
+    let key = K::read(&mut segments)?;
+    segments.is_empty().then_some(key)
+}
+
+/// A typed map from keys of type `K` to values encoded with `C`, stored under a namespace path.
+#[derive(Clone, Debug)]
+pub struct Map<S, K, C> {
+    store: TypedStore<S, Path, C>,
+    namespace: Path,
+    _key: PhantomData<K>,
+}
+
+impl<S, K, C, V> Map<S, K, C>
+where
+    S: Store,
+    K: MapKey,
+    C: Codec<Value = V>,
+{
+    pub fn new(store: S, namespace: Path) -> Self {
+        Self {
+            store: TypedStore::new(store),
+            namespace,
+            _key: PhantomData,
+        }
+    }
+
+    /// Returns the path at which the value for `key` is stored.
+    pub fn key_path(&self, key: &K) -> Result<Path, TypedStoreError> {
+        join_key(&self.namespace, key)
+    }
+
+    #[inline]
+    pub fn get(&self, height: Height, key: &K) -> Result<Option<V>, TypedStoreError> {
+        self.store.get_at_path(height, &self.key_path(key)?)
+    }
+
+    #[inline]
+    pub fn get_existing(&self, height: Height, key: &K) -> Result<V, TypedStoreError> {
+        self.store
+            .get_existing_at_path(height, &self.key_path(key)?)
+    }
+
+    /// Sets `value` for `key`, returning the previous value.
+    #[inline]
+    pub fn set(&mut self, key: &K, value: V) -> Result<Option<V>, TypedStoreError> {
+        let path = self.key_path(key)?;
+        self.store.set_at_path(path, value)
+    }
+
+    #[inline]
+    pub fn delete(&mut self, key: &K) -> Result<(), TypedStoreError> {
+        let path = self.key_path(key)?;
+        self.store.delete_at_path(&path)
+    }
+
+    /// Returns a lazy iterator over all the entries of the map at specified `height`, in
+    /// ascending path order.
+    pub fn iter_at(&self, height: Height) -> impl Iterator<Item = Result<(K, V), TypedStoreError>> {
+        let namespace = self.namespace.clone();
+        self.store
+            .scan_at(height, &self.namespace, None, None, move |path| {
+                read_key(&namespace, path)
+            })
+    }
+
+    /// Returns a lazy iterator over the entries of the map at specified `height` whose keys start
+    /// with the segments of `prefix`, e.g. the first element of a tuple key, in ascending path
+    /// order.
+    pub fn prefix_at<P: MapKey>(
+        &self,
+        height: Height,
+        prefix: &P,
+    ) -> Result<impl Iterator<Item = Result<(K, V), TypedStoreError>>, TypedStoreError> {
+        let key_prefix = join_key(&self.namespace, prefix)?;
+        let namespace = self.namespace.clone();
+        let prefix = key_prefix.clone();
+        Ok(self
+            .store
+            .scan_at(height, &key_prefix, None, None, move |path| {
+                path.strip_prefix(&prefix)?;
+                read_key(&namespace, path)
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{InMemoryStore, SharedStore};
+    use crate::utils::codec::JsonCodec;
+
+    #[test]
+    fn test_map_keys() {
+        let key = ("alice".to_owned(), 42u64, b"a/b".to_vec());
+        let namespace = Path::from("ns".to_owned());
+        let path = join_key(&namespace, &key).unwrap();
+        assert_eq!(read_key(&namespace, &path), Some(key));
+        assert_eq!(read_key::<(String, u64)>(&namespace, &path), None);
+
+        assert!(join_key(&namespace, &"a/b".to_owned()).is_err());
+        let path = join_key(&namespace, &300u64).unwrap();
+        assert_eq!(read_key::<u16>(&namespace, &path), Some(300));
+        assert_eq!(read_key::<u8>(&namespace, &path), None);
+    }
+
+    #[test]
+    fn test_map() {
+        let store = SharedStore::new(InMemoryStore::default());
+        let mut map =
+            Map::<_, (String, u64), JsonCodec<String>>::new(store.clone(), "map".to_owned().into());
+        // a store whose namespace is a byte prefix of the map's namespace
+        let mut other = Map::<_, String, JsonCodec<String>>::new(store, "mapper".to_owned().into());
+
+        for (owner, id) in [("bob", 1), ("alice", 10), ("alice", 9), ("bo", 3)] {
+            map.set(&(owner.to_owned(), id), format!("{owner}-{id}"))
+                .unwrap();
+        }
+        other.set(&"x".to_owned(), "x".to_owned()).unwrap();
+
+        assert_eq!(
+            map.get_existing(Height::Pending, &("bob".to_owned(), 1))
+                .unwrap(),
+            "bob-1"
+        );
+        assert!(map.set(&("a/b".to_owned(), 1), String::new()).is_err());
+
+        // integer segments are iterated in numeric order
+        let all: Vec<_> = map
+            .iter_at(Height::Pending)
+            .map(|entry| entry.unwrap().1)
+            .collect();
+        assert_eq!(all, ["alice-9", "alice-10", "bo-3", "bob-1"]);
+
+        let bo: Vec<_> = map
+            .prefix_at(Height::Pending, &"bo".to_owned())
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(bo, [("bo".to_owned(), 3)]);
+
+        map.delete(&("alice".to_owned(), 9)).unwrap();
+        assert_eq!(
+            map.get(Height::Pending, &("alice".to_owned(), 9)).unwrap(),
+            None
+        );
+        assert_eq!(map.iter_at(Height::Pending).count(), 3);
+    }
+}
@@ -1,9 +1,21 @@
+pub mod change;
 pub mod height;
 pub mod identifier;
+pub mod indexed_map;
+pub mod item;
+pub mod map;
 pub mod path;
+pub mod pruning;
 pub mod store;
 
+pub use change::{Change, ChangeSet};
 pub use height::{Height, RawHeight};
 pub use identifier::Identifier;
+pub use indexed_map::{Index, IndexedMap};
+pub use item::{Item, Sequence};
+pub use map::{Map, MapKey};
 pub use path::*;
-pub use store::{BinStore, JsonStore, MainStore, ProtobufStore, State, TypedSet, TypedStore};
+pub use pruning::PruningStrategy;
+pub use store::{
+    BinStore, JsonStore, MainStore, ProtobufStore, State, TypedSet, TypedStore, TypedStoreError,
+};
@@ -1,4 +1,4 @@
-use core::fmt::{Display, Formatter};
+use core::fmt::{Debug, Display, Formatter};
 use core::str::{from_utf8, FromStr, Utf8Error};
 
 use displaydoc::Display as DisplayDoc;
@@ -14,13 +14,78 @@ pub enum Error {
     ParseError(String),
 }
 
-/// A new type representing a valid ICS024 `Path`.
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
-pub struct Path(Vec<Identifier>);
+/// Tags the integer segments of a [`Path`]. Neither tag is a valid UTF-8 byte, so binary
+/// segments can't be mistaken for string segments.
+const U64_TAG: u8 = 0xfe;
+/// Tags the byte string segments of a [`Path`].
+const BYTES_TAG: u8 = 0xff;
+
+/// A store key made of segments separated by `/`.
+///
+/// String segments, such as the ICS024 identifiers that make up IBC paths, are stored verbatim,
+/// so a path made only of string segments is encoded as the path string itself. Hence, a string
+/// containing `/` is the same as the string segments it separates, e.g. `join("a/b")` and
+/// `join("a").join("b")` make the same path. Binary segments are self-delimiting and start with
+/// a tag byte that never appears in strings: integers are stored big-endian and byte strings are
+/// prefixed with their length. Hence, binary segments are never encoded as string segments or
+/// as other binary segments. Paths are ordered by their encoding, so that stores iterate over
+/// their entries in the same order as the keys of their commitment proofs.
+#[derive(Ord, PartialOrd, Eq, PartialEq, Clone)]
+pub struct Path(Vec<u8>);
 
 impl Path {
-    pub fn get(&self, index: usize) -> Option<&Identifier> {
-        self.0.get(index)
+    /// Appends the string `segment`, which may itself contain several `/`-separated segments.
+    pub fn join(self, segment: impl AsRef<str>) -> Self {
+        self.join_raw(segment.as_ref().as_bytes())
+    }
+
+    /// Appends a big-endian integer segment, so that integers are ordered numerically.
+    pub fn join_u64(self, value: u64) -> Self {
+        let mut segment = vec![U64_TAG];
+        segment.extend_from_slice(&value.to_be_bytes());
+        self.join_raw(&segment)
+    }
+
+    /// Appends a byte string segment prefixed with its length, so that byte strings are ordered
+    /// by length first.
+    pub fn join_bytes(self, bytes: &[u8]) -> Self {
+        let len = u32::try_from(bytes.len()).expect("segment longer than u32::MAX bytes");
+        let mut segment = vec![BYTES_TAG];
+        segment.extend_from_slice(&len.to_be_bytes());
+        segment.extend_from_slice(bytes);
+        self.join_raw(&segment)
+    }
+
+    /// Appends the segments of `path`.
+    pub fn join_path(self, path: &Path) -> Self {
+        self.join_raw(&path.0)
+    }
+
+    fn join_raw(mut self, segment: &[u8]) -> Self {
+        if !self.0.is_empty() {
+            self.0.push(b'/');
+        }
+        self.0.extend_from_slice(segment);
+        self
+    }
+
+    /// Returns the segments that follow `prefix`, or `None` if `prefix` isn't made of leading
+    /// segments of this path.
+    pub fn strip_prefix(&self, prefix: &Path) -> Option<Segments<'_>> {
+        let rest = self.0.strip_prefix(prefix.0.as_slice())?;
+        let rest = match rest {
+            [] => rest,
+            [b'/', rest @ ..] if !prefix.0.is_empty() => rest,
+            _ if prefix.0.is_empty() => rest,
+            _ => return None,
+        };
+        Some(Segments(rest))
+    }
+
+    /// Returns the string view of this path, which fails for paths with binary segments that
+    /// aren't valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, Error> {
+        from_utf8(&self.0).map_err(|e| Error::MalformedPathString { error: e })
     }
 
     pub fn try_into<K, E>(self) -> Result<K, E>
@@ -31,49 +96,99 @@ impl Path {
     }
 }
 
+/// A reader over the segments of a [`Path`], which has to know the kind of each segment.
+#[derive(Clone, Debug)]
+pub struct Segments<'a>(&'a [u8]);
+
+impl<'a> Segments<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reads a string segment, up to the next `/`.
+    pub fn next_str(&mut self) -> Option<&'a str> {
+        let len = self
+            .0
+            .iter()
+            .position(|b| *b == b'/')
+            .unwrap_or(self.0.len());
+        from_utf8(self.next_raw(len)?).ok()
+    }
+
+    /// Reads a segment appended with [`Path::join_u64`].
+    pub fn next_u64(&mut self) -> Option<u64> {
+        if self.0.first() != Some(&U64_TAG) {
+            return None;
+        }
+        Some(u64::from_be_bytes(self.next_raw(9)?[1..].try_into().ok()?))
+    }
+
+    /// Reads a segment appended with [`Path::join_bytes`].
+    pub fn next_bytes(&mut self) -> Option<&'a [u8]> {
+        let [BYTES_TAG, len @ ..] = self.0.get(..5)? else {
+            return None;
+        };
+        let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+        let segment = self.next_raw(len.checked_add(5)?)?;
+        Some(&segment[5..])
+    }
+
+    /// Reads `len` bytes and the separator that follows them, if any.
+    fn next_raw(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (segment, rest) = (self.0.get(..len)?, &self.0[len..]);
+        self.0 = match rest {
+            [] => rest,
+            [b'/', rest @ ..] => rest,
+            _ => return None,
+        };
+        Some(segment)
+    }
+}
+
 impl From<String> for Path {
     fn from(s: String) -> Self {
-        let mut identifiers = vec![];
-        let parts = s.split('/'); // split will never return an empty iterator
-        for part in parts {
-            identifiers.push(Identifier::from(part.to_owned()));
-        }
-        Self(identifiers)
+        Self(s.into_bytes())
     }
 }
 
-impl TryFrom<&[u8]> for Path {
-    type Error = Error;
+impl From<Vec<u8>> for Path {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let s = from_utf8(value).map_err(|e| Error::MalformedPathString { error: e })?;
-        Ok(s.to_owned().into())
+impl From<&[u8]> for Path {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
     }
 }
 
 impl From<Identifier> for Path {
     fn from(id: Identifier) -> Self {
-        Self(vec![id])
+        Self(id.to_string().into_bytes())
     }
 }
 
+/// Displays the string view of the path, with the bytes of binary segments escaped if they aren't
+/// valid UTF-8.
 impl Display for Path {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(
-            f,
-            "{}",
-            self.0
-                .iter()
-                .map(|iden| iden.as_str().to_owned())
-                .collect::<Vec<String>>()
-                .join("/")
-        )
+        match from_utf8(&self.0) {
+            Ok(s) => write!(f, "{s}"),
+            Err(_) => write!(f, "{}", self.0.escape_ascii()),
+        }
+    }
+}
+
+impl Debug for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Path({:?})", self.to_string())
     }
 }
 
 impl AsBytes for Path {
     fn as_bytes(&self) -> ByteSlice<'_> {
-        ByteSlice::Vector(self.to_string().into_bytes())
+        ByteSlice::Slice(&self.0)
     }
 }
 
@@ -84,12 +199,74 @@ mod tests {
     #[test]
     fn happy_test() {
         let bytes: &[u8] = b"hello/world";
-        assert!(Path::try_from(bytes).is_ok());
+        assert_eq!(Path::from(bytes).as_str().unwrap(), "hello/world");
     }
 
     #[test]
-    fn sad_test() {
+    fn binary_path_test() {
         let bytes: &[u8] = b"hello/\xf0\x28\x8c\xbc";
-        assert!(Path::try_from(bytes).is_err());
+        let path = Path::from(bytes);
+        assert!(path.as_str().is_err());
+        assert_eq!(path.as_bytes().as_ref(), bytes);
+    }
+
+    #[test]
+    fn segments_test() {
+        let prefix = Path::from("balances".to_owned());
+        let path = prefix
+            .clone()
+            .join("alice")
+            .join_u64(u64::from(b'/'))
+            .join_bytes(b"a/b");
+        assert!(path.as_str().is_err());
+
+        let mut segments = path.strip_prefix(&prefix).unwrap();
+        assert_eq!(segments.next_str(), Some("alice"));
+        assert_eq!(segments.next_u64(), Some(u64::from(b'/')));
+        assert_eq!(segments.next_bytes(), Some(&b"a/b"[..]));
+        assert!(segments.is_empty());
+
+        // segments are read with the kind they were written with
+        let mut segments = path.strip_prefix(&prefix).unwrap();
+        assert_eq!(segments.next_u64(), None);
+        assert_eq!(segments.next_bytes(), None);
+
+        assert!(path.strip_prefix(&"bal".to_owned().into()).is_none());
+        assert!(path
+            .strip_prefix(&"balances/alice".to_owned().into())
+            .is_some());
+    }
+
+    #[test]
+    fn collision_test() {
+        // an integer segment whose bytes start with `x/` doesn't collide with a string segment
+        let path = Path::from("bal".to_owned()).join_u64(0x782f_0000_0000_0000);
+        assert!(path.strip_prefix(&"bal/x".to_owned().into()).is_none());
+        assert_ne!(path, Path::from("bal/x".to_owned()).join_u64(0));
+        let mut segments = path.strip_prefix(&"bal".to_owned().into()).unwrap();
+        assert_eq!(segments.clone().next_str(), None);
+        assert_eq!(segments.next_u64(), Some(0x782f_0000_0000_0000));
+
+        // nor do integer and byte string segments of the same length
+        assert_ne!(
+            Path::from("bal".to_owned()).join_u64(0x0000_0004_782f_7878),
+            Path::from("bal".to_owned()).join_bytes(b"x/xx")
+        );
+    }
+
+    #[test]
+    fn order_test() {
+        // paths are ordered by their encoding, and integers numerically
+        let base = Path::from("a".to_owned());
+        assert!(base.clone().join_u64(9) < base.clone().join_u64(10));
+        assert!(base.clone().join_u64(255) < base.clone().join_u64(256));
+        assert!(Path::from("a!".to_owned()) < Path::from("a/b".to_owned()));
+
+        // string paths are encoded as is, as IBC paths must be
+        let ibc_path = "clients/07-tendermint-0/clientState";
+        assert_eq!(
+            Path::from(ibc_path.to_owned()).as_bytes().as_ref(),
+            ibc_path.as_bytes()
+        );
     }
 }
@@ -0,0 +1,97 @@
+use crate::context::Store;
+use crate::types::RawHeight;
+
+/// Which committed heights a store keeps, applied after every commit with
+/// [`PruningStrategy::prune`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum PruningStrategy {
+    /// Keep every committed height.
+    #[default]
+    Nothing,
+    /// Only keep the latest committed height.
+    Everything,
+    /// Keep the `n` latest committed heights (at least one).
+    KeepRecent(RawHeight),
+    /// Keep the latest committed height and every height that is a multiple of `k`.
+    KeepEvery(RawHeight),
+}
+
+impl PruningStrategy {
+    /// Prunes the heights of `store` that are no longer kept since its latest commit.
+    ///
+    /// Returns the retain height to report to CometBFT, i.e. the lowest height from which all
+    /// heights are kept, or `0` to keep all blocks when no height was pruned or when some heights
+    /// are kept since genesis.
+    pub fn prune<S: Store>(&self, store: &mut S) -> Result<RawHeight, S::Error> {
+        let latest = store.current_height();
+        match *self {
+            Self::Nothing => Ok(0),
+            Self::Everything => Self::KeepRecent(1).prune(store),
+            Self::KeepRecent(n) => match latest.saturating_sub(n.max(1)) {
+                0 => Ok(0),
+                pruned => {
+                    store.prune(pruned)?;
+                    Ok(pruned + 1)
+                }
+            },
+            Self::KeepEvery(k) => {
+                // heights are kept since genesis, so blocks can't be pruned
+                let previous = latest.saturating_sub(1);
+                if previous != 0 && previous % k.max(1) != 0 {
+                    store.prune_at(previous)?;
+                }
+                Ok(0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::InMemoryStore;
+    use crate::types::{Height, Path};
+
+    fn committed_heights(strategy: PruningStrategy, blocks: RawHeight) -> Vec<RawHeight> {
+        let mut store = InMemoryStore::default();
+        let path = Path::from("a".to_owned());
+        let mut retain_height = 0;
+        for block in 1..=blocks {
+            store
+                .set(path.clone(), block.to_be_bytes().to_vec())
+                .unwrap();
+            store.commit().unwrap();
+            retain_height = strategy.prune(&mut store).unwrap();
+        }
+
+        let kept: Vec<_> = (1..=blocks)
+            .filter(|&h| store.get(Height::Stable(h), &path).is_some())
+            .collect();
+        // every height from the retain height on is kept
+        if retain_height != 0 {
+            assert!((retain_height..=blocks).all(|h| kept.contains(&h)));
+        }
+        kept
+    }
+
+    #[test]
+    fn pruning_strategies() {
+        assert_eq!(
+            committed_heights(PruningStrategy::Nothing, 5),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(committed_heights(PruningStrategy::Everything, 5), vec![5]);
+        assert_eq!(
+            committed_heights(PruningStrategy::KeepRecent(2), 5),
+            vec![4, 5]
+        );
+        assert_eq!(
+            committed_heights(PruningStrategy::KeepRecent(8), 5),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            committed_heights(PruningStrategy::KeepEvery(3), 10),
+            vec![3, 6, 9, 10]
+        );
+    }
+}
@@ -1,12 +1,16 @@
 use core::fmt::Debug;
 use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+use core::str::FromStr;
 
-use crate::avl::AvlTree;
+use displaydoc::Display as DisplayDoc;
+
+use crate::avl::{AsBytes, AvlTree};
 use crate::context::Store;
 use crate::impls::SharedStore;
-use crate::types::{Height, Path, RawHeight};
+use crate::types::{ChangeSet, Height, Path, RawHeight};
 use crate::utils::codec::{BinCodec, JsonCodec, NullCodec, ProtobufCodec};
-use crate::utils::Codec;
+use crate::utils::{Codec, CodecError};
 
 // A state type that represents a snapshot of the store at every block.
 // The value is a `Vec<u8>` to allow stored types to choose their own serde.
@@ -26,6 +30,36 @@ pub type TypedSet<S, K> = TypedStore<S, K, NullCodec>;
 /// A `TypedStore` that uses the `BinCodec`
 pub type BinStore<S, K, V> = TypedStore<S, K, BinCodec<V>>;
 
+#[derive(Debug, DisplayDoc)]
+pub enum TypedStoreError {
+    /// no value found at path `{path}`
+    NotFound { path: Path },
+    /// failed to encode value for path `{path}`: `{reason}`
+    EncodeFailed { path: Path, reason: String },
+    /// failed to decode value at path `{path}`: `{reason}`
+    DecodeFailed { path: Path, reason: String },
+    /// store backend error: `{reason}`
+    Backend { reason: String },
+}
+
+impl TypedStoreError {
+    fn codec(path: Path, error: CodecError) -> Self {
+        match error {
+            CodecError::Encode { reason } => Self::EncodeFailed { path, reason },
+            CodecError::Decode { reason } => Self::DecodeFailed { path, reason },
+        }
+    }
+
+    fn backend(error: impl Debug) -> Self {
+        Self::Backend {
+            reason: format!("{error:?}"),
+        }
+    }
+}
+
+/// A store of values encoded with `C` at the paths of keys of type `K`, which are converted to
+/// paths through their string representation. Use a [`Map`](crate::types::Map) for keys with
+/// binary segments.
 #[derive(Clone, Debug)]
 pub struct TypedStore<S, K, C> {
     store: S,
@@ -48,26 +82,73 @@ where
         }
     }
 
+    /// Sets `value` for `path`, returning the previous value. Fails without writing `value` if the
+    /// previous value fails to decode.
     #[inline]
-    pub fn set(&mut self, path: K, value: V) -> Result<Option<V>, S::Error> {
-        self.store
-            .set(
-                path.to_string().into(),
-                C::encode(&value).unwrap().as_ref().to_vec(),
-            )
-            .map(|prev_val| prev_val.and_then(|v| C::decode(&v)))
+    pub fn set(&mut self, path: K, value: V) -> Result<Option<V>, TypedStoreError> {
+        self.set_at_path(path.to_string().into(), value)
     }
 
     #[inline]
-    pub fn delete(&mut self, path: K) {
-        self.store.delete(&path.to_string().into())
+    pub fn delete(&mut self, path: K) -> Result<(), TypedStoreError> {
+        self.delete_at_path(&path.to_string().into())
     }
 
+    /// Returns the value for `path` at specified `height`, or `None` if there is none.
     #[inline]
-    pub fn get(&self, height: Height, path: &K) -> Option<V> {
+    pub fn get(&self, height: Height, path: &K) -> Result<Option<V>, TypedStoreError> {
+        self.get_at_path(height, &path.to_string().into())
+    }
+
+    /// Returns the value for `path` at specified `height`, failing with
+    /// [`TypedStoreError::NotFound`] if there is none.
+    #[inline]
+    pub fn get_existing(&self, height: Height, path: &K) -> Result<V, TypedStoreError> {
+        self.get_existing_at_path(height, &path.to_string().into())
+    }
+
+    // The `*_at_path` methods take the path itself rather than a key converted through its string
+    // representation, which would mangle the binary segments of the path.
+
+    pub(crate) fn set_at_path(
+        &mut self,
+        path: Path,
+        value: V,
+    ) -> Result<Option<V>, TypedStoreError> {
+        // the previous value is decoded first, so that a failure leaves the store untouched
+        let previous = self.get_at_path(Height::Pending, &path)?;
+        let value = C::encode(&value).map_err(|e| TypedStoreError::codec(path.clone(), e))?;
         self.store
-            .get(height, &path.to_string().into())
-            .and_then(|v| C::decode(&v))
+            .set(path, value.as_ref().to_vec())
+            .map_err(TypedStoreError::backend)?;
+        Ok(previous)
+    }
+
+    pub(crate) fn delete_at_path(&mut self, path: &Path) -> Result<(), TypedStoreError> {
+        self.store.delete(path).map_err(TypedStoreError::backend)
+    }
+
+    pub(crate) fn get_at_path(
+        &self,
+        height: Height,
+        path: &Path,
+    ) -> Result<Option<V>, TypedStoreError> {
+        self.store
+            .get(height, path)
+            .map(|v| Self::decode(path, &v))
+            .transpose()
+    }
+
+    pub(crate) fn get_existing_at_path(
+        &self,
+        height: Height,
+        path: &Path,
+    ) -> Result<V, TypedStoreError> {
+        let value = self
+            .store
+            .get(height, path)
+            .ok_or_else(|| TypedStoreError::NotFound { path: path.clone() })?;
+        Self::decode(path, &value)
     }
 
     #[inline]
@@ -75,10 +156,112 @@ where
         self.store.get_keys(key_prefix)
     }
 
+    #[inline]
+    pub fn get_keys_at(&self, height: Height, key_prefix: &Path) -> Vec<Path> {
+        self.store.get_keys_at(height, key_prefix)
+    }
+
+    /// Returns a lazy iterator over the entries whose paths are within `range` at specified
+    /// `height`, in ascending path order.
+    #[inline]
+    pub fn range_at(
+        &self,
+        height: Height,
+        range: impl RangeBounds<Path>,
+    ) -> impl DoubleEndedIterator<Item = Result<(Path, V), TypedStoreError>> {
+        self.store
+            .range_at(height, range)
+            .map(|(path, value)| Ok((path.clone(), Self::decode(&path, &value)?)))
+    }
+
+    /// Returns a lazy iterator over the entries whose paths start with specified prefix at
+    /// specified `height`, in ascending path order.
+    #[inline]
+    pub fn iter_prefix_at(
+        &self,
+        height: Height,
+        key_prefix: &Path,
+    ) -> impl DoubleEndedIterator<Item = Result<(Path, V), TypedStoreError>> {
+        self.store
+            .iter_prefix_at(height, key_prefix)
+            .map(|(path, value)| Ok((path.clone(), Self::decode(&path, &value)?)))
+    }
+
+    /// Returns a lazy iterator over the entries whose paths start with specified prefix at
+    /// specified `height`, with their paths parsed as keys of type `Q` (usually `K` itself), in
+    /// ascending path order. The iteration starts at `start` if given, and yields at most `limit`
+    /// entries.
+    ///
+    /// Paths that don't parse as `Q` are skipped without decoding their values, so that the
+    /// entries of a store can be listed even if it shares its prefix with other stores.
+    pub fn entries_at<Q: FromStr>(
+        &self,
+        height: Height,
+        key_prefix: &Path,
+        start: Option<&K>,
+        limit: Option<usize>,
+    ) -> impl Iterator<Item = Result<(Q, V), TypedStoreError>> {
+        let start = start.map(|start| Path::from(start.to_string()));
+        self.scan_at(height, key_prefix, start, limit, |path| {
+            Q::from_str(&path.to_string()).ok()
+        })
+    }
+
+    /// Same as [`Self::entries_at`], but parses the paths with `parse` and starts at a raw path.
+    pub(crate) fn scan_at<Q>(
+        &self,
+        height: Height,
+        key_prefix: &Path,
+        start: Option<Path>,
+        limit: Option<usize>,
+        parse: impl Fn(&Path) -> Option<Q>,
+    ) -> impl Iterator<Item = Result<(Q, V), TypedStoreError>> {
+        let start = match start {
+            Some(start) => start.max(key_prefix.clone()),
+            None => key_prefix.clone(),
+        };
+        let prefix = key_prefix.as_bytes().as_ref().to_vec();
+        self.store
+            .range_at(height, (Bound::Included(start), Bound::Unbounded))
+            .take_while(move |(path, _)| path.as_bytes().as_ref().starts_with(&prefix))
+            .filter_map(move |(path, value)| {
+                let key = parse(&path)?;
+                Some(Self::decode(&path, &value).map(|value| (key, value)))
+            })
+            .take(limit.unwrap_or(usize::MAX))
+    }
+
+    /// Returns the changes to the entries whose paths start with specified prefix, between the
+    /// states at heights `from` and `to`, or `None` if the store doesn't hold either state.
+    pub fn diff(
+        &self,
+        from: Height,
+        to: Height,
+        key_prefix: &Path,
+    ) -> Result<Option<ChangeSet<V>>, TypedStoreError> {
+        let Some(changes) = self.store.diff(from, to) else {
+            return Ok(None);
+        };
+        let prefix = key_prefix.as_bytes();
+        changes
+            .into_iter()
+            .filter(|(path, _)| path.as_bytes().as_ref().starts_with(prefix.as_ref()))
+            .map(|(path, change)| {
+                let change = change.try_map(|v| Self::decode(&path, &v))?;
+                Ok((path, change))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
     #[inline]
     pub fn current_height(&self) -> RawHeight {
         self.store.current_height()
     }
+
+    fn decode(path: &Path, value: &[u8]) -> Result<V, TypedStoreError> {
+        C::decode(value).map_err(|e| TypedStoreError::codec(path.clone(), e))
+    }
 }
 
 impl<S, K> TypedStore<S, K, NullCodec>
@@ -87,10 +270,8 @@ where
     K: ToString,
 {
     #[inline]
-    pub fn set_path(&mut self, path: K) -> Result<(), S::Error> {
-        self.store
-            .set(path.to_string().into(), NullCodec::encode(&()).unwrap())
-            .map(|_| ())
+    pub fn set_path(&mut self, path: K) -> Result<(), TypedStoreError> {
+        self.set(path, ()).map(|_| ())
     }
 
     #[inline]
@@ -1,17 +1,40 @@
 use core::marker::PhantomData;
 
+use displaydoc::Display;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+#[derive(Debug, Display)]
+pub enum CodecError {
+    /// failed to encode value: `{reason}`
+    Encode { reason: String },
+    /// failed to decode value: `{reason}`
+    Decode { reason: String },
+}
+
+impl CodecError {
+    fn encode(reason: impl ToString) -> Self {
+        Self::Encode {
+            reason: reason.to_string(),
+        }
+    }
+
+    fn decode(reason: impl ToString) -> Self {
+        Self::Decode {
+            reason: reason.to_string(),
+        }
+    }
+}
+
 /// A trait that defines how types are decoded/encoded.
 pub trait Codec {
     type Value;
 
     type Encoded: AsRef<[u8]>;
 
-    fn encode(d: &Self::Value) -> Option<Self::Encoded>;
+    fn encode(d: &Self::Value) -> Result<Self::Encoded, CodecError>;
 
-    fn decode(bytes: &[u8]) -> Option<Self::Value>;
+    fn decode(bytes: &[u8]) -> Result<Self::Value, CodecError>;
 }
 
 /// A JSON codec that uses `serde_json` to encode/decode as a JSON string
@@ -25,13 +48,12 @@ where
     type Value = T;
     type Encoded = String;
 
-    fn encode(d: &Self::Value) -> Option<Self::Encoded> {
-        serde_json::to_string(d).ok()
+    fn encode(d: &Self::Value) -> Result<Self::Encoded, CodecError> {
+        serde_json::to_string(d).map_err(CodecError::encode)
     }
 
-    fn decode(bytes: &[u8]) -> Option<Self::Value> {
-        let json_string = String::from_utf8(bytes.to_vec()).ok()?;
-        serde_json::from_str(&json_string).ok()
+    fn decode(bytes: &[u8]) -> Result<Self::Value, CodecError> {
+        serde_json::from_slice(bytes).map_err(CodecError::decode)
     }
 }
 
@@ -44,16 +66,18 @@ impl Codec for NullCodec {
     type Value = ();
     type Encoded = Vec<u8>;
 
-    fn encode(_d: &Self::Value) -> Option<Self::Encoded> {
+    fn encode(_d: &Self::Value) -> Result<Self::Encoded, CodecError> {
         // using [0x00] to represent null
-        Some(vec![0x00])
+        Ok(vec![0x00])
     }
 
-    fn decode(bytes: &[u8]) -> Option<Self::Value> {
+    fn decode(bytes: &[u8]) -> Result<Self::Value, CodecError> {
         match bytes {
             // the encoded bytes must be [0x00]
-            [0x00] => Some(()),
-            _ => None,
+            [0x00] => Ok(()),
+            _ => Err(CodecError::decode(format!(
+                "expected null, got {bytes:02X?}"
+            ))),
         }
     }
 }
@@ -73,14 +97,15 @@ where
     type Value = T;
     type Encoded = Vec<u8>;
 
-    fn encode(d: &Self::Value) -> Option<Self::Encoded> {
+    fn encode(d: &Self::Value) -> Result<Self::Encoded, CodecError> {
         let r = d.clone().into();
-        Some(r.encode_to_vec())
+        Ok(r.encode_to_vec())
     }
 
-    fn decode(bytes: &[u8]) -> Option<Self::Value> {
-        let r = R::decode(bytes).ok()?;
-        r.try_into().ok()
+    fn decode(bytes: &[u8]) -> Result<Self::Value, CodecError> {
+        let r = R::decode(bytes).map_err(CodecError::decode)?;
+        r.try_into()
+            .map_err(|_| CodecError::decode("invalid domain type"))
     }
 }
 
@@ -95,11 +120,11 @@ where
     type Value = T;
     type Encoded = Vec<u8>;
 
-    fn encode(d: &Self::Value) -> Option<Self::Encoded> {
-        Some(d.as_ref().to_vec())
+    fn encode(d: &Self::Value) -> Result<Self::Encoded, CodecError> {
+        Ok(d.as_ref().to_vec())
     }
 
-    fn decode(bytes: &[u8]) -> Option<Self::Value> {
-        Some(bytes.to_vec().into())
+    fn decode(bytes: &[u8]) -> Result<Self::Value, CodecError> {
+        Ok(bytes.to_vec().into())
     }
 }